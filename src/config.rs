@@ -0,0 +1,61 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// Persistent defaults from `config.toml`. Every field is optional; a value
+/// here only applies when neither the flag nor its `VIZIER_*` variable is set.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub format: Option<String>,
+    pub pretty: Option<bool>,
+    pub verbose: Option<bool>,
+    pub no_public_ip: Option<bool>,
+    pub no_network: Option<bool>,
+    pub public_ip_provider: Option<String>,
+    pub public_ip_timeout_ms: Option<u64>,
+    pub public_ip_retries: Option<u32>,
+    pub proxy: Option<String>,
+    pub command_timeout_ms: Option<u64>,
+    pub max_runtime: Option<String>,
+    pub watch_path: Option<PathBuf>,
+    pub wake: WakeDefaults,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WakeDefaults {
+    pub exclude_sections: Vec<String>,
+    pub exclude_paths: Vec<String>,
+}
+
+impl Config {
+    /// Reads `explicit` if given (an error if it is missing), otherwise the
+    /// default location when it exists.
+    pub fn load(explicit: Option<&Path>) -> Result<Self> {
+        let path = match explicit {
+            Some(path) => path.to_path_buf(),
+            None => match default_path().filter(|path| path.is_file()) {
+                Some(path) => path,
+                None => return Ok(Self::default()),
+            },
+        };
+
+        let contents =
+            fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("parsing {}", path.display()))
+    }
+}
+
+/// `$XDG_CONFIG_HOME/vizier/config.toml`, falling back to
+/// `~/.config/vizier/config.toml` on every platform.
+pub fn default_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+    Some(base.join("vizier").join("config.toml"))
+}
@@ -1,24 +1,359 @@
-use anyhow::Result;
+use std::collections::HashSet;
+
+use anyhow::{Result, ensure};
+use json_patch::jsonptr::PointerBuf;
+use json_patch::{AddOperation, MoveOperation, PatchOperation, RemoveOperation};
 use serde::Serialize;
+use serde_json::Value;
+
+use crate::observation::{Observation, WakeObservation};
+
+const CLOCK_PATHS: [&str; 4] = [
+    "/ts",
+    "/monotonic_ms",
+    "/idle_ms",
+    "/focus/focus_duration_ms",
+];
+
+/// Fields `wake --baseline` skips unless told otherwise: clocks, usage
+/// counters, and activity that change between any two runs. `*` matches every
+/// array index or object key at that level.
+pub const DEFAULT_BASELINE_IGNORE: [&str; 12] = [
+    "/ts",
+    "/datetime",
+    "/filesystem/recent_files",
+    "/filesystem/mounts/*/free_gb",
+    "/resources/ram_free_gb",
+    "/resources/sensors",
+    "/listening_ports/*/pid",
+    "/audio/mic_in_use",
+    "/recent_activity",
+    "/other_sessions",
+    "/diagnostics",
+    "/truncated",
+];
 
-use crate::observation::Observation;
+/// Clocks `wake --delta-only` leaves out of its patch; the record's `ts` and
+/// `base_ts` already say when both runs happened, and `login_ts` is derived
+/// from whole uptime seconds, so its fraction moves on every run.
+const WAKE_CLOCK_PATHS: [&str; 5] = [
+    "/ts",
+    "/datetime/ts",
+    "/datetime/iso",
+    "/datetime/uptime_seconds",
+    "/datetime/login_ts",
+];
+
+/// Top-level arrays diffed by element identity under `--keyed-arrays`, with
+/// the fields that identify an element across snapshots.
+const KEYED_ARRAYS: [(&str, &[&str]); 2] = [
+    ("windows", &["id"]),
+    (
+        "net_connections",
+        &["proto", "local_port", "remote_addr", "remote_port", "pid"],
+    ),
+];
 
 #[derive(Debug, Clone, Serialize)]
 pub struct DiffEnvelope {
     pub ts: f64,
     pub monotonic_ms: u64,
+    pub base_monotonic_ms: u64,
+    pub patch: json_patch::Patch,
+}
+
+/// Result of comparing a fresh wake against a stored baseline.
+#[derive(Debug, Clone, Serialize)]
+pub struct Drift {
+    pub drifted: bool,
+    pub patch: json_patch::Patch,
+}
+
+/// Result of comparing a wake against the previous one stored for the same
+/// machine; `base_ts` is absent on the first run, whose patch builds the whole
+/// wake from `null`.
+#[derive(Debug, Clone, Serialize)]
+pub struct WakeDelta {
+    pub machine_id: String,
+    pub ts: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_ts: Option<f64>,
     pub patch: json_patch::Patch,
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DiffRecord<'a> {
+    Snapshot {
+        ts: f64,
+        monotonic_ms: u64,
+        observation: &'a Observation,
+    },
+    Patch(&'a DiffEnvelope),
+}
+
+impl<'a> DiffRecord<'a> {
+    pub fn snapshot(observation: &'a Observation) -> Self {
+        Self::Snapshot {
+            ts: observation.ts,
+            monotonic_ms: observation.monotonic_ms,
+            observation,
+        }
+    }
+}
+
+impl DiffEnvelope {
+    pub fn has_changes(&self) -> bool {
+        self.patch
+            .0
+            .iter()
+            .any(|op| !CLOCK_PATHS.contains(&op.path().as_str()))
+    }
+}
+
 pub fn create_diff_envelope(previous: &Observation, current: &Observation) -> Result<DiffEnvelope> {
-    let previous_value = serde_json::to_value(previous)?;
+    envelope(previous, current, false)
+}
+
+pub fn create_keyed_diff_envelope(
+    previous: &Observation,
+    current: &Observation,
+) -> Result<DiffEnvelope> {
+    envelope(previous, current, true)
+}
+
+fn envelope(previous: &Observation, current: &Observation, keyed: bool) -> Result<DiffEnvelope> {
+    ensure!(
+        current.monotonic_ms > previous.monotonic_ms,
+        "diff requires increasing monotonic_ms (previous {}, current {})",
+        previous.monotonic_ms,
+        current.monotonic_ms
+    );
+
+    let mut previous_value = serde_json::to_value(previous)?;
     let current_value = serde_json::to_value(current)?;
 
-    let patch = json_patch::diff(&previous_value, &current_value);
+    let mut keyed_ops = Vec::new();
+    if keyed {
+        for (field, key) in KEYED_ARRAYS {
+            let (Some(Value::Array(before)), Some(Value::Array(after))) =
+                (previous_value.get_mut(field), current_value.get(field))
+            else {
+                continue;
+            };
+            if let Some(ops) = diff_keyed_array(field, before, after, key) {
+                keyed_ops.extend(ops);
+                *before = after.clone();
+            }
+        }
+    }
+
+    let mut patch = json_patch::diff(&previous_value, &current_value);
+    patch.0.extend(keyed_ops);
 
     Ok(DiffEnvelope {
         ts: current.ts,
         monotonic_ms: current.monotonic_ms,
+        base_monotonic_ms: previous.monotonic_ms,
         patch,
     })
 }
+
+/// Diffs `current` against `baseline` after dropping every field matched by
+/// `ignore` from both sides, so the patch rewrites the baseline into the
+/// current state of the monitored fields only.
+pub fn baseline_drift(baseline: &Value, current: &Value, ignore: &[String]) -> Drift {
+    let mut baseline = baseline.clone();
+    let mut current = current.clone();
+    for pointer in ignore {
+        remove_pointer(&mut baseline, pointer);
+        remove_pointer(&mut current, pointer);
+    }
+
+    let patch = json_patch::diff(&baseline, &current);
+    Drift {
+        drifted: !patch.0.is_empty(),
+        patch,
+    }
+}
+
+/// Diffs `current` against the previous stored wake (the serialized JSON it
+/// was written as), minus `WAKE_CLOCK_PATHS` on both sides, so an unchanged
+/// machine yields an empty patch.
+pub fn create_wake_delta(previous: Option<&Value>, current: &WakeObservation) -> Result<WakeDelta> {
+    let mut base = previous.cloned().unwrap_or(Value::Null);
+    let mut current_value = serde_json::to_value(current)?;
+    for pointer in WAKE_CLOCK_PATHS {
+        remove_pointer(&mut base, pointer);
+        remove_pointer(&mut current_value, pointer);
+    }
+
+    Ok(WakeDelta {
+        machine_id: current.machine.machine_id.clone(),
+        ts: current.ts,
+        base_ts: previous.and_then(|previous| previous.get("ts")?.as_f64()),
+        patch: json_patch::diff(&base, &current_value),
+    })
+}
+
+fn remove_pointer(value: &mut Value, pointer: &str) {
+    let tokens: Vec<String> = pointer
+        .split('/')
+        .skip(1)
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect();
+    remove_matching(value, &tokens);
+}
+
+/// Validates a `--baseline-ignore` JSON Pointer.
+pub fn parse_pointer(value: &str) -> Result<String, String> {
+    if value.starts_with('/') {
+        Ok(value.to_string())
+    } else {
+        Err("expected a JSON Pointer such as /machine/kernel".to_string())
+    }
+}
+
+fn remove_matching(value: &mut Value, tokens: &[String]) {
+    let Some((token, rest)) = tokens.split_first() else {
+        return;
+    };
+    match value {
+        Value::Object(map) if rest.is_empty() => {
+            if token == "*" {
+                map.clear();
+            } else {
+                map.remove(token);
+            }
+        }
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                if token == "*" || key == token {
+                    remove_matching(child, rest);
+                }
+            }
+        }
+        Value::Array(items) if rest.is_empty() => {
+            if token == "*" {
+                items.clear();
+            } else if let Ok(index) = token.parse::<usize>()
+                && index < items.len()
+            {
+                items.remove(index);
+            }
+        }
+        Value::Array(items) => {
+            for (index, child) in items.iter_mut().enumerate() {
+                if token == "*" || token.parse() == Ok(index) {
+                    remove_matching(child, rest);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Rewrites `before` into `after` with remove/move/add operations addressed by
+/// element identity, then patches changed fields in place. Returns `None` when
+/// either side has duplicate keys, leaving the array to the positional differ.
+fn diff_keyed_array(
+    field: &str,
+    before: &[Value],
+    after: &[Value],
+    key: &[&str],
+) -> Option<Vec<PatchOperation>> {
+    let identity = |value: &Value| -> Vec<Value> {
+        key.iter()
+            .map(|name| value.get(name).cloned().unwrap_or(Value::Null))
+            .collect()
+    };
+    let before_keys: Vec<Vec<Value>> = before.iter().map(identity).collect();
+    let after_keys: Vec<Vec<Value>> = after.iter().map(identity).collect();
+    if !all_unique(&before_keys) || !all_unique(&after_keys) {
+        return None;
+    }
+
+    let base = PointerBuf::from_tokens([field]);
+    let at = |index: usize| base.concat(&PointerBuf::from(index));
+    let mut ops = Vec::new();
+
+    let mut working: Vec<(Vec<Value>, &Value)> = Vec::new();
+    for (index, (item_key, item)) in before_keys.into_iter().zip(before).enumerate().rev() {
+        if after_keys.contains(&item_key) {
+            working.insert(0, (item_key, item));
+        } else {
+            ops.push(PatchOperation::Remove(RemoveOperation { path: at(index) }));
+        }
+    }
+
+    for (index, (item_key, item)) in after_keys.iter().zip(after).enumerate() {
+        match working
+            .iter()
+            .position(|(existing, _)| existing == item_key)
+        {
+            Some(from) => {
+                if from != index {
+                    let moved = working.remove(from);
+                    working.insert(index, moved);
+                    ops.push(PatchOperation::Move(MoveOperation {
+                        from: at(from),
+                        path: at(index),
+                    }));
+                }
+                let prefix = at(index);
+                ops.extend(
+                    json_patch::diff(working[index].1, item)
+                        .0
+                        .into_iter()
+                        .map(|op| rebase(op, &prefix)),
+                );
+            }
+            None => {
+                working.insert(index, (item_key.clone(), item));
+                ops.push(PatchOperation::Add(AddOperation {
+                    path: at(index),
+                    value: item.clone(),
+                }));
+            }
+        }
+    }
+
+    Some(ops)
+}
+
+fn all_unique(keys: &[Vec<Value>]) -> bool {
+    let mut seen = HashSet::new();
+    keys.iter()
+        .all(|key| seen.insert(serde_json::to_string(key).unwrap_or_default()))
+}
+
+fn rebase(op: PatchOperation, prefix: &PointerBuf) -> PatchOperation {
+    match op {
+        PatchOperation::Add(mut op) => {
+            op.path = prefix.concat(&op.path);
+            PatchOperation::Add(op)
+        }
+        PatchOperation::Remove(mut op) => {
+            op.path = prefix.concat(&op.path);
+            PatchOperation::Remove(op)
+        }
+        PatchOperation::Replace(mut op) => {
+            op.path = prefix.concat(&op.path);
+            PatchOperation::Replace(op)
+        }
+        PatchOperation::Move(mut op) => {
+            op.from = prefix.concat(&op.from);
+            op.path = prefix.concat(&op.path);
+            PatchOperation::Move(op)
+        }
+        PatchOperation::Copy(mut op) => {
+            op.from = prefix.concat(&op.from);
+            op.path = prefix.concat(&op.path);
+            PatchOperation::Copy(op)
+        }
+        PatchOperation::Test(mut op) => {
+            op.path = prefix.concat(&op.path);
+            PatchOperation::Test(op)
+        }
+    }
+}
@@ -1,4 +1,10 @@
+pub mod config;
 pub mod diff;
+pub mod merge;
 pub mod observation;
 pub mod observer;
+pub mod output;
+pub mod render;
+pub mod replay;
+pub mod serve;
 pub mod util;
@@ -1,13 +1,56 @@
-use std::io::Write;
-use std::path::PathBuf;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{Context, Result, anyhow, bail};
+use clap::builder::BoolishValueParser;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use globset::Glob;
+use is_terminal::IsTerminal;
 use serde::Serialize;
-use vizier::diff::create_diff_envelope;
-use vizier::observer::{ObserverConfig, WakeConfig, create_observer, create_waker};
+use vizier::config::Config;
+use vizier::diff::{
+    DEFAULT_BASELINE_IGNORE, DiffRecord, baseline_drift, create_diff_envelope,
+    create_keyed_diff_envelope, create_wake_delta, parse_pointer,
+};
+use vizier::merge::merge_previous;
+use vizier::observation::{
+    DEFAULT_COMPACT_LEVEL, Diagnostic, FocusRecord, MAX_COMPACT_LEVEL, Observation, WakeObservation,
+};
+use vizier::observer::builder::WakeConfigBuilder;
+use vizier::observer::catalog::{AppCatalog, builtin_catalog};
+use vizier::observer::mock::{MockObserver, MockWaker};
+use vizier::observer::plan::Plan;
+use vizier::observer::{
+    DEFAULT_MAX_FS_EVENTS, DEFAULT_PUBLIC_IP_RETRIES, DEFAULT_PUBLIC_IP_TIMEOUT, ExternalCollector,
+    IpFilter, Observer, ObserverConfig, ProcessSort, TitleRedaction, WakeConfig, WakeSection,
+    Waker, capabilities, create_observer, create_waker, explain_snapshot, explain_wake,
+    parse_exclude_path,
+};
+use vizier::output::{
+    CompressingSink, Compression, JsonArraySink, RollingFileSink, Sink, WebhookSink, create_sink,
+    write_private,
+};
+use vizier::render::{Format, Section, Text, to_csv, to_vec_pretty_compact};
+use vizier::replay::{self, Replay};
+use vizier::serve::Server;
+#[cfg(unix)]
+use vizier::serve::Socket;
+use vizier::util::deadline::{Deadline, Jitter, parse_duration};
+use vizier::util::interrupt;
+use vizier::util::net::parse_proxy;
+use vizier::util::process::DEFAULT_COMMAND_TIMEOUT;
+use vizier::util::status::{self, Outcome};
+
+const WATCHDOG_GRACE: Duration = Duration::from_millis(250);
+const WATCHDOG_EXIT_CODE: i32 = 124;
+/// How often `watch --on-event` wakes to check `--max-runtime`.
+const EVENT_POLL: Duration = Duration::from_secs(1);
+/// Bursts longer than this many debounce windows still produce a record.
+const MAX_DEBOUNCE_WINDOWS: u32 = 10;
 
 #[derive(Debug, Parser)]
 #[command(
@@ -17,28 +60,342 @@ use vizier::observer::{ObserverConfig, WakeConfig, create_observer, create_waker
     about = "System perception utility"
 )]
 struct Cli {
-    #[arg(long, global = true)]
+    #[arg(long, global = true, env = "VIZIER_PRETTY", value_parser = BoolishValueParser::new())]
     pretty: bool,
 
-    #[arg(long, global = true)]
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "pretty",
+        env = "VIZIER_PRETTY_COMPACT",
+        value_parser = BoolishValueParser::new()
+    )]
+    pretty_compact: bool,
+
+    #[arg(long, global = true, env = "VIZIER_VERBOSE", value_parser = BoolishValueParser::new())]
     verbose: bool,
 
-    #[arg(long, global = true)]
+    #[arg(
+        long,
+        global = true,
+        env = "VIZIER_INCLUDE_LOOPBACK",
+        value_parser = BoolishValueParser::new()
+    )]
+    include_loopback: bool,
+
+    #[arg(
+        long,
+        global = true,
+        hide = true,
+        env = "VIZIER_ALL_CONNECTIONS",
+        value_parser = BoolishValueParser::new()
+    )]
     all_connections: bool,
 
-    #[arg(long, global = true)]
+    #[arg(
+        long,
+        global = true,
+        value_delimiter = ',',
+        value_name = "STATES",
+        env = "VIZIER_CONN_STATES"
+    )]
+    conn_states: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "VIZIER_NO_PUBLIC_IP",
+        value_parser = BoolishValueParser::new()
+    )]
     no_public_ip: bool,
 
-    #[arg(long, global = true)]
+    #[arg(
+        long,
+        global = true,
+        visible_alias = "offline",
+        env = "VIZIER_NO_NETWORK",
+        value_parser = BoolishValueParser::new()
+    )]
+    no_network: bool,
+
+    #[arg(
+        long = "public-ip-timeout-ms",
+        global = true,
+        value_name = "MS",
+        default_value_t = DEFAULT_PUBLIC_IP_TIMEOUT.as_millis() as u64,
+        env = "VIZIER_PUBLIC_IP_TIMEOUT_MS"
+    )]
+    public_ip_timeout: u64,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        default_value_t = DEFAULT_PUBLIC_IP_RETRIES,
+        env = "VIZIER_PUBLIC_IP_RETRIES"
+    )]
+    public_ip_retries: u32,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "URL",
+        value_parser = parse_proxy,
+        env = "VIZIER_PROXY"
+    )]
+    proxy: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "URL",
+        env = "VIZIER_PUBLIC_IP_PROVIDER"
+    )]
+    public_ip_provider: Option<String>,
+
+    #[arg(long, global = true, value_name = "FILE", env = "VIZIER_CONFIG")]
+    config: Option<PathBuf>,
+
+    #[arg(long, global = true, env = "VIZIER_WATCH_PATH")]
     watch_path: Option<PathBuf>,
 
+    #[arg(long, global = true, env = "VIZIER_CLIPBOARD", value_parser = BoolishValueParser::new())]
+    clipboard: bool,
+
+    #[arg(
+        long,
+        global = true,
+        env = "VIZIER_NO_WINDOWS",
+        value_parser = BoolishValueParser::new()
+    )]
+    no_windows: bool,
+
+    #[arg(
+        long,
+        global = true,
+        env = "VIZIER_NO_CONNECTIONS",
+        value_parser = BoolishValueParser::new()
+    )]
+    no_connections: bool,
+
+    #[arg(
+        long,
+        global = true,
+        env = "VIZIER_WINDOW_RESOURCES",
+        value_parser = BoolishValueParser::new()
+    )]
+    window_resources: bool,
+
+    #[arg(
+        long,
+        global = true,
+        env = "VIZIER_INCLUDE_DESKTOP",
+        value_parser = BoolishValueParser::new()
+    )]
+    include_desktop: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "MODE",
+        env = "VIZIER_REDACT_TITLES"
+    )]
+    redact_titles: Option<TitleRedaction>,
+
+    #[arg(long, global = true, env = "VIZIER_OUTPUT")]
+    output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = Compression::None,
+        env = "VIZIER_COMPRESS"
+    )]
+    compress: Compression,
+
+    #[arg(long, global = true, value_enum, default_value_t = Format::Json, env = "VIZIER_FORMAT")]
+    format: Format,
+
+    #[arg(long, global = true, value_enum, env = "VIZIER_SECTION")]
+    section: Option<Section>,
+
+    #[arg(
+        long = "filter-app",
+        global = true,
+        value_delimiter = ',',
+        value_name = "APP",
+        env = "VIZIER_FILTER_APP"
+    )]
+    filter_apps: Vec<String>,
+
+    #[arg(long, global = true, value_name = "N", env = "VIZIER_MAX_CONNECTIONS")]
+    max_connections: Option<usize>,
+
+    #[arg(long, global = true, value_name = "N", env = "VIZIER_MAX_WINDOWS")]
+    max_windows: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        default_value_t = DEFAULT_MAX_FS_EVENTS,
+        env = "VIZIER_MAX_FS_EVENTS"
+    )]
+    max_fs_events: usize,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        env = "VIZIER_MAX_LISTENING_PORTS"
+    )]
+    max_listening_ports: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "MS",
+        default_value_t = DEFAULT_COMMAND_TIMEOUT.as_millis() as u64,
+        env = "VIZIER_COMMAND_TIMEOUT"
+    )]
+    command_timeout: u64,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        env = "VIZIER_MAX_RUNTIME"
+    )]
+    max_runtime: Option<Duration>,
+
+    #[arg(long, global = true)]
+    explain: bool,
+
+    #[arg(long, global = true, env = "VIZIER_COUNT_ONLY", value_parser = BoolishValueParser::new())]
+    count_only: bool,
+
+    #[arg(long, global = true, env = "VIZIER_HASH", value_parser = BoolishValueParser::new())]
+    hash: bool,
+
+    #[arg(long, global = true, env = "VIZIER_STRICT", value_parser = BoolishValueParser::new())]
+    strict: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        env = "VIZIER_MERGE_PREVIOUS"
+    )]
+    merge_previous: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "VIZIER_RELATIVE_PATHS",
+        value_parser = BoolishValueParser::new()
+    )]
+    relative_paths: bool,
+
+    #[arg(
+        long,
+        global = true,
+        env = "VIZIER_GROUP_BY_APP",
+        value_parser = BoolishValueParser::new()
+    )]
+    group_by_app: bool,
+
+    #[arg(
+        long,
+        global = true,
+        env = "VIZIER_ANONYMIZE_IPS",
+        value_parser = BoolishValueParser::new()
+    )]
+    anonymize_ips: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "FILTER",
+        env = "VIZIER_IP_FILTER"
+    )]
+    ip_filter: Option<IpFilter>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "LEVEL",
+        value_parser = clap::value_parser!(u8).range(0..=MAX_COMPACT_LEVEL as i64),
+        env = "VIZIER_COMPACT_LEVEL"
+    )]
+    compact_level: Option<u8>,
+
+    #[arg(long, global = true, value_name = "FILE", hide = true)]
+    mock_fixture: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Option<Command>,
 }
 
 #[derive(Debug, Subcommand)]
 enum Command {
-    Wake,
+    Wake {
+        #[arg(long, value_name = "N")]
+        top_processes: Option<usize>,
+
+        #[arg(long, value_enum, default_value_t = ProcessSort::Cpu, requires = "top_processes")]
+        sort_by: ProcessSort,
+
+        #[arg(long = "collector", value_name = "NAME=COMMAND")]
+        collectors: Vec<ExternalCollector>,
+
+        #[arg(long = "exclude-path", value_name = "GLOB", value_parser = parse_exclude_path)]
+        exclude_paths: Vec<Glob>,
+
+        #[arg(long)]
+        disk_health: bool,
+
+        #[arg(long)]
+        all_mounts: bool,
+
+        #[arg(long, value_enum, value_delimiter = ',', value_name = "SECTIONS")]
+        only: Vec<WakeSection>,
+
+        #[arg(long)]
+        env: bool,
+
+        #[arg(long = "env-var", value_name = "NAME", requires = "env")]
+        env_vars: Vec<String>,
+
+        #[arg(long)]
+        sensors: bool,
+
+        #[arg(long)]
+        include_hidden: bool,
+
+        #[arg(long)]
+        git: bool,
+
+        #[arg(long, value_name = "FILE")]
+        app_catalog: Option<PathBuf>,
+
+        #[arg(long, value_name = "FILE")]
+        baseline: Option<PathBuf>,
+
+        #[arg(
+            long = "baseline-ignore",
+            value_name = "POINTER",
+            requires = "baseline",
+            value_parser = parse_pointer
+        )]
+        baseline_ignore: Vec<String>,
+
+        #[arg(long, conflicts_with = "baseline")]
+        delta_only: bool,
+    },
     Snapshot,
     Watch {
         #[arg(long, default_value_t = 1000)]
@@ -46,7 +403,92 @@ enum Command {
 
         #[arg(long)]
         diff: bool,
+
+        #[arg(long, requires = "diff")]
+        poll_adaptive: bool,
+
+        #[arg(long, requires = "diff")]
+        skip_empty: bool,
+
+        #[arg(long, requires = "diff")]
+        keyed_arrays: bool,
+
+        #[arg(long, conflicts_with = "diff")]
+        dedupe: bool,
+
+        #[arg(
+            long,
+            value_name = "DURATION",
+            value_parser = parse_duration,
+            requires = "dedupe"
+        )]
+        heartbeat: Option<Duration>,
+
+        #[arg(long, default_value_t = 30_000, requires = "poll_adaptive")]
+        max_interval: u64,
+
+        #[arg(long, conflicts_with = "poll_adaptive")]
+        on_event: bool,
+
+        #[arg(long, value_name = "MS", default_value_t = 200, requires = "on_event")]
+        debounce: u64,
+
+        #[arg(
+            long,
+            value_name = "MS",
+            default_value_t = 0,
+            conflicts_with = "on_event"
+        )]
+        jitter_ms: u64,
+
+        #[arg(
+            long,
+            value_name = "N",
+            requires = "diff",
+            value_parser = clap::value_parser!(u64).range(1..)
+        )]
+        full_every: Option<u64>,
+
+        #[arg(long)]
+        log_dir: Option<PathBuf>,
+
+        #[arg(long, default_value_t = 10 * 1024 * 1024, requires = "log_dir")]
+        max_file_bytes: u64,
+
+        #[arg(long, default_value_t = 5, requires = "log_dir")]
+        max_files: usize,
+
+        #[arg(long, value_name = "URL")]
+        webhook: Option<String>,
+
+        #[arg(long, value_name = "N", default_value_t = 100, requires = "webhook")]
+        webhook_queue: usize,
+
+        #[arg(long, requires = "webhook")]
+        webhook_only: bool,
+
+        #[arg(long, conflicts_with_all = ["log_dir", "webhook"])]
+        json_array: bool,
+    },
+    Focus {
+        #[arg(long, default_value_t = 500)]
+        interval: u64,
     },
+    Replay {
+        file: PathBuf,
+
+        #[arg(long, value_name = "INDEX")]
+        at: Option<usize>,
+    },
+    #[command(group(clap::ArgGroup::new("listen").required(true)))]
+    Serve {
+        #[arg(long, value_name = "PATH", group = "listen")]
+        socket: Option<PathBuf>,
+
+        #[arg(long, value_name = "ADDR", group = "listen")]
+        http: Option<SocketAddr>,
+    },
+    Capabilities,
 }
 
 fn main() {
@@ -54,74 +496,855 @@ fn main() {
         eprintln!("{err:#}");
         std::process::exit(1);
     }
+    std::process::exit(status::exit_code());
 }
 
 fn run() -> Result<()> {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches)?;
+    let config = Config::load(cli.config.as_deref())?;
+    apply_config(&mut cli, &matches, config)?;
+    if cli.all_connections {
+        eprintln!("vz: warning: --all-connections is deprecated; use --include-loopback");
+        cli.include_loopback = true;
+    }
+    let deadline = Deadline::after(cli.max_runtime);
+    if let Some(max_runtime) = cli.max_runtime {
+        spawn_watchdog(max_runtime);
+    }
     let default_command = cli.command.is_none();
-    let command = cli.command.unwrap_or(Command::Snapshot);
+    let command = cli.command.take().unwrap_or(Command::Snapshot);
+    if cli.explain
+        && matches!(
+            command,
+            Command::Focus { .. }
+                | Command::Replay { .. }
+                | Command::Serve { .. }
+                | Command::Capabilities
+        )
+    {
+        bail!("--explain applies to wake, snapshot, and watch");
+    }
+    if cli.mock_fixture.is_some()
+        && matches!(
+            command,
+            Command::Replay { .. } | Command::Serve { .. } | Command::Capabilities
+        )
+    {
+        bail!("--mock-fixture applies to wake, snapshot, watch, and focus");
+    }
+    if cli.count_only
+        && matches!(
+            command,
+            Command::Focus { .. }
+                | Command::Replay { .. }
+                | Command::Serve { .. }
+                | Command::Capabilities
+                | Command::Watch { diff: true, .. }
+        )
+    {
+        bail!("--count-only applies to wake, snapshot, and watch without --diff");
+    }
+    if cli.anonymize_ips
+        && matches!(
+            command,
+            Command::Focus { .. }
+                | Command::Replay { .. }
+                | Command::Serve { .. }
+                | Command::Capabilities
+        )
+    {
+        bail!("--anonymize-ips applies to wake, snapshot, and watch");
+    }
+    if cli.redact_titles.is_some()
+        && matches!(
+            command,
+            Command::Wake { .. } | Command::Replay { .. } | Command::Capabilities
+        )
+    {
+        bail!("--redact-titles applies to snapshot, watch, focus, and serve");
+    }
+    if cli.ip_filter.is_some() && !matches!(command, Command::Wake { .. } | Command::Serve { .. }) {
+        bail!("--ip-filter applies to wake and serve");
+    }
+    if cli.compact_level.is_some()
+        && !matches!(command, Command::Wake { .. } | Command::Serve { .. })
+    {
+        bail!("--compact-level applies to wake and serve");
+    }
+    // `--verbose` is level 0; an explicit `--compact-level` wins over it.
+    let compact_level = cli.compact_level.unwrap_or(if cli.verbose {
+        0
+    } else {
+        DEFAULT_COMPACT_LEVEL
+    });
+    // Compact wakes have always listed IPv4 only; verbose ones list everything.
+    let ip_filter = cli.ip_filter.unwrap_or(if compact_level == 0 {
+        IpFilter::All
+    } else {
+        IpFilter::V4
+    });
+    if cli.hash && !matches!(command, Command::Snapshot | Command::Watch { .. }) {
+        bail!("--hash applies to snapshot and watch");
+    }
+    if cli.group_by_app {
+        if !matches!(command, Command::Snapshot | Command::Watch { .. }) {
+            bail!("--group-by-app applies to snapshot and watch");
+        }
+        if cli.count_only {
+            bail!("--group-by-app cannot be combined with --count-only");
+        }
+    }
+    if cli.format == Format::Csv {
+        if !matches!(command, Command::Snapshot | Command::Wake { .. }) {
+            bail!("--format csv applies to wake and snapshot");
+        }
+        if cli.section.is_none() {
+            bail!("--format csv requires --section (connections, windows, ports, or recent_files)");
+        }
+    } else if cli.section.is_some() {
+        bail!("--section applies to --format csv");
+    }
+    let mut sink: Box<dyn Sink> = match &command {
+        Command::Watch {
+            log_dir: Some(log_dir),
+            max_file_bytes,
+            max_files,
+            ..
+        } => {
+            if cli.output.is_some() {
+                bail!("--log-dir cannot be combined with --output");
+            }
+            Box::new(RollingFileSink::open(log_dir, *max_file_bytes, *max_files)?)
+        }
+        Command::Watch { .. } | Command::Focus { .. } | Command::Replay { at: None, .. } => {
+            create_sink(cli.output.as_deref(), true)?
+        }
+        _ => create_sink(cli.output.as_deref(), false)?,
+    };
+    sink = CompressingSink::wrap(sink, cli.compress);
+    if let Command::Watch {
+        json_array: true, ..
+    } = &command
+    {
+        if cli.format != Format::Json {
+            bail!("--json-array requires --format json");
+        }
+        interrupt::install();
+        sink = Box::new(JsonArraySink::new(sink));
+    }
+    if let Command::Watch {
+        webhook: Some(url),
+        webhook_queue,
+        webhook_only,
+        ..
+    } = &command
+    {
+        if cli.no_network {
+            bail!("--webhook cannot be combined with --no-network");
+        }
+        let inner = (!webhook_only).then_some(sink);
+        sink = Box::new(WebhookSink::new(inner, url, *webhook_queue));
+    }
+
+    let color = cli.output.is_none()
+        && cli.compress == Compression::None
+        && std::io::stdout().is_terminal();
+    let style = Style {
+        pretty: cli.pretty,
+        pretty_compact: cli.pretty_compact,
+        format: cli.format,
+        section: cli.section,
+        color,
+    };
+
+    let home = cli.relative_paths.then(dirs::home_dir).flatten();
+    let shape = SnapshotShape {
+        filter_apps: cli.filter_apps.clone(),
+        max_windows: cli.max_windows,
+        max_connections: cli.max_connections,
+        home: home.clone(),
+        group_by_app: cli.group_by_app,
+        anonymize_ips: cli.anonymize_ips,
+        hash: cli.hash,
+        strict: cli.strict,
+        previous: cli
+            .merge_previous
+            .as_deref()
+            .map(read_previous)
+            .transpose()?,
+    };
 
     match command {
-        Command::Wake => {
-            let waker = create_waker(WakeConfig {
-                no_public_ip: cli.no_public_ip,
-            });
-            let wake = waker.wake()?;
-            let wake = if cli.verbose { wake } else { wake.compact() };
-            print_json(&wake, cli.pretty)?;
+        Command::Wake {
+            top_processes,
+            sort_by,
+            collectors,
+            exclude_paths,
+            disk_health,
+            all_mounts,
+            only,
+            env,
+            env_vars,
+            sensors,
+            include_hidden,
+            git,
+            app_catalog,
+            baseline,
+            baseline_ignore,
+            delta_only,
+        } => {
+            let app_catalog = match app_catalog {
+                Some(path) => AppCatalog::load(&path)?.entries(),
+                None => builtin_catalog(),
+            };
+            let mut builder = wake_config(&cli, deadline)
+                .top_processes(top_processes.unwrap_or(0))
+                .sort_by(sort_by)
+                .disk_health(disk_health)
+                .all_mounts(all_mounts)
+                .only(only)
+                .env(env)
+                .sensors(sensors)
+                .include_hidden(include_hidden)
+                .git(git)
+                .app_catalog(app_catalog)
+                .ip_filter(ip_filter);
+            for collector in collectors {
+                builder = builder.collector(collector);
+            }
+            for glob in exclude_paths {
+                builder = builder.exclude_path(glob);
+            }
+            for name in env_vars {
+                builder = builder.env_var(name);
+            }
+            let config = builder.build();
+            if cli.explain {
+                return emit(sink.as_mut(), &explain_wake(&config), &style);
+            }
+
+            let wants_machine = config.collects(WakeSection::Machine);
+            let merge_skip = config.merge_skip_paths();
+            let waker = waker_for(config, cli.mock_fixture.as_deref())?;
+            let mut wake = waker.wake()?;
+            if cli.strict {
+                check_strict()?;
+                if wants_machine && (wake.machine.hostname.is_empty() || wake.machine.os.is_empty())
+                {
+                    bail!("--strict: wake collected no machine info (hostname and os are empty)");
+                }
+            }
+            if let Some(previous) = &shape.previous {
+                wake = merge_previous(wake, previous, &merge_skip)?;
+            }
+            let mut wake = wake
+                .filter_apps(&cli.filter_apps)
+                .cap(cli.max_listening_ports);
+            if let Some(home) = &home {
+                wake = wake.relative_paths(home);
+            }
+            if cli.anonymize_ips {
+                wake = wake.anonymize_ips();
+            }
+            if cli.count_only {
+                if baseline.is_some() {
+                    bail!("--count-only cannot be combined with --baseline");
+                }
+                if delta_only {
+                    bail!("--count-only cannot be combined with --delta-only");
+                }
+                return emit(sink.as_mut(), &wake.counts(), &style);
+            }
+            let wake = wake.compact_to(compact_level);
+            if delta_only {
+                let state = wake_state_path(&wake.machine.machine_id)?;
+                let previous = read_wake_state(&state);
+                emit(
+                    sink.as_mut(),
+                    &create_wake_delta(previous.as_ref(), &wake)?,
+                    &style,
+                )?;
+                return write_wake_state(&state, &wake);
+            }
+            if let Some(baseline) = baseline {
+                let expected: serde_json::Value = serde_json::from_slice(
+                    &std::fs::read(&baseline)
+                        .with_context(|| format!("reading baseline {}", baseline.display()))?,
+                )
+                .with_context(|| format!("parsing baseline {}", baseline.display()))?;
+                let ignore: Vec<String> = DEFAULT_BASELINE_IGNORE
+                    .iter()
+                    .map(|pointer| pointer.to_string())
+                    .chain(baseline_ignore)
+                    .collect();
+                let drift = baseline_drift(&expected, &serde_json::to_value(&wake)?, &ignore);
+                if drift.drifted {
+                    status::report(Outcome::Drifted);
+                }
+                return emit(sink.as_mut(), &drift, &style);
+            }
+            emit(sink.as_mut(), &wake, &style)?;
         }
         Command::Snapshot => {
-            let mut observer = create_observer(ObserverConfig {
-                watch_path: cli.watch_path,
-                all_connections: cli.all_connections,
-            });
-            let snapshot = observer.snapshot()?;
+            let config = observer_config(&cli, deadline, false);
+            if cli.explain {
+                return emit(sink.as_mut(), &explain_snapshot(&config), &style);
+            }
+
+            let mut observer = observer_for(config, cli.mock_fixture.as_deref())?;
+            let snapshot = take_snapshot(observer.as_mut(), &shape)?;
+            if cli.verbose {
+                warn_diagnostics(&snapshot.diagnostics);
+            }
+            if cli.count_only {
+                return emit(sink.as_mut(), &snapshot.counts(), &style);
+            }
             let snapshot = if default_command && !cli.verbose {
                 snapshot.compact()
             } else {
                 snapshot
             };
-            print_json(&snapshot, cli.pretty || default_command)?;
+            emit(
+                sink.as_mut(),
+                &snapshot,
+                &Style {
+                    pretty: cli.pretty || default_command,
+                    ..style
+                },
+            )?;
         }
-        Command::Watch { interval, diff } => {
-            let mut observer = create_observer(ObserverConfig {
-                watch_path: cli.watch_path,
-                all_connections: cli.all_connections,
-            });
+        Command::Watch {
+            interval,
+            diff,
+            poll_adaptive,
+            max_interval,
+            skip_empty,
+            keyed_arrays,
+            full_every,
+            on_event,
+            debounce,
+            jitter_ms,
+            dedupe,
+            heartbeat,
+            ..
+        } => {
+            let config = observer_config(&cli, deadline, false);
+            if cli.explain {
+                let plan = Plan::new("watch", explain_snapshot(&config).collectors);
+                return emit(sink.as_mut(), &plan, &style);
+            }
+
+            let mut observer = observer_for(config, cli.mock_fixture.as_deref())?;
+            let debounce = on_event.then(|| Duration::from_millis(debounce));
+            let mut jitter = Jitter::new(jitter_ms);
 
             if diff {
-                let mut previous = observer.snapshot()?;
-                print_json(&previous, cli.pretty)?;
+                let mut previous = take_snapshot(observer.as_mut(), &shape)?;
+                if cli.verbose {
+                    warn_diagnostics(&previous.diagnostics);
+                }
+                emit(sink.as_mut(), &DiffRecord::snapshot(&previous), &style)?;
+
+                let mut sleep_ms = interval;
+                let mut emitted: u64 = 1;
 
                 loop {
-                    thread::sleep(Duration::from_millis(interval));
-                    let current = observer.snapshot()?;
-                    let envelope = create_diff_envelope(&previous, &current)?;
-                    print_json(&envelope, cli.pretty)?;
+                    let sleep = Duration::from_millis(sleep_ms) + jitter.sample();
+                    if !wait_for_tick(observer.as_mut(), debounce, sleep, deadline) {
+                        break;
+                    }
+                    let current = take_snapshot(observer.as_mut(), &shape)?;
+                    let envelope = if keyed_arrays {
+                        create_keyed_diff_envelope(&previous, &current)?
+                    } else {
+                        create_diff_envelope(&previous, &current)?
+                    };
+                    let keyframe = full_every.is_some_and(|n| emitted.is_multiple_of(n));
+                    if keyframe {
+                        emit(sink.as_mut(), &DiffRecord::snapshot(&current), &style)?;
+                        emitted += 1;
+                    } else if !skip_empty || envelope.has_changes() {
+                        emit(sink.as_mut(), &DiffRecord::Patch(&envelope), &style)?;
+                        emitted += 1;
+                    }
                     previous = current;
+
+                    if poll_adaptive {
+                        sleep_ms = if envelope.has_changes() {
+                            interval
+                        } else {
+                            sleep_ms.saturating_mul(2).min(max_interval.max(interval))
+                        };
+                    }
                 }
             } else {
+                let mut dedupe = dedupe.then(|| Dedupe::new(heartbeat));
+                let mut warned = !cli.verbose;
                 loop {
-                    let snapshot = observer.snapshot()?;
-                    print_json(&snapshot, cli.pretty)?;
-                    thread::sleep(Duration::from_millis(interval));
+                    let snapshot = take_snapshot(observer.as_mut(), &shape)?;
+                    if !warned {
+                        warn_diagnostics(&snapshot.diagnostics);
+                        warned = true;
+                    }
+                    let fresh = dedupe
+                        .as_mut()
+                        .map_or(Ok(true), |dedupe| dedupe.admit(&snapshot))?;
+                    if fresh && cli.count_only {
+                        emit(sink.as_mut(), &snapshot.counts(), &style)?;
+                    } else if fresh {
+                        emit(sink.as_mut(), &snapshot, &style)?;
+                    }
+                    let sleep = Duration::from_millis(interval) + jitter.sample();
+                    if !wait_for_tick(observer.as_mut(), debounce, sleep, deadline) {
+                        break;
+                    }
+                }
+            }
+        }
+        Command::Focus { interval } => {
+            let config = observer_config(&cli, deadline, true);
+            let mut observer = observer_for(config, cli.mock_fixture.as_deref())?;
+            let mut last: Option<FocusRecord> = None;
+            loop {
+                let record = observer.snapshot()?.focus_record();
+                if last.as_ref().is_none_or(|last| !last.same_focus(&record)) {
+                    emit(sink.as_mut(), &record, &style)?;
+                    last = Some(record);
+                }
+                let sleep = Duration::from_millis(interval);
+                if !wait_for_tick(observer.as_mut(), None, sleep, deadline) {
+                    break;
+                }
+            }
+        }
+        Command::Replay { file, at } => {
+            let mut found = false;
+            for step in Replay::new(replay::open(&file)?) {
+                if deadline.expired() {
+                    break;
+                }
+                let step = step?;
+                if at.is_some_and(|at| at != step.index) {
+                    continue;
+                }
+                emit(sink.as_mut(), &step.observation, &style)?;
+                if at.is_some() {
+                    found = true;
+                    break;
+                }
+            }
+
+            if let Some(at) = at
+                && !found
+            {
+                bail!(
+                    "record {at} is past the end of {} or precedes its first snapshot",
+                    file.display()
+                );
+            }
+        }
+        Command::Serve { socket, http } => {
+            let observer = create_observer(observer_config(&cli, deadline, false));
+            let waker = create_waker(wake_config(&cli, deadline).ip_filter(ip_filter).build());
+
+            let mut server = Server::new(observer, waker, cli.filter_apps, compact_level);
+
+            match (socket, http) {
+                (_, Some(addr)) => server.run_http(addr, deadline)?,
+                #[cfg(unix)]
+                (Some(socket), None) => {
+                    let socket = Socket::bind(&socket)?;
+                    server.run(socket.listener(), deadline)?;
                 }
+                #[cfg(not(unix))]
+                (Some(_), None) => bail!("vz serve --socket requires Unix domain sockets"),
+                (None, None) => unreachable!("clap requires --socket or --http"),
             }
         }
+        Command::Capabilities => {
+            let observer = observer_config(&cli, deadline, false);
+            let wake = wake_config(&cli, deadline).build();
+            emit(sink.as_mut(), &capabilities(&observer, &wake), &style)?;
+        }
     }
 
     Ok(())
 }
 
-fn print_json<T: Serialize>(value: &T, pretty: bool) -> Result<()> {
-    let line = if pretty {
-        serde_json::to_string_pretty(value)?
-    } else {
-        serde_json::to_string(value)?
+/// Where `wake --delta-only` keeps the last wake for `machine_id`, keyed by
+/// machine so a cache directory shared across hosts (a network home) never
+/// diffs one machine against another.
+fn wake_state_path(machine_id: &str) -> Result<PathBuf> {
+    let cache = dirs::cache_dir().context("no cache directory for --delta-only state")?;
+    let machine: String = machine_id
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    Ok(cache.join("vizier").join(format!("wake-{machine}.json")))
+}
+
+/// Reads the stored wake, treating a missing or unreadable file as a first
+/// run so a corrupted cache resets instead of failing the job.
+fn read_wake_state(path: &Path) -> Option<serde_json::Value> {
+    let bytes = std::fs::read(path).ok()?;
+    match serde_json::from_slice(&bytes) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            eprintln!(
+                "vz: ignoring unreadable --delta-only state {}: {err}",
+                path.display()
+            );
+            None
+        }
+    }
+}
+
+fn read_previous(path: &Path) -> Result<serde_json::Value> {
+    serde_json::from_slice(
+        &std::fs::read(path)
+            .with_context(|| format!("reading --merge-previous {}", path.display()))?,
+    )
+    .with_context(|| format!("parsing --merge-previous {}", path.display()))
+}
+
+fn write_wake_state(path: &Path, wake: &WakeObservation) -> Result<()> {
+    write_private(path, &serde_json::to_vec(wake)?)
+        .with_context(|| format!("writing --delta-only state {}", path.display()))
+}
+
+/// Fails the run once any required tool was found missing, so CI gets an
+/// error instead of a payload with a silently empty section.
+fn check_strict() -> Result<()> {
+    let missing = status::missing_tools();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    bail!(
+        "--strict: required tool{} not found on PATH: {}",
+        if missing.len() == 1 { "" } else { "s" },
+        missing.join(", ")
+    )
+}
+
+/// `--verbose` echoes snapshot diagnostics to stderr once per run, since
+/// they describe conditions (like a failed watcher) that persist for its life.
+fn warn_diagnostics(diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        eprintln!("vz: warning: {}: {}", diagnostic.source, diagnostic.message);
+    }
+}
+
+fn spawn_watchdog(max_runtime: Duration) {
+    thread::spawn(move || {
+        thread::sleep(max_runtime + WATCHDOG_GRACE);
+        eprintln!(
+            "vz: exceeded --max-runtime of {}ms; aborting",
+            max_runtime.as_millis()
+        );
+        std::process::exit(WATCHDOG_EXIT_CODE);
+    });
+}
+
+/// Fills every setting the user left at its clap default from the config
+/// file, so the file sits below both `VIZIER_*` variables and flags.
+fn apply_config(cli: &mut Cli, matches: &ArgMatches, config: Config) -> Result<()> {
+    let unset = |matches: &ArgMatches, id: &str| {
+        matches!(
+            matches.value_source(id),
+            None | Some(ValueSource::DefaultValue)
+        )
     };
 
-    let mut stdout = std::io::stdout().lock();
-    writeln!(stdout, "{line}")?;
-    stdout.flush()?;
+    if let Some(format) = config.format.filter(|_| unset(matches, "format")) {
+        cli.format = Format::from_str(&format, true)
+            .map_err(|err| anyhow!("config format {format:?}: {err}"))?;
+    }
+    if let Some(pretty) = config.pretty.filter(|_| unset(matches, "pretty")) {
+        cli.pretty = pretty && !cli.pretty_compact;
+    }
+    if let Some(verbose) = config.verbose.filter(|_| unset(matches, "verbose")) {
+        cli.verbose = verbose;
+    }
+    if let Some(no_public_ip) = config
+        .no_public_ip
+        .filter(|_| unset(matches, "no_public_ip"))
+    {
+        cli.no_public_ip = no_public_ip;
+    }
+    if let Some(no_network) = config.no_network.filter(|_| unset(matches, "no_network")) {
+        cli.no_network = no_network;
+    }
+    if let Some(provider) = config
+        .public_ip_provider
+        .filter(|_| unset(matches, "public_ip_provider"))
+    {
+        cli.public_ip_provider = Some(provider);
+    }
+    if let Some(timeout) = config
+        .public_ip_timeout_ms
+        .filter(|_| unset(matches, "public_ip_timeout"))
+    {
+        cli.public_ip_timeout = timeout;
+    }
+    if let Some(retries) = config
+        .public_ip_retries
+        .filter(|_| unset(matches, "public_ip_retries"))
+    {
+        cli.public_ip_retries = retries;
+    }
+    if let Some(proxy) = config.proxy.filter(|_| unset(matches, "proxy")) {
+        cli.proxy = Some(parse_proxy(&proxy).map_err(|err| anyhow!("config proxy: {err}"))?);
+    }
+    if let Some(timeout) = config
+        .command_timeout_ms
+        .filter(|_| unset(matches, "command_timeout"))
+    {
+        cli.command_timeout = timeout;
+    }
+    if let Some(max_runtime) = config.max_runtime.filter(|_| unset(matches, "max_runtime")) {
+        cli.max_runtime = Some(parse_duration(&max_runtime).context("config max_runtime")?);
+    }
+    if let Some(watch_path) = config.watch_path.filter(|_| unset(matches, "watch_path")) {
+        cli.watch_path = Some(watch_path);
+    }
+
+    if let (
+        Some(Command::Wake {
+            exclude_paths,
+            only,
+            ..
+        }),
+        Some(wake),
+    ) = (&mut cli.command, matches.subcommand_matches("wake"))
+    {
+        if exclude_paths.is_empty() && unset(wake, "exclude_paths") {
+            *exclude_paths = config
+                .wake
+                .exclude_paths
+                .iter()
+                .map(|path| parse_exclude_path(path))
+                .collect::<Result<_>>()
+                .context("config wake.exclude_paths")?;
+        }
+        if !config.wake.exclude_sections.is_empty() && unset(wake, "only") {
+            let excluded = config
+                .wake
+                .exclude_sections
+                .iter()
+                .map(|name| {
+                    WakeSection::from_str(name, true)
+                        .map_err(|err| anyhow!("config wake.exclude_sections {name:?}: {err}"))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            *only = WakeSection::value_variants()
+                .iter()
+                .copied()
+                .filter(|section| !excluded.contains(section))
+                .collect();
+        }
+    }
     Ok(())
 }
+
+/// The observer settings every live command shares, taken from the global
+/// flags. `focus_only` observers only need the flags that shape the focused
+/// window, so the rest stay at their defaults.
+fn observer_config(cli: &Cli, deadline: Deadline, focus_only: bool) -> ObserverConfig {
+    if focus_only {
+        return ObserverConfig {
+            no_network: cli.no_network,
+            include_desktop: cli.include_desktop,
+            redact_titles: cli.redact_titles,
+            focus_only: true,
+            deadline,
+            ..ObserverConfig::default()
+        };
+    }
+
+    ObserverConfig {
+        watch_path: cli.watch_path.clone(),
+        include_loopback: cli.include_loopback,
+        conn_states: cli.conn_states.clone(),
+        no_network: cli.no_network,
+        no_windows: cli.no_windows,
+        no_connections: cli.no_connections,
+        clipboard: cli.clipboard,
+        window_resources: cli.window_resources,
+        include_desktop: cli.include_desktop,
+        max_fs_events: cli.max_fs_events,
+        redact_titles: cli.redact_titles,
+        focus_only: false,
+        deadline,
+    }
+}
+
+/// The wake settings every command that wakes shares, taken from the global
+/// flags; `wake` adds its own subcommand flags on top before building.
+fn wake_config(cli: &Cli, deadline: Deadline) -> WakeConfigBuilder {
+    let mut builder = WakeConfig::builder()
+        .no_public_ip(cli.no_public_ip)
+        .no_network(cli.no_network)
+        .public_ip_timeout(Duration::from_millis(cli.public_ip_timeout))
+        .public_ip_retries(cli.public_ip_retries)
+        .command_timeout(Duration::from_millis(cli.command_timeout))
+        .deadline(deadline);
+    if let Some(proxy) = &cli.proxy {
+        builder = builder.proxy(proxy);
+    }
+    if let Some(url) = &cli.public_ip_provider {
+        builder = builder.public_ip_provider(url);
+    }
+    builder
+}
+
+fn observer_for(config: ObserverConfig, fixture: Option<&Path>) -> Result<Box<dyn Observer>> {
+    Ok(match fixture {
+        Some(path) => Box::new(MockObserver::load(path)?),
+        None => create_observer(config),
+    })
+}
+
+fn waker_for(config: WakeConfig, fixture: Option<&Path>) -> Result<Box<dyn Waker>> {
+    Ok(match fixture {
+        Some(path) => Box::new(MockWaker::load(path)?),
+        None => create_waker(config),
+    })
+}
+
+/// Blocks until the next watch record is due: after `sleep`, or under
+/// `--on-event` once filesystem activity has settled for one `debounce`
+/// window. Returns false once `--max-runtime` has passed or, under
+/// `--json-array`, SIGINT has arrived.
+fn wait_for_tick(
+    observer: &mut dyn Observer,
+    debounce: Option<Duration>,
+    sleep: Duration,
+    deadline: Deadline,
+) -> bool {
+    let Some(debounce) = debounce else {
+        interrupt::sleep(deadline.cap(sleep));
+        return !deadline.expired() && !interrupt::interrupted();
+    };
+
+    while !observer.wait_for_event(deadline.cap(EVENT_POLL)) {
+        if deadline.expired() || interrupt::interrupted() {
+            return false;
+        }
+    }
+    for _ in 0..MAX_DEBOUNCE_WINDOWS {
+        if !observer.wait_for_event(deadline.cap(debounce)) {
+            break;
+        }
+    }
+    !deadline.expired() && !interrupt::interrupted()
+}
+
+/// Change-only gate for `watch --dedupe`: admits a snapshot whose content
+/// digest differs from the last one emitted, or any snapshot once
+/// `--heartbeat` has passed since that emission.
+struct Dedupe {
+    heartbeat: Option<Duration>,
+    last: Option<(String, Instant)>,
+}
+
+impl Dedupe {
+    fn new(heartbeat: Option<Duration>) -> Self {
+        Self {
+            heartbeat,
+            last: None,
+        }
+    }
+
+    fn admit(&mut self, snapshot: &Observation) -> Result<bool> {
+        let digest = snapshot.content_digest()?;
+        let fresh = match &self.last {
+            Some((last, emitted_at)) => {
+                *last != digest
+                    || self
+                        .heartbeat
+                        .is_some_and(|heartbeat| emitted_at.elapsed() >= heartbeat)
+            }
+            None => true,
+        };
+        if fresh {
+            self.last = Some((digest, Instant::now()));
+        }
+        Ok(fresh)
+    }
+}
+
+/// Post-collection scoping shared by every `snapshot`/`watch` path.
+#[derive(Debug, Clone)]
+struct SnapshotShape {
+    filter_apps: Vec<String>,
+    max_windows: Option<usize>,
+    max_connections: Option<usize>,
+    home: Option<PathBuf>,
+    group_by_app: bool,
+    anonymize_ips: bool,
+    hash: bool,
+    strict: bool,
+    previous: Option<serde_json::Value>,
+}
+
+fn take_snapshot(observer: &mut dyn Observer, shape: &SnapshotShape) -> Result<Observation> {
+    let mut snapshot = observer.snapshot()?;
+    if shape.strict {
+        check_strict()?;
+    }
+    if let Some(previous) = &shape.previous {
+        snapshot = merge_previous(snapshot, previous, &[])?;
+    }
+    let mut snapshot = snapshot.filter_apps(&shape.filter_apps);
+    if shape.anonymize_ips {
+        snapshot = snapshot.anonymize_ips();
+    }
+    if shape.group_by_app {
+        snapshot = snapshot.group_by_app();
+    }
+    snapshot = snapshot.cap(shape.max_windows, shape.max_connections);
+    if let Some(home) = &shape.home {
+        snapshot = snapshot.relative_paths(home);
+    }
+    if shape.hash {
+        return Ok(snapshot.with_content_hash()?);
+    }
+    Ok(snapshot)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Style {
+    pretty: bool,
+    pretty_compact: bool,
+    format: Format,
+    section: Option<Section>,
+    color: bool,
+}
+
+fn emit<T>(sink: &mut dyn Sink, value: &T, style: &Style) -> Result<()>
+where
+    T: Serialize,
+    for<'a> Text<'a, T>: Display,
+{
+    let record = match style.format {
+        Format::Json => {
+            let mut line = if style.pretty_compact {
+                to_vec_pretty_compact(value)?
+            } else if style.pretty {
+                serde_json::to_vec_pretty(value)?
+            } else {
+                serde_json::to_vec(value)?
+            };
+            line.push(b'\n');
+            line
+        }
+        Format::Text => Text::new(value, style.color).to_string().into_bytes(),
+        Format::Csv => to_csv(
+            value,
+            style.section.context("--format csv requires --section")?,
+        )?,
+    };
+
+    sink.write_record(&record)
+}
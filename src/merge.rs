@@ -0,0 +1,146 @@
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+/// Fields `--merge-previous` never carries forward: live state where empty
+/// is a real observation (no focused window, no new fs events, no sessions),
+/// per-run bookkeeping, and the marker itself.
+pub const MERGE_SKIP_PATHS: [&str; 22] = [
+    "/focus",
+    "/windows",
+    "/windows_truncated",
+    "/terminal_ctx",
+    "/net_connections",
+    "/connections_truncated",
+    "/connections_by_app",
+    "/fs_events",
+    "/fs_events_dropped",
+    "/clipboard",
+    "/content_hash",
+    "/diagnostics",
+    "/truncated",
+    "/stale_fields",
+    "/filesystem/recent_files",
+    "/filesystem/skipped_reason",
+    "/listening_ports",
+    "/listening_ports_truncated",
+    "/recent_activity",
+    "/other_sessions",
+    "/dev_context",
+    "/extra",
+];
+
+/// Fills every field of `current` that came back empty (`null`, missing,
+/// `""`, `"unknown"`, `[]`, or `{}`) with its value in `previous`, recursing
+/// into objects but taking arrays whole, and lists the JSON Pointers it
+/// filled in `stale_fields`. `skip` adds pointers to leave alone on top of
+/// `MERGE_SKIP_PATHS`, such as sections this run was configured not to collect.
+pub fn merge_previous<T: Serialize + DeserializeOwned>(
+    current: T,
+    previous: &Value,
+    skip: &[String],
+) -> serde_json::Result<T> {
+    let mut value = serde_json::to_value(current)?;
+    if let (Value::Object(current), Value::Object(previous)) = (&mut value, previous) {
+        let mut stale = Vec::new();
+        fill(current, previous, "", skip, &mut stale);
+        if !stale.is_empty() {
+            current.insert("stale_fields".to_string(), stale.into());
+        }
+    }
+    serde_json::from_value(value)
+}
+
+fn fill(
+    current: &mut Map<String, Value>,
+    previous: &Map<String, Value>,
+    prefix: &str,
+    skip: &[String],
+    stale: &mut Vec<String>,
+) {
+    for (key, old) in previous {
+        let path = format!("{prefix}/{}", key.replace('~', "~0").replace('/', "~1"));
+        if MERGE_SKIP_PATHS.contains(&path.as_str()) || skip.contains(&path) || is_empty(old) {
+            continue;
+        }
+        match current.get_mut(key) {
+            Some(Value::Object(new)) if !new.is_empty() => {
+                if let Value::Object(old) = old {
+                    fill(new, old, &path, skip, stale);
+                }
+            }
+            Some(new) if !is_empty(new) => {}
+            _ => {
+                current.insert(key.clone(), old.clone());
+                stale.push(path);
+            }
+        }
+    }
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(text) => text.is_empty() || text == "unknown",
+        Value::Array(items) => items.is_empty(),
+        Value::Object(fields) => fields.is_empty(),
+        Value::Bool(_) | Value::Number(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::merge_previous;
+
+    #[test]
+    fn fills_empty_fields_from_previous_and_lists_them() {
+        let previous = json!({
+            "displays": [{ "id": 1 }],
+            "session": { "display_server": "wayland", "compositor": "hyprland" },
+            "resources": { "cpu_model": "Ryzen 7", "gpus": [{ "name": "RTX 4070" }] },
+            "windows": [{ "id": "old" }],
+            "stale_fields": ["/displays"],
+        });
+        let current = json!({
+            "displays": [],
+            "session": { "display_server": "wayland", "compositor": null },
+            "resources": { "cpu_model": "unknown", "gpus": [{ "name": "Arc A770" }] },
+            "windows": [],
+        });
+
+        let merged: Value = merge_previous(current, &previous, &[]).expect("merge should succeed");
+        assert_eq!(merged["displays"], json!([{ "id": 1 }]));
+        assert_eq!(merged["session"]["compositor"], "hyprland");
+        assert_eq!(merged["resources"]["cpu_model"], "Ryzen 7");
+        assert_eq!(merged["resources"]["gpus"], json!([{ "name": "Arc A770" }]));
+        assert_eq!(merged["windows"], json!([]));
+        assert_eq!(
+            merged["stale_fields"],
+            json!(["/displays", "/resources/cpu_model", "/session/compositor"])
+        );
+    }
+
+    #[test]
+    fn complete_payloads_are_left_alone() {
+        let current = json!({ "displays": [{ "id": 2 }], "idle_ms": 0 });
+        let previous = json!({ "displays": [{ "id": 1 }], "idle_ms": 500 });
+        let merged: Value = merge_previous(current.clone(), &previous, &[]).expect("merge");
+        assert_eq!(merged, current);
+    }
+
+    #[test]
+    fn skipped_paths_stay_empty() {
+        let current = json!({ "audio": null, "network_identity": { "public_ip": null } });
+        let previous = json!({
+            "audio": { "output": "Speakers" },
+            "network_identity": { "public_ip": "203.0.113.9" },
+        });
+        let skip = ["/network_identity/public_ip".to_string()];
+        let merged: Value = merge_previous(current, &previous, &skip).expect("merge");
+        assert_eq!(merged["network_identity"]["public_ip"], Value::Null);
+        assert_eq!(merged["audio"]["output"], "Speakers");
+        assert_eq!(merged["stale_fields"], json!(["/audio"]));
+    }
+}
@@ -1,6 +1,14 @@
 use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
 
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::util::net::anonymize_ip;
+
+/// Top-level `Observation` fields left out of `content_hash` because they
+/// change on every snapshot regardless of what was observed.
+const VOLATILE_FIELDS: [&str; 4] = ["ts", "monotonic_ms", "idle_ms", "content_hash"];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WakeObservation {
@@ -13,14 +21,40 @@ pub struct WakeObservation {
     pub installed_apps: Vec<InstalledApp>,
     pub network_identity: NetworkIdentity,
     pub listening_ports: Vec<ListeningPort>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listening_ports_truncated: Option<usize>,
     pub resources: ResourceInfo,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<AudioInfo>,
     pub recent_activity: RecentActivity,
     pub other_sessions: Vec<SessionInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dev_context: Option<DevContext>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<BTreeMap<String, String>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<Diagnostic>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stale_fields: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub source: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct MachineInfo {
     pub hostname: String,
+    #[serde(default)]
+    pub machine_id: String,
+    #[serde(default)]
+    pub fingerprint: String,
     pub os: String,
     pub os_version: String,
     pub kernel: String,
@@ -29,9 +63,22 @@ pub struct MachineInfo {
     pub is_container: bool,
     pub hypervisor: Option<String>,
     pub chassis: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<ContainerInfo>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerInfo {
+    pub runtime: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod_namespace: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UserInfo {
     pub username: String,
     pub full_name: String,
@@ -39,24 +86,34 @@ pub struct UserInfo {
     pub shell: String,
     pub uid: u32,
     pub groups: Vec<String>,
+    #[serde(default)]
+    pub locale: Option<String>,
+    #[serde(default)]
+    pub keyboard_layout: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DateTimeInfo {
     pub ts: f64,
     pub iso: String,
     pub timezone: String,
+    #[serde(default)]
+    pub tz_name: Option<String>,
     pub utc_offset_seconds: i32,
     pub uptime_seconds: u64,
     pub login_ts: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct FilesystemInfo {
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub home_tree: Vec<HomeTreeEntry>,
     pub recent_files: Vec<RecentFileInfo>,
     pub mounts: Vec<MountInfo>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub git_repos: Vec<GitRepoInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped_reason: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -88,12 +145,26 @@ pub struct FileActivityInfo {
     pub modified_ago_s: Option<u64>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GitRepoInfo {
+    pub path: String,
+    pub branch: Option<String>,
+    pub dirty: Option<bool>,
+    pub remote_url: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MountInfo {
     pub path: String,
     pub fs_type: String,
     pub total_gb: f64,
     pub free_gb: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smart_status: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -105,7 +176,7 @@ pub struct InstalledApp {
     pub version: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct NetworkIdentity {
     pub local_ips: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -115,6 +186,8 @@ pub struct NetworkIdentity {
     pub vpn_interface: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub default_gateway: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub primary_mac: Option<String>,
     pub dns_servers: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub hostname_fqdn: Option<String>,
@@ -127,15 +200,38 @@ pub struct ListeningPort {
     pub pid: u32,
     pub app: String,
     pub addr: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ResourceInfo {
     pub cpu_cores: u32,
     pub cpu_model: String,
     pub ram_total_gb: f64,
     pub ram_free_gb: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ram_limit_gb: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_quota: Option<f32>,
     pub gpus: Vec<GpuInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensors: Option<Vec<SensorReading>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SensorReading {
+    pub chip: String,
+    pub label: String,
+    pub kind: SensorKind,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SensorKind {
+    TemperatureC,
+    FanRpm,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -143,12 +239,45 @@ pub struct GpuInfo {
     pub name: String,
     pub vram_gb: Option<f64>,
     pub driver: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub driver_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utilization_pct: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pci_address: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioInfo {
+    pub default_output: Option<String>,
+    pub default_input: Option<String>,
+    pub mic_in_use: Option<bool>,
+}
+
+/// The active language toolchains: what explains a build that behaves
+/// differently on another machine. Only fields that were found are emitted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DevContext {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub python_venv: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_manager: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rust_toolchain: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_manager: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tool_versions: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct RecentActivity {
     pub shell_history: Vec<String>,
     pub running_since_boot: Vec<RunningProcessInfo>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub top_processes: Vec<RunningProcessInfo>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -156,6 +285,10 @@ pub struct RunningProcessInfo {
     pub pid: u32,
     pub app: String,
     pub started_ago_s: u64,
+    #[serde(default)]
+    pub started_at_ts: f64,
+    pub cpu_pct: f32,
+    pub rss_mb: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -164,6 +297,10 @@ pub struct SessionInfo {
     pub tty: String,
     pub from: String,
     pub login_ts: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -174,11 +311,87 @@ pub struct Observation {
     pub idle_ms: u64,
     pub focus: Option<WindowInfo>,
     pub windows: Vec<WindowInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub windows_truncated: Option<usize>,
     pub cursor: Point,
     pub displays: Vec<DisplayInfo>,
     pub terminal_ctx: Option<TerminalCtx>,
     pub net_connections: Vec<ConnInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connections_truncated: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connections_by_app: Option<Vec<AppConnections>>,
     pub fs_events: Vec<FSEvent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fs_events_dropped: Option<usize>,
+    #[serde(default)]
+    pub session: Option<SessionEnv>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clipboard: Option<ClipboardInfo>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<Diagnostic>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stale_fields: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+}
+
+/// Array cardinalities printed by `--count-only` in place of a snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotCounts {
+    pub windows: usize,
+    pub displays: usize,
+    pub connections: usize,
+    pub listening: usize,
+    pub fs_events: usize,
+}
+
+/// Array cardinalities printed by `--count-only` in place of a wake payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WakeCounts {
+    pub apps: usize,
+    pub mounts: usize,
+    pub listening: usize,
+    pub sessions: usize,
+    pub recent_files: usize,
+}
+
+/// One `vz focus` record. The window fields are absent while nothing has
+/// focus (a locked screen, or a backend that cannot tell).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FocusRecord {
+    pub ts: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    pub idle_ms: u64,
+}
+
+impl FocusRecord {
+    /// Whether `other` is the same focused window, ignoring the clock and
+    /// idle time.
+    pub fn same_focus(&self, other: &FocusRecord) -> bool {
+        (&self.app, &self.title, self.pid) == (&other.app, &other.title, other.pid)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipboardInfo {
+    pub has_text: bool,
+    pub has_image: bool,
+    pub text_len: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionEnv {
+    pub display_server: String,
+    pub compositor: Option<String>,
+    pub desktop: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -191,6 +404,18 @@ pub struct WindowInfo {
     pub workspace: i32,
     pub is_minimized: bool,
     pub is_fullscreen: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_special: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rss_mb: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_pct: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub z_index: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layer: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus_duration_ms: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -199,6 +424,10 @@ pub struct DisplayInfo {
     pub bounds: Bounds,
     pub is_primary: bool,
     pub scale_factor: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bit_depth: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hdr: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -207,6 +436,15 @@ pub struct TerminalCtx {
     pub shell: String,
 }
 
+/// One app's share of `net_connections`, emitted by `--group-by-app`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppConnections {
+    pub app: String,
+    pub connections: usize,
+    pub remote_addrs: Vec<String>,
+    pub remote_ports: Vec<u16>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnInfo {
     pub proto: String,
@@ -216,6 +454,7 @@ pub struct ConnInfo {
     pub pid: u32,
     pub app: String,
     pub state: String,
+    pub direction: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub connection_count: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -245,36 +484,386 @@ pub struct Bounds {
     pub h: i32,
 }
 
+/// The `--compact-level` a wake gets when neither it nor `--verbose` is set.
+pub const DEFAULT_COMPACT_LEVEL: u8 = 1;
+/// The highest `--compact-level`; larger values are treated as this one.
+pub const MAX_COMPACT_LEVEL: u8 = 3;
+
+/// How many entries each trimmed list keeps at one compaction level.
+#[derive(Debug, Clone, Copy)]
+struct CompactLimits {
+    groups: usize,
+    recent_files: usize,
+    mounts: usize,
+    local_ips: usize,
+    listening_ports: usize,
+    shell_history: usize,
+    sessions: usize,
+    /// Also drops the inventory sections (apps, GPUs, repos, processes,
+    /// audio), leaving who and where the machine is.
+    identity_only: bool,
+}
+
+/// Levels 1 through `MAX_COMPACT_LEVEL`; level 0 is the uncompacted wake.
+const COMPACT_LIMITS: [CompactLimits; MAX_COMPACT_LEVEL as usize] = [
+    CompactLimits {
+        groups: 2,
+        recent_files: 5,
+        mounts: 3,
+        local_ips: 2,
+        listening_ports: 12,
+        shell_history: 5,
+        sessions: 3,
+        identity_only: false,
+    },
+    CompactLimits {
+        groups: 1,
+        recent_files: 2,
+        mounts: 1,
+        local_ips: 1,
+        listening_ports: 5,
+        shell_history: 2,
+        sessions: 1,
+        identity_only: false,
+    },
+    CompactLimits {
+        groups: 0,
+        recent_files: 0,
+        mounts: 0,
+        local_ips: 1,
+        listening_ports: 0,
+        shell_history: 0,
+        sessions: 0,
+        identity_only: true,
+    },
+];
+
 impl WakeObservation {
-    pub fn compact(mut self) -> Self {
-        self.user.groups = compact_groups(std::mem::take(&mut self.user.groups));
-        self.filesystem.home_tree.clear();
-        self.filesystem.recent_files =
-            compact_recent_files(std::mem::take(&mut self.filesystem.recent_files));
-        self.filesystem.mounts = compact_mounts(std::mem::take(&mut self.filesystem.mounts));
-        self.network_identity.local_ips =
-            compact_local_ips(std::mem::take(&mut self.network_identity.local_ips));
-        self.listening_ports = compact_listening_ports(std::mem::take(&mut self.listening_ports));
-        self.recent_activity.shell_history =
-            compact_shell_history(std::mem::take(&mut self.recent_activity.shell_history));
+    pub fn compact(self) -> Self {
+        self.compact_to(DEFAULT_COMPACT_LEVEL)
+    }
+
+    /// Trims the wake to `--compact-level` `level`: 0 leaves it untouched,
+    /// 1 is the default compact wake, 2 keeps one or two of each list, and
+    /// 3 keeps only identity. Sections that exist only because a flag asked
+    /// for them (`dev_context`, `env`, `extra`, sensors) survive every level.
+    pub fn compact_to(mut self, level: u8) -> Self {
+        let Some(limits) = level
+            .checked_sub(1)
+            .map(|index| COMPACT_LIMITS[usize::from(index.min(MAX_COMPACT_LEVEL - 1))])
+        else {
+            return self;
+        };
+
+        self.user.groups = compact_groups(std::mem::take(&mut self.user.groups), limits.groups);
+        // Hidden entries are only collected under `--include-hidden`, so
+        // keeping them is how that request survives compaction.
+        self.filesystem
+            .home_tree
+            .retain(|entry| entry.path.starts_with("~/."));
+        self.filesystem.recent_files = compact_recent_files(
+            std::mem::take(&mut self.filesystem.recent_files),
+            limits.recent_files,
+        );
+        self.filesystem.mounts =
+            compact_mounts(std::mem::take(&mut self.filesystem.mounts), limits.mounts);
+        self.network_identity.local_ips = compact_local_ips(
+            std::mem::take(&mut self.network_identity.local_ips),
+            limits.local_ips,
+        );
+        self.listening_ports = compact_listening_ports(
+            std::mem::take(&mut self.listening_ports),
+            limits.listening_ports,
+        );
+        self.recent_activity.shell_history = compact_shell_history(
+            std::mem::take(&mut self.recent_activity.shell_history),
+            limits.shell_history,
+        );
         self.recent_activity.running_since_boot.clear();
         self.other_sessions.retain(|session| {
             let from = session.from.trim().to_ascii_lowercase();
             !(from.is_empty() || from == "local" || from == "-")
         });
-        self.other_sessions.truncate(3);
+        self.other_sessions.truncate(limits.sessions);
+
+        if limits.identity_only {
+            self.installed_apps.clear();
+            self.filesystem.git_repos.clear();
+            self.resources.gpus.clear();
+            self.recent_activity.top_processes.clear();
+            self.audio = None;
+        }
+        self
+    }
+
+    pub fn counts(&self) -> WakeCounts {
+        WakeCounts {
+            apps: self.installed_apps.len(),
+            mounts: self.filesystem.mounts.len(),
+            listening: self.listening_ports.len(),
+            sessions: self.other_sessions.len(),
+            recent_files: self.filesystem.recent_files.len(),
+        }
+    }
+
+    /// Keeps the lowest `max_listening_ports` ports and records how many
+    /// were dropped in `listening_ports_truncated`.
+    pub fn cap(mut self, max_listening_ports: Option<usize>) -> Self {
+        if max_listening_ports.is_some() {
+            self.listening_ports
+                .sort_by(|left, right| (left.port, &left.app).cmp(&(right.port, &right.app)));
+        }
+        self.listening_ports_truncated =
+            truncate_to(&mut self.listening_ports, max_listening_ports);
+        self
+    }
+
+    /// Rewrites `recent_files` paths under `home` as `~/...`.
+    pub fn relative_paths(mut self, home: &Path) -> Self {
+        for file in &mut self.filesystem.recent_files {
+            tilde_home(&mut file.path, home);
+        }
+        self
+    }
+
+    /// Masks `local_ips`, `public_ip`, and listening-port addresses down to
+    /// their network (`anonymize_ip`).
+    pub fn anonymize_ips(mut self) -> Self {
+        let network = &mut self.network_identity;
+        for ip in &mut network.local_ips {
+            *ip = anonymize_ip(ip);
+        }
+        network.local_ips.sort();
+        network.local_ips.dedup();
+        for ip in network
+            .public_ip
+            .iter_mut()
+            .chain(network.default_gateway.iter_mut())
+            .chain(network.dns_servers.iter_mut())
+        {
+            *ip = anonymize_ip(ip);
+        }
+        for port in &mut self.listening_ports {
+            port.addr = anonymize_ip(&port.addr);
+        }
+        self
+    }
+
+    pub fn filter_apps(mut self, patterns: &[String]) -> Self {
+        if patterns.is_empty() {
+            return self;
+        }
+
+        self.listening_ports
+            .retain(|port| app_matches(&port.app, patterns));
         self
     }
 }
 
+impl MountInfo {
+    fn smart_failed(&self) -> bool {
+        self.smart_status.as_deref() == Some("failed")
+    }
+}
+
 impl Observation {
+    /// Puts collection-ordered arrays into a stable order so identical state
+    /// serializes identically regardless of which backend gathered it.
+    pub fn sort_canonical(&mut self) {
+        self.windows.sort_by(|left, right| {
+            (left.workspace, &left.app, &left.id).cmp(&(right.workspace, &right.app, &right.id))
+        });
+        self.net_connections
+            .sort_by(|left, right| conn_tuple(left).cmp(&conn_tuple(right)));
+        self.displays.sort_by_key(|display| display.id);
+        self.fs_events.sort_by(|left, right| {
+            left.ts
+                .total_cmp(&right.ts)
+                .then_with(|| (&left.path, &left.kind).cmp(&(&right.path, &right.kind)))
+        });
+    }
+
+    /// Sockets only appear in `LISTEN` when `--conn-states` asks for them,
+    /// so they are counted apart from the connections they would inflate.
+    pub fn counts(&self) -> SnapshotCounts {
+        let listening = self
+            .net_connections
+            .iter()
+            .filter(|connection| connection.state == "LISTEN")
+            .count();
+        SnapshotCounts {
+            windows: self.windows.len(),
+            displays: self.displays.len(),
+            connections: self.net_connections.len() - listening,
+            listening,
+            fs_events: self.fs_events.len(),
+        }
+    }
+
+    pub fn focus_record(&self) -> FocusRecord {
+        let focus = self.focus.as_ref();
+        FocusRecord {
+            ts: self.ts,
+            app: focus.map(|window| window.app.clone()),
+            title: focus.map(|window| window.title.clone()),
+            pid: focus.map(|window| window.pid),
+            idle_ms: self.idle_ms,
+        }
+    }
+
+    /// Stamps `content_hash` with `content_digest`.
+    pub fn with_content_hash(mut self) -> serde_json::Result<Self> {
+        self.content_hash = Some(self.content_digest()?);
+        Ok(self)
+    }
+
+    /// SHA-256 over the key-sorted JSON of everything but `VOLATILE_FIELDS`
+    /// and the focus clock, so two snapshots of the same state hash
+    /// identically.
+    pub fn content_digest(&self) -> serde_json::Result<String> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(object) = value.as_object_mut() {
+            for field in VOLATILE_FIELDS {
+                object.remove(field);
+            }
+            if let Some(focus) = object
+                .get_mut("focus")
+                .and_then(|focus| focus.as_object_mut())
+            {
+                focus.remove("focus_duration_ms");
+            }
+        }
+        let canonical = serde_json::to_vec(&value)?;
+        Ok(format!("{:x}", Sha256::digest(canonical)))
+    }
+
+    /// Truncates `windows` and `net_connections` (in canonical order, so
+    /// connections are kept by protocol and local port) and records the
+    /// dropped counts in `windows_truncated`/`connections_truncated`.
+    pub fn cap(mut self, max_windows: Option<usize>, max_connections: Option<usize>) -> Self {
+        if max_windows.is_some() || max_connections.is_some() {
+            self.sort_canonical();
+        }
+        self.windows_truncated = truncate_to(&mut self.windows, max_windows);
+        self.connections_truncated = truncate_to(&mut self.net_connections, max_connections);
+        self
+    }
+
+    /// Replaces `net_connections` with `connections_by_app`: per app, the
+    /// number of active connections and the distinct remote addresses and
+    /// ports they reach, busiest app first. `LISTEN` rows have no remote end
+    /// and are dropped.
+    pub fn group_by_app(mut self) -> Self {
+        let mut apps: BTreeMap<String, (usize, BTreeSet<String>, BTreeSet<u16>)> = BTreeMap::new();
+        for conn in std::mem::take(&mut self.net_connections) {
+            if conn.state == "LISTEN" {
+                continue;
+            }
+            let (connections, addrs, ports) = apps.entry(conn.app).or_default();
+            *connections += conn.connection_count.map_or(1, |count| count as usize);
+            addrs.insert(conn.remote_addr);
+            ports.insert(conn.remote_port);
+        }
+
+        let mut grouped: Vec<AppConnections> = apps
+            .into_iter()
+            .map(|(app, (connections, addrs, ports))| AppConnections {
+                app,
+                connections,
+                remote_addrs: addrs.into_iter().collect(),
+                remote_ports: ports.into_iter().collect(),
+            })
+            .collect();
+        grouped.sort_by_key(|group| std::cmp::Reverse(group.connections));
+        self.connections_by_app = Some(grouped);
+        self
+    }
+
+    /// Rewrites `fs_events` paths and the terminal `cwd` under `home` as
+    /// `~/...`, matching the home tree's spelling.
+    pub fn relative_paths(mut self, home: &Path) -> Self {
+        for event in &mut self.fs_events {
+            tilde_home(&mut event.path, home);
+        }
+        if let Some(terminal) = &mut self.terminal_ctx {
+            tilde_home(&mut terminal.cwd, home);
+        }
+        self
+    }
+
+    /// Masks every connection's `remote_addr` down to its network
+    /// (`anonymize_ip`).
+    pub fn anonymize_ips(mut self) -> Self {
+        for connection in &mut self.net_connections {
+            connection.remote_addr = anonymize_ip(&connection.remote_addr);
+        }
+        self
+    }
+
     pub fn compact(mut self) -> Self {
         self.net_connections = compact_net_connections(std::mem::take(&mut self.net_connections));
         self
     }
+
+    pub fn filter_apps(mut self, patterns: &[String]) -> Self {
+        if patterns.is_empty() {
+            return self;
+        }
+
+        self.windows
+            .retain(|window| app_matches(&window.app, patterns));
+        self.focus = self
+            .focus
+            .filter(|window| app_matches(&window.app, patterns));
+        self.net_connections
+            .retain(|connection| app_matches(&connection.app, patterns));
+        self
+    }
+}
+
+fn conn_tuple(connection: &ConnInfo) -> (&str, u16, &str, u16, u32, &str) {
+    (
+        &connection.proto,
+        connection.local_port,
+        &connection.remote_addr,
+        connection.remote_port,
+        connection.pid,
+        &connection.state,
+    )
+}
+
+fn tilde_home(path: &mut String, home: &Path) {
+    let Ok(suffix) = Path::new(path.as_str()).strip_prefix(home) else {
+        return;
+    };
+    *path = if suffix.as_os_str().is_empty() {
+        "~".to_string()
+    } else {
+        format!("~/{}", suffix.display())
+    };
 }
 
-fn compact_groups(groups: Vec<String>) -> Vec<String> {
+fn truncate_to<T>(items: &mut Vec<T>, max: Option<usize>) -> Option<usize> {
+    let dropped = items.len().saturating_sub(max?);
+    if dropped == 0 {
+        return None;
+    }
+    items.truncate(items.len() - dropped);
+    Some(dropped)
+}
+
+fn app_matches(app: &str, patterns: &[String]) -> bool {
+    let app = app.to_lowercase();
+    patterns
+        .iter()
+        .any(|pattern| app.contains(&pattern.to_lowercase()))
+}
+
+fn compact_groups(groups: Vec<String>, limit: usize) -> Vec<String> {
+    if limit == 0 {
+        return Vec::new();
+    }
+
     let mut filtered: Vec<String> = groups
         .into_iter()
         .filter(|group| {
@@ -294,13 +883,14 @@ fn compact_groups(groups: Vec<String>) -> Vec<String> {
         return vec!["admin".to_string()];
     }
 
-    filtered.truncate(2);
+    filtered.truncate(limit);
     filtered
 }
 
+type ConnGroupKey = (String, u32, String, String, String);
+
 fn compact_net_connections(connections: Vec<ConnInfo>) -> Vec<ConnInfo> {
-    let mut grouped: BTreeMap<(String, u32, String, String), (ConnInfo, u32, BTreeSet<String>)> =
-        BTreeMap::new();
+    let mut grouped: BTreeMap<ConnGroupKey, (ConnInfo, u32, BTreeSet<String>)> = BTreeMap::new();
 
     for mut connection in connections {
         connection.connection_count = None;
@@ -310,6 +900,7 @@ fn compact_net_connections(connections: Vec<ConnInfo>) -> Vec<ConnInfo> {
             connection.pid,
             connection.proto.clone(),
             connection.state.clone(),
+            connection.direction.clone(),
         );
 
         if let Some((_, count, remote_hosts)) = grouped.get_mut(&key) {
@@ -348,36 +939,45 @@ fn compact_net_connections(connections: Vec<ConnInfo>) -> Vec<ConnInfo> {
     compacted
 }
 
-fn compact_recent_files(files: Vec<RecentFileInfo>) -> Vec<RecentFileInfo> {
+fn compact_recent_files(files: Vec<RecentFileInfo>, limit: usize) -> Vec<RecentFileInfo> {
     let mut compacted: Vec<RecentFileInfo> = files;
 
-    compacted.truncate(5);
+    compacted.truncate(limit);
     compacted
 }
 
-fn compact_mounts(mounts: Vec<MountInfo>) -> Vec<MountInfo> {
+fn compact_mounts(mounts: Vec<MountInfo>, limit: usize) -> Vec<MountInfo> {
     let mut compacted: Vec<MountInfo> = mounts
         .into_iter()
         .filter(|mount| {
-            mount.path == "/" || mount.path == "/home" || mount.path.starts_with("/Volumes/")
+            mount.path == "/"
+                || mount.path == "/home"
+                || mount.path.starts_with("/Volumes/")
+                || mount.smart_failed()
         })
         .collect();
 
-    compacted.sort_by(|left, right| left.path.cmp(&right.path));
+    compacted.sort_by(|left, right| {
+        right
+            .smart_failed()
+            .cmp(&left.smart_failed())
+            .then_with(|| left.path.cmp(&right.path))
+    });
     compacted.dedup_by(|left, right| left.path == right.path);
-    compacted.truncate(3);
+    compacted.truncate(limit);
     compacted
 }
 
-fn compact_local_ips(ips: Vec<String>) -> Vec<String> {
-    let mut filtered: Vec<String> = ips.into_iter().filter(|ip| ip.contains('.')).collect();
-    filtered.sort();
-    filtered.dedup();
-    filtered.truncate(2);
-    filtered
+/// Family filtering happens at collection (`--ip-filter`, IPv4 by default
+/// for compact output), so compaction only bounds the list.
+fn compact_local_ips(mut ips: Vec<String>, limit: usize) -> Vec<String> {
+    ips.sort();
+    ips.dedup();
+    ips.truncate(limit);
+    ips
 }
 
-fn compact_listening_ports(ports: Vec<ListeningPort>) -> Vec<ListeningPort> {
+fn compact_listening_ports(ports: Vec<ListeningPort>, limit: usize) -> Vec<ListeningPort> {
     let keep_ports = [11434_u16, 8080, 6379, 5432, 5173, 3030, 3000, 5000];
     let noise_apps = ["controlce", "rapportd", "ardagent", "identitys"];
 
@@ -392,18 +992,18 @@ fn compact_listening_ports(ports: Vec<ListeningPort>) -> Vec<ListeningPort> {
 
     filtered.sort_by(|left, right| left.port.cmp(&right.port).then(left.app.cmp(&right.app)));
     filtered.dedup_by(|left, right| left.port == right.port && left.app == right.app);
-    filtered.truncate(12);
+    filtered.truncate(limit);
     filtered
 }
 
-fn compact_shell_history(history: Vec<String>) -> Vec<String> {
+fn compact_shell_history(history: Vec<String>, limit: usize) -> Vec<String> {
     let mut filtered: Vec<String> = history
         .into_iter()
         .filter_map(|line| normalize_shell_history_line(&line))
         .collect();
 
     filtered.dedup();
-    let start = filtered.len().saturating_sub(5);
+    let start = filtered.len().saturating_sub(limit);
     filtered.drain(0..start);
     filtered
 }
@@ -431,7 +1031,104 @@ fn normalize_shell_history_line(line: &str) -> Option<String> {
 
 #[cfg(test)]
 mod tests {
-    use super::{ConnInfo, compact_net_connections};
+    use super::{
+        Bounds, COMPACT_LIMITS, ConnInfo, DisplayInfo, MountInfo, Observation, Point, WindowInfo,
+        compact_groups, compact_mounts, compact_net_connections, compact_shell_history,
+    };
+
+    #[test]
+    fn sort_canonical_orders_windows_connections_and_displays() {
+        let window = |workspace: i32, app: &str, id: &str| WindowInfo {
+            id: id.to_string(),
+            title: String::new(),
+            app: app.to_string(),
+            pid: 1,
+            bounds: Bounds {
+                x: 0,
+                y: 0,
+                w: 0,
+                h: 0,
+            },
+            workspace,
+            is_minimized: false,
+            is_fullscreen: false,
+            is_special: false,
+            rss_mb: None,
+            cpu_pct: None,
+            z_index: None,
+            layer: None,
+            focus_duration_ms: None,
+        };
+        let connection = |proto: &str, local_port: u16| ConnInfo {
+            proto: proto.to_string(),
+            local_port,
+            remote_addr: "1.1.1.1".to_string(),
+            remote_port: 443,
+            pid: 42,
+            app: "Browser".to_string(),
+            state: "ESTABLISHED".to_string(),
+            direction: "outbound".to_string(),
+            connection_count: None,
+            remote_host_count: None,
+        };
+        let display = |id: i32| DisplayInfo {
+            id,
+            bounds: Bounds {
+                x: 0,
+                y: 0,
+                w: 0,
+                h: 0,
+            },
+            is_primary: id == 0,
+            scale_factor: 1.0,
+            bit_depth: None,
+            hdr: None,
+        };
+        let mut observation = Observation {
+            schema_version: 1,
+            ts: 0.0,
+            monotonic_ms: 0,
+            idle_ms: 0,
+            focus: None,
+            windows: vec![
+                window(2, "editor", "b"),
+                window(1, "terminal", "a"),
+                window(1, "editor", "c"),
+            ],
+            windows_truncated: None,
+            cursor: Point { x: 0, y: 0 },
+            displays: vec![display(1), display(0)],
+            terminal_ctx: None,
+            net_connections: vec![
+                connection("udp", 1),
+                connection("tcp", 9),
+                connection("tcp", 2),
+            ],
+            connections_truncated: None,
+            connections_by_app: None,
+            fs_events: Vec::new(),
+            fs_events_dropped: None,
+            session: None,
+            clipboard: None,
+            diagnostics: Vec::new(),
+            stale_fields: Vec::new(),
+            truncated: false,
+            content_hash: None,
+        };
+
+        observation.sort_canonical();
+
+        let windows: Vec<&str> = observation.windows.iter().map(|w| w.id.as_str()).collect();
+        assert_eq!(windows, ["c", "a", "b"]);
+        let connections: Vec<(&str, u16)> = observation
+            .net_connections
+            .iter()
+            .map(|c| (c.proto.as_str(), c.local_port))
+            .collect();
+        assert_eq!(connections, [("tcp", 2), ("tcp", 9), ("udp", 1)]);
+        let displays: Vec<i32> = observation.displays.iter().map(|d| d.id).collect();
+        assert_eq!(displays, [0, 1]);
+    }
 
     #[test]
     fn compact_net_connections_groups_duplicate_apps() {
@@ -444,6 +1141,7 @@ mod tests {
                 pid: 42,
                 app: "Browser".to_string(),
                 state: "ESTABLISHED".to_string(),
+                direction: "unknown".to_string(),
                 connection_count: None,
                 remote_host_count: None,
             },
@@ -455,6 +1153,7 @@ mod tests {
                 pid: 42,
                 app: "Browser".to_string(),
                 state: "ESTABLISHED".to_string(),
+                direction: "unknown".to_string(),
                 connection_count: None,
                 remote_host_count: None,
             },
@@ -466,6 +1165,7 @@ mod tests {
                 pid: 7,
                 app: "Discord".to_string(),
                 state: "ESTABLISHED".to_string(),
+                direction: "unknown".to_string(),
                 connection_count: None,
                 remote_host_count: None,
             },
@@ -482,4 +1182,69 @@ mod tests {
         assert_eq!(compacted[1].connection_count, None);
         assert_eq!(compacted[1].remote_host_count, None);
     }
+
+    #[test]
+    fn compact_mounts_keeps_failing_disks_first() {
+        let mount = |path: &str, smart_status: Option<&str>| MountInfo {
+            path: path.to_string(),
+            fs_type: "ext4".to_string(),
+            total_gb: 100.0,
+            free_gb: 50.0,
+            device: Some("/dev/sda1".to_string()),
+            read_only: false,
+            smart_status: smart_status.map(str::to_string),
+        };
+
+        let compacted = compact_mounts(
+            vec![
+                mount("/", Some("passed")),
+                mount("/home", None),
+                mount("/Volumes/Backup", None),
+                mount("/mnt/data", Some("failed")),
+                mount("/mnt/scratch", Some("passed")),
+            ],
+            3,
+        );
+
+        let paths: Vec<&str> = compacted.iter().map(|mount| mount.path.as_str()).collect();
+        assert_eq!(paths, vec!["/mnt/data", "/", "/Volumes/Backup"]);
+    }
+
+    #[test]
+    fn higher_compact_levels_never_keep_more() {
+        for pair in COMPACT_LIMITS.windows(2) {
+            let (looser, tighter) = (pair[0], pair[1]);
+            assert!(tighter.groups <= looser.groups);
+            assert!(tighter.recent_files <= looser.recent_files);
+            assert!(tighter.mounts <= looser.mounts);
+            assert!(tighter.local_ips <= looser.local_ips);
+            assert!(tighter.listening_ports <= looser.listening_ports);
+            assert!(tighter.shell_history <= looser.shell_history);
+            assert!(tighter.sessions <= looser.sessions);
+        }
+
+        let groups = || {
+            vec![
+                "wheel".to_string(),
+                "docker".to_string(),
+                "staff".to_string(),
+            ]
+        };
+        assert_eq!(compact_groups(groups(), 2), vec!["docker", "staff"]);
+        assert_eq!(compact_groups(groups(), 1), vec!["docker"]);
+        assert!(compact_groups(groups(), 0).is_empty());
+
+        let history = || {
+            vec![
+                "ls".to_string(),
+                "cd src".to_string(),
+                "cargo test".to_string(),
+            ]
+        };
+        assert_eq!(
+            compact_shell_history(history(), 2),
+            vec!["cd src", "cargo test"]
+        );
+        assert!(compact_shell_history(history(), 0).is_empty());
+    }
 }
@@ -0,0 +1,231 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use globset::Glob;
+
+use crate::observer::catalog::CatalogEntry;
+use crate::observer::{
+    ExternalCollector, IpFilter, ObserverConfig, ProcessSort, TitleRedaction, WakeConfig,
+    WakeSection,
+};
+use crate::util::deadline::Deadline;
+
+/// Fluent construction of an `ObserverConfig`, starting from its `Default`.
+/// Setters exist for every field, so new options only add methods and
+/// existing call chains keep compiling.
+#[derive(Debug, Clone, Default)]
+pub struct ObserverConfigBuilder {
+    config: ObserverConfig,
+}
+
+impl ObserverConfig {
+    pub fn builder() -> ObserverConfigBuilder {
+        ObserverConfigBuilder::default()
+    }
+}
+
+impl ObserverConfigBuilder {
+    pub fn watch_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.watch_path = Some(path.into());
+        self
+    }
+
+    pub fn include_loopback(mut self, enabled: bool) -> Self {
+        self.config.include_loopback = enabled;
+        self
+    }
+
+    #[deprecated(note = "use `include_loopback`")]
+    pub fn all_connections(self, enabled: bool) -> Self {
+        self.include_loopback(enabled)
+    }
+
+    pub fn conn_states<S: Into<String>>(mut self, states: impl IntoIterator<Item = S>) -> Self {
+        self.config.conn_states = states.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn no_network(mut self, enabled: bool) -> Self {
+        self.config.no_network = enabled;
+        self
+    }
+
+    pub fn no_windows(mut self, enabled: bool) -> Self {
+        self.config.no_windows = enabled;
+        self
+    }
+
+    pub fn no_connections(mut self, enabled: bool) -> Self {
+        self.config.no_connections = enabled;
+        self
+    }
+
+    pub fn clipboard(mut self, enabled: bool) -> Self {
+        self.config.clipboard = enabled;
+        self
+    }
+
+    pub fn window_resources(mut self, enabled: bool) -> Self {
+        self.config.window_resources = enabled;
+        self
+    }
+
+    pub fn include_desktop(mut self, enabled: bool) -> Self {
+        self.config.include_desktop = enabled;
+        self
+    }
+
+    pub fn max_fs_events(mut self, max: usize) -> Self {
+        self.config.max_fs_events = max;
+        self
+    }
+
+    pub fn redact_titles(mut self, mode: TitleRedaction) -> Self {
+        self.config.redact_titles = Some(mode);
+        self
+    }
+
+    pub fn focus_only(mut self, enabled: bool) -> Self {
+        self.config.focus_only = enabled;
+        self
+    }
+
+    pub fn deadline(mut self, deadline: Deadline) -> Self {
+        self.config.deadline = deadline;
+        self
+    }
+
+    pub fn build(self) -> ObserverConfig {
+        self.config
+    }
+}
+
+/// Fluent construction of a `WakeConfig`, starting from its `Default` (the
+/// CLI defaults: 500ms public-IP timeout, two retries, 5s command timeout,
+/// every section).
+#[derive(Debug, Clone, Default)]
+pub struct WakeConfigBuilder {
+    config: WakeConfig,
+}
+
+impl WakeConfig {
+    pub fn builder() -> WakeConfigBuilder {
+        WakeConfigBuilder::default()
+    }
+}
+
+impl WakeConfigBuilder {
+    pub fn no_public_ip(mut self, enabled: bool) -> Self {
+        self.config.no_public_ip = enabled;
+        self
+    }
+
+    pub fn no_network(mut self, enabled: bool) -> Self {
+        self.config.no_network = enabled;
+        self
+    }
+
+    pub fn public_ip_timeout(mut self, timeout: Duration) -> Self {
+        self.config.public_ip_timeout = timeout;
+        self
+    }
+
+    pub fn public_ip_retries(mut self, retries: u32) -> Self {
+        self.config.public_ip_retries = retries;
+        self
+    }
+
+    pub fn proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.config.proxy = Some(proxy.into());
+        self
+    }
+
+    pub fn public_ip_provider(mut self, url: impl Into<String>) -> Self {
+        self.config.public_ip_provider = Some(url.into());
+        self
+    }
+
+    pub fn top_processes(mut self, count: usize) -> Self {
+        self.config.top_processes = count;
+        self
+    }
+
+    pub fn sort_by(mut self, sort: ProcessSort) -> Self {
+        self.config.sort_by = sort;
+        self
+    }
+
+    pub fn collector(mut self, collector: ExternalCollector) -> Self {
+        self.config.collectors.push(collector);
+        self
+    }
+
+    pub fn command_timeout(mut self, timeout: Duration) -> Self {
+        self.config.command_timeout = timeout;
+        self
+    }
+
+    pub fn exclude_path(mut self, glob: Glob) -> Self {
+        self.config.exclude_paths.push(glob);
+        self
+    }
+
+    pub fn disk_health(mut self, enabled: bool) -> Self {
+        self.config.disk_health = enabled;
+        self
+    }
+
+    pub fn all_mounts(mut self, enabled: bool) -> Self {
+        self.config.all_mounts = enabled;
+        self
+    }
+
+    pub fn only(mut self, sections: impl IntoIterator<Item = WakeSection>) -> Self {
+        self.config.only = sections.into_iter().collect();
+        self
+    }
+
+    pub fn env(mut self, enabled: bool) -> Self {
+        self.config.env = enabled;
+        self
+    }
+
+    pub fn env_var(mut self, name: impl Into<String>) -> Self {
+        self.config.env_vars.push(name.into());
+        self
+    }
+
+    pub fn sensors(mut self, enabled: bool) -> Self {
+        self.config.sensors = enabled;
+        self
+    }
+
+    pub fn include_hidden(mut self, enabled: bool) -> Self {
+        self.config.include_hidden = enabled;
+        self
+    }
+
+    pub fn git(mut self, enabled: bool) -> Self {
+        self.config.git = enabled;
+        self
+    }
+
+    pub fn app_catalog(mut self, entries: impl IntoIterator<Item = CatalogEntry>) -> Self {
+        self.config.app_catalog = entries.into_iter().collect();
+        self
+    }
+
+    pub fn ip_filter(mut self, filter: IpFilter) -> Self {
+        self.config.ip_filter = filter;
+        self
+    }
+
+    pub fn deadline(mut self, deadline: Deadline) -> Self {
+        self.config.deadline = deadline;
+        self
+    }
+
+    pub fn build(self) -> WakeConfig {
+        self.config
+    }
+}
@@ -0,0 +1,42 @@
+use serde::Serialize;
+
+/// Which collectors answer on this host, as found by `vz capabilities`
+/// probing each backend once. A `false` field means the matching snapshot or
+/// wake field will come back empty or at its baseline default.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Capabilities {
+    pub windows: bool,
+    pub focus: bool,
+    pub displays: bool,
+    pub cursor: bool,
+    pub idle: bool,
+    pub connections: bool,
+    pub listening_ports: bool,
+    pub fs_events: bool,
+    pub clipboard: bool,
+    pub public_ip: bool,
+    pub sessions: bool,
+    pub audio: bool,
+    pub sensors: bool,
+}
+
+impl Capabilities {
+    /// Field names and values in serialization order, for the text renderer.
+    pub fn entries(&self) -> [(&'static str, bool); 13] {
+        [
+            ("windows", self.windows),
+            ("focus", self.focus),
+            ("displays", self.displays),
+            ("cursor", self.cursor),
+            ("idle", self.idle),
+            ("connections", self.connections),
+            ("listening_ports", self.listening_ports),
+            ("fs_events", self.fs_events),
+            ("clipboard", self.clipboard),
+            ("public_ip", self.public_ip),
+            ("sessions", self.sessions),
+            ("audio", self.audio),
+            ("sensors", self.sensors),
+        ]
+    }
+}
@@ -0,0 +1,130 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+const BUILTIN_APPS: [(&str, &str, &str, Option<&str>); 9] = [
+    ("Visual Studio Code", "code", "ide", None),
+    ("Firefox", "firefox", "browser", None),
+    ("Google Chrome", "google-chrome", "browser", None),
+    ("Alacritty", "alacritty", "terminal", None),
+    ("WezTerm", "wezterm", "terminal", None),
+    ("Docker", "docker", "infra", None),
+    ("Python", "python3", "runtime", Some("python3 --version")),
+    ("Node", "node", "runtime", None),
+    ("Git", "git", "other", None),
+];
+
+/// One app `wake` looks for: present when `binary` is on `PATH` (or, on
+/// macOS, `/Applications/<name>.app` exists). `version_cmd` runs through the
+/// shell under `--command-timeout` and its first output line is the version.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CatalogEntry {
+    pub name: String,
+    pub binary: String,
+    pub kind: String,
+    #[serde(default)]
+    pub version_cmd: Option<String>,
+}
+
+/// A `--app-catalog` file: `[[app]]` tables merged over the built-in list,
+/// or used alone when `replace = true`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppCatalog {
+    pub replace: bool,
+    #[serde(rename = "app")]
+    pub apps: Vec<CatalogEntry>,
+}
+
+impl AppCatalog {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// The entries `wake` probes. A custom entry with the same `binary` as a
+    /// built-in one takes its place; the rest are appended in file order.
+    pub fn entries(self) -> Vec<CatalogEntry> {
+        let mut entries = if self.replace {
+            Vec::new()
+        } else {
+            builtin_catalog()
+        };
+        for app in self.apps {
+            match entries.iter_mut().find(|entry| entry.binary == app.binary) {
+                Some(entry) => *entry = app,
+                None => entries.push(app),
+            }
+        }
+        entries
+    }
+}
+
+pub fn builtin_catalog() -> Vec<CatalogEntry> {
+    BUILTIN_APPS
+        .iter()
+        .map(|(name, binary, kind, version_cmd)| CatalogEntry {
+            name: name.to_string(),
+            binary: binary.to_string(),
+            kind: kind.to_string(),
+            version_cmd: version_cmd.map(str::to_string),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AppCatalog, builtin_catalog};
+
+    #[test]
+    fn custom_entries_override_by_binary_and_append() {
+        let catalog: AppCatalog = toml::from_str(
+            r#"
+            [[app]]
+            name = "Git (internal build)"
+            binary = "git"
+            kind = "vcs"
+            version_cmd = "git --version"
+
+            [[app]]
+            name = "Deploy CLI"
+            binary = "deployctl"
+            kind = "infra"
+            "#,
+        )
+        .expect("catalog should parse");
+
+        let entries = catalog.entries();
+        assert_eq!(entries.len(), builtin_catalog().len() + 1);
+        let git = entries.iter().find(|entry| entry.binary == "git").unwrap();
+        assert_eq!(git.name, "Git (internal build)");
+        assert_eq!(git.version_cmd.as_deref(), Some("git --version"));
+        let last = entries.last().unwrap();
+        assert_eq!(last.binary, "deployctl");
+        assert_eq!(last.version_cmd, None);
+    }
+
+    #[test]
+    fn replace_drops_the_builtin_list() {
+        let catalog: AppCatalog = toml::from_str(
+            r#"
+            replace = true
+
+            [[app]]
+            name = "Deploy CLI"
+            binary = "deployctl"
+            kind = "infra"
+            "#,
+        )
+        .expect("catalog should parse");
+        let entries = catalog.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "Deploy CLI");
+
+        assert!(toml::from_str::<AppCatalog>("[[app]]\nname = \"x\"\nbinary = \"x\"\n").is_err());
+    }
+}
@@ -1,25 +1,41 @@
 use std::env;
 use std::fs;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 use std::os::unix::net::UnixStream;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
 
 use anyhow::Result;
-use chrono::{Datelike, Local, NaiveDateTime, TimeZone};
+use chrono::Local;
 use serde_json::Value;
 use sysinfo::System;
 
 use crate::observation::{
-    Bounds, DisplayInfo, GpuInfo, RunningProcessInfo, SessionInfo, TerminalCtx, WakeObservation,
-    WindowInfo,
+    AudioInfo, Bounds, ContainerInfo, DisplayInfo, GpuInfo, Observation, RunningProcessInfo,
+    SensorKind, SensorReading, SessionEnv, SessionInfo, TerminalCtx, WakeObservation, WindowInfo,
 };
-use crate::observer::common::{BaselineObserver, BaselineWaker};
-use crate::observer::{Observer, ObserverConfig, WakeConfig, Waker};
+use crate::observer::capabilities::Capabilities;
+use crate::observer::common::{
+    BaselineObserver, BaselineWaker, ProcessClock, binary_in_path, bytes_to_gb, iana_zone_name,
+};
+use crate::observer::plan::CollectorPlan;
+use crate::observer::{Observer, ObserverConfig, WakeConfig, WakeSection, Waker, common, wlr};
+use crate::util::deadline::Budget;
+use crate::util::net::normalize_mac;
+use crate::util::process::{lossy_text, run_with_timeout};
+use crate::util::status;
+use crate::util::who::parse_who_line;
+
+const HWMON_ROOT: &str = "/sys/class/hwmon";
+const DRM_ROOT: &str = "/sys/class/drm";
+const HYPR_QUERY_ATTEMPTS: u32 = 3;
+const HYPR_RETRY_DELAY: Duration = Duration::from_millis(25);
 
 pub fn create_observer(config: ObserverConfig) -> Box<dyn Observer> {
     Box::new(LinuxObserver {
         baseline: BaselineObserver::new(config),
+        wlr: None,
     })
 }
 
@@ -31,33 +47,53 @@ pub fn create_waker(config: WakeConfig) -> Box<dyn Waker> {
 
 struct LinuxObserver {
     baseline: BaselineObserver,
+    wlr: Option<wlr::Session>,
 }
 
 impl Observer for LinuxObserver {
-    fn snapshot(&mut self) -> Result<crate::observation::Observation> {
-        let mut observation = self.baseline.snapshot()?;
+    fn wait_for_event(&mut self, timeout: Duration) -> bool {
+        self.baseline.wait_for_event(timeout)
+    }
 
-        let Some(socket_path) = hyprland_socket_path() else {
-            return Ok(observation);
-        };
+    fn snapshot(&mut self) -> Result<Observation> {
+        let mut observation = self.baseline.collect()?;
+        let budget = Budget::new(self.baseline.deadline());
 
-        if let Some(monitors) = hyprland_monitors(&socket_path)
-            && !monitors.is_empty()
-        {
-            observation.displays = monitors;
-        }
+        let focus = if let Some(socket_path) = hyprland_socket_path() {
+            set_compositor(&mut observation, "Hyprland", true);
 
-        if let Some(windows) = hyprland_clients(&socket_path)
-            && !windows.is_empty()
-        {
-            observation.windows = windows;
-        }
+            if !self.baseline.focus_only()
+                && let Some(monitors) = budget.run(|| hyprland_monitors(&socket_path))
+                && !monitors.is_empty()
+            {
+                observation.displays = monitors;
+            }
+
+            if self.baseline.collects_windows()
+                && let Some(windows) = budget.run(|| hyprland_clients(&socket_path))
+                && !windows.is_empty()
+            {
+                observation.windows = windows;
+            }
 
-        if let Some(focus) = hyprland_active_window(&socket_path) {
+            budget.run(|| hyprland_active_window(&socket_path))
+        } else if let Some(toplevels) = budget.run(|| wlr::toplevels(&mut self.wlr)) {
+            set_compositor(&mut observation, "wlroots", false);
+            if !toplevels.windows.is_empty() {
+                observation.windows = toplevels.windows;
+            }
+            toplevels.focus
+        } else {
+            None
+        };
+
+        if let Some(focus) = focus {
             observation.focus = Some(focus.clone());
 
-            if is_terminal_app(&focus.app)
-                && let Some(cwd) = linux_process_cwd(focus.pid)
+            if focus.pid != 0
+                && !self.baseline.focus_only()
+                && is_terminal_app(&focus.app)
+                && let Some(cwd) = self.baseline.process_cwd(focus.pid)
             {
                 observation.terminal_ctx = Some(TerminalCtx {
                     cwd,
@@ -66,6 +102,8 @@ impl Observer for LinuxObserver {
             }
         }
 
+        observation.truncated |= budget.truncated();
+        self.baseline.finish(&mut observation);
         Ok(observation)
     }
 }
@@ -76,41 +114,110 @@ struct LinuxWaker {
 
 impl Waker for LinuxWaker {
     fn wake(&self) -> Result<WakeObservation> {
-        let mut wake = self.baseline.wake()?;
+        let (mut wake, processes) = self.baseline.wake_with_processes()?;
+        let budget = Budget::new(self.baseline.deadline());
+        let config = self.baseline.config();
+        let timeout = || config.deadline.cap(config.command_timeout);
+
+        if self.baseline.collects(WakeSection::Machine) {
+            wake.machine.os = "Linux".to_string();
+
+            let os_info = os_release();
+            if let Some(version) = os_info
+                .get("VERSION_ID")
+                .cloned()
+                .or_else(|| os_info.get("PRETTY_NAME").cloned())
+            {
+                wake.machine.os_version = version;
+            }
 
-        wake.machine.os = "Linux".to_string();
+            if let Some(kernel) = command_stdout("uname", &["-r"], timeout()) {
+                wake.machine.kernel = kernel;
+            }
 
-        let os_info = os_release();
-        if let Some(version) = os_info
-            .get("VERSION_ID")
-            .cloned()
-            .or_else(|| os_info.get("PRETTY_NAME").cloned())
-        {
-            wake.machine.os_version = version;
+            wake.machine.is_container = wake.machine.is_container || detect_container();
+            wake.machine.container = container_info(wake.machine.is_container);
+            if wake.machine.container.is_some() {
+                wake.machine.is_container = true;
+            }
+
+            if let Some(chassis) = chassis_from_dmi() {
+                wake.machine.chassis = chassis;
+            }
+
+            if let Some(hypervisor) = hypervisor_from_dmi().or_else(cpuid_hypervisor) {
+                wake.machine.is_vm = true;
+                wake.machine.hypervisor = Some(hypervisor);
+            }
+
+            if let Some(machine_id) = linux_machine_id() {
+                wake.machine.machine_id = machine_id;
+            }
         }
 
-        if let Some(kernel) = command_stdout("uname", &["-r"]) {
-            wake.machine.kernel = kernel;
+        if self.baseline.collects(WakeSection::User) {
+            let groups = budget.run(|| user_groups(timeout()));
+            if !groups.is_empty() {
+                wake.user.groups = groups;
+            }
+
+            let (locale, keyboard_layout) = budget.run(|| {
+                command_stdout("localectl", &["status"], timeout())
+                    .map(|raw| parse_localectl_status(&raw))
+                    .unwrap_or_default()
+            });
+            if wake.user.locale.is_none() {
+                wake.user.locale = locale;
+            }
+            wake.user.keyboard_layout = keyboard_layout;
         }
 
-        wake.machine.is_container = wake.machine.is_container || detect_container();
+        if self.baseline.collects(WakeSection::Network) {
+            let (default_gateway, route_interface) = budget.run(|| {
+                command_stdout("ip", &["route", "show", "default"], timeout())
+                    .map(|raw| parse_default_route(&raw))
+                    .unwrap_or_default()
+            });
+            if let Some(default_gateway) = default_gateway {
+                wake.network_identity.default_gateway = Some(default_gateway);
+            }
+            wake.network_identity.primary_mac = route_interface.and_then(|interface| {
+                fs::read_to_string(format!("/sys/class/net/{interface}/address"))
+                    .ok()
+                    .and_then(|raw| normalize_mac(&raw))
+            });
+        }
 
-        if let Some(chassis) = chassis_from_dmi() {
-            wake.machine.chassis = chassis;
+        if self.baseline.collects(WakeSection::Resources) {
+            let gpus = budget.run(|| gpu_info(timeout()));
+            if !gpus.is_empty() {
+                wake.resources.gpus = gpus;
+            }
+
+            if config.sensors {
+                wake.resources.sensors = Some(budget.run(|| hwmon_readings(Path::new(HWMON_ROOT))));
+            }
+        }
+
+        if self.baseline.collects(WakeSection::Audio) {
+            wake.audio = budget.run(|| audio_info(timeout()));
         }
 
-        let groups = user_groups();
-        if !groups.is_empty() {
-            wake.user.groups = groups;
+        if let Some(limit_bytes) = cgroup_memory_limit_bytes() {
+            let limit_gb = bytes_to_gb(limit_bytes);
+            if wake.resources.ram_total_gb <= 0.0 || limit_gb < wake.resources.ram_total_gb {
+                wake.resources.ram_limit_gb = Some(limit_gb);
+            }
         }
 
-        if let Some(default_gateway) = default_gateway() {
-            wake.network_identity.default_gateway = Some(default_gateway);
+        if let Some(quota) = cgroup_cpu_quota()
+            && quota < wake.resources.cpu_cores as f32
+        {
+            wake.resources.cpu_quota = Some(quota);
         }
 
-        let gpus = gpu_info();
-        if !gpus.is_empty() {
-            wake.resources.gpus = gpus;
+        if wake.datetime.tz_name.is_none() {
+            wake.datetime.tz_name = linux_tz_name(timeout());
         }
 
         if let Some(uptime_seconds) = linux_uptime_seconds() {
@@ -118,24 +225,134 @@ impl Waker for LinuxWaker {
             wake.datetime.login_ts = wake.ts - uptime_seconds as f64;
         }
 
-        let running_since_boot = running_since_boot(wake.ts as u64);
+        let running_since_boot = running_since_boot(&processes, &ProcessClock::new(wake.ts));
         if !running_since_boot.is_empty() {
             wake.recent_activity.running_since_boot = running_since_boot;
         }
 
-        let sessions = other_sessions();
-        if !sessions.is_empty() {
-            wake.datetime.login_ts = sessions
-                .iter()
-                .map(|x| x.login_ts)
-                .fold(wake.datetime.login_ts, f64::min);
-            wake.other_sessions = sessions;
+        if self.baseline.collects(WakeSection::Sessions) {
+            let sessions = budget.run(|| other_sessions(timeout()));
+            if !sessions.is_empty() {
+                wake.datetime.login_ts = sessions
+                    .iter()
+                    .map(|x| x.login_ts)
+                    .fold(wake.datetime.login_ts, f64::min);
+                wake.other_sessions = sessions;
+            }
         }
 
+        wake.truncated |= budget.truncated();
+        self.baseline.finish(&mut wake);
+
         Ok(wake)
     }
 }
 
+pub fn wake_plan(config: &WakeConfig) -> Vec<CollectorPlan> {
+    let mut plan = common::wake_plan(config);
+    plan.extend([
+        CollectorPlan::new("os_release")
+            .reads("/etc/os-release")
+            .command("uname -r")
+            .timeout(config.command_timeout),
+        CollectorPlan::new("container")
+            .reads("/proc/1/cgroup")
+            .reads("/proc/self/cgroup")
+            .reads("/proc/self/mountinfo")
+            .reads("/var/run/secrets/kubernetes.io/serviceaccount"),
+        CollectorPlan::new("chassis").reads("/sys/class/dmi/id/chassis_type"),
+        CollectorPlan::new("hypervisor")
+            .reads("/sys/class/dmi/id/sys_vendor")
+            .reads("/sys/class/dmi/id/product_name"),
+        CollectorPlan::new("machine_id")
+            .reads("/etc/machine-id")
+            .reads("/var/lib/dbus/machine-id"),
+        CollectorPlan::new("user_groups").command("id -Gn").timeout(config.command_timeout),
+        CollectorPlan::new("locale").command("localectl status").timeout(config.command_timeout),
+        CollectorPlan::new("default_route")
+            .command("ip route show default")
+            .reads("/sys/class/net/<dev>/address")
+            .timeout(config.command_timeout),
+        CollectorPlan::new("gpus")
+            .command("lspci")
+            .command(
+                "nvidia-smi --query-gpu=pci.bus_id,name,memory.total,driver_version,utilization.gpu --format=csv,noheader,nounits",
+            )
+            .reads("/sys/class/drm/card<n>/device")
+            .timeout(config.command_timeout),
+        CollectorPlan::new("audio")
+            .command("pactl info")
+            .command("pactl list short sources")
+            .timeout(config.command_timeout),
+        CollectorPlan::new("cgroup_limits").reads("/sys/fs/cgroup"),
+        CollectorPlan::new("sensors")
+            .reads(format!("{HWMON_ROOT}/*/{{name,temp*,fan*}}"))
+            .enabled_if(config.sensors, "--sensors not set"),
+        CollectorPlan::new("tz_name")
+            .reads("/etc/timezone")
+            .command("timedatectl show -p Timezone --value")
+            .timeout(config.command_timeout),
+        CollectorPlan::new("uptime").reads("/proc/uptime"),
+        CollectorPlan::new("running_since_boot").reads("process table"),
+        CollectorPlan::new("other_sessions").command("who -u").timeout(config.command_timeout),
+    ]);
+    plan
+}
+
+/// Hyprland IPC (windows, focus, and monitors) or, failing that, wlr
+/// foreign-toplevel (windows and focus) on top of the baseline probes. Linux
+/// has no idle-time or cursor source yet.
+pub fn capabilities(observer: &ObserverConfig, wake: &WakeConfig) -> Capabilities {
+    let mut capabilities = common::capabilities(observer, wake);
+    if let Some(socket_path) = hyprland_socket_path()
+        && let Some(monitors) = hyprland_monitors(&socket_path)
+    {
+        capabilities.windows = true;
+        capabilities.focus = true;
+        capabilities.displays = !monitors.is_empty();
+    } else if wlr::Session::connect().is_some() {
+        capabilities.windows = true;
+        capabilities.focus = true;
+    }
+    capabilities.audio = audio_info(wake.deadline.cap(wake.command_timeout)).is_some();
+    capabilities.sensors = !hwmon_readings(Path::new(HWMON_ROOT)).is_empty();
+    capabilities
+}
+
+pub fn snapshot_plan(config: &ObserverConfig) -> Vec<CollectorPlan> {
+    let mut plan = common::snapshot_plan(config);
+    plan.extend([
+        CollectorPlan::new("hyprland")
+            .reads("$XDG_RUNTIME_DIR/hypr/$HYPRLAND_INSTANCE_SIGNATURE/.socket.sock")
+            .enabled_if(
+                env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some(),
+                "not a Hyprland session",
+            ),
+        CollectorPlan::new("wlr_foreign_toplevel")
+            .reads("$WAYLAND_DISPLAY")
+            .enabled_if(
+                env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_none(),
+                "Hyprland IPC takes precedence",
+            )
+            .enabled_if(
+                env::var_os("WAYLAND_DISPLAY").is_some(),
+                "not a Wayland session",
+            ),
+    ]);
+    plan
+}
+
+fn set_compositor(observation: &mut Observation, name: &str, overwrite: bool) {
+    let session = observation.session.get_or_insert_with(|| SessionEnv {
+        display_server: "wayland".to_string(),
+        compositor: None,
+        desktop: None,
+    });
+    if overwrite || session.compositor.is_none() {
+        session.compositor = Some(name.to_string());
+    }
+}
+
 fn hyprland_socket_path() -> Option<PathBuf> {
     let signature = env::var("HYPRLAND_INSTANCE_SIGNATURE").ok()?;
     let runtime = env::var("XDG_RUNTIME_DIR").ok()?;
@@ -148,7 +365,7 @@ fn hyprland_socket_path() -> Option<PathBuf> {
     if path.exists() { Some(path) } else { None }
 }
 
-fn hyprland_clients(socket_path: &PathBuf) -> Option<Vec<WindowInfo>> {
+fn hyprland_clients(socket_path: &Path) -> Option<Vec<WindowInfo>> {
     let raw = hypr_query(socket_path, "j/clients")?;
     let clients: Value = serde_json::from_str(&raw).ok()?;
     let clients = clients.as_array()?;
@@ -174,13 +391,13 @@ fn hyprland_clients(socket_path: &PathBuf) -> Option<Vec<WindowInfo>> {
     Some(output)
 }
 
-fn hyprland_active_window(socket_path: &PathBuf) -> Option<WindowInfo> {
+fn hyprland_active_window(socket_path: &Path) -> Option<WindowInfo> {
     let raw = hypr_query(socket_path, "j/activewindow")?;
     let window: Value = serde_json::from_str(&raw).ok()?;
     Some(hypr_window(&window))
 }
 
-fn hyprland_monitors(socket_path: &PathBuf) -> Option<Vec<DisplayInfo>> {
+fn hyprland_monitors(socket_path: &Path) -> Option<Vec<DisplayInfo>> {
     let raw = hypr_query(socket_path, "j/monitors")?;
     let monitors: Value = serde_json::from_str(&raw).ok()?;
     let monitors = monitors.as_array()?;
@@ -202,6 +419,8 @@ fn hyprland_monitors(socket_path: &PathBuf) -> Option<Vec<DisplayInfo>> {
                         .and_then(|x| x.as_bool())
                         .unwrap_or(false),
                     scale_factor: monitor.get("scale").and_then(|x| x.as_f64()).unwrap_or(1.0),
+                    bit_depth: None,
+                    hdr: None,
                 }
             })
             .collect(),
@@ -231,12 +450,25 @@ fn hypr_window(value: &Value) -> WindowInfo {
 
     let pid = value.get("pid").and_then(|x| x.as_u64()).unwrap_or(0) as u32;
 
-    let workspace = value
-        .get("workspace")
+    // Special (scratchpad) workspaces have negative ids and are named
+    // `special` or `special:<name>`.
+    let workspace = value.get("workspace");
+    let is_special = workspace
+        .and_then(|x| x.get("name"))
+        .and_then(|x| x.as_str())
+        .is_some_and(|name| name == "special" || name.starts_with("special:"));
+    let workspace = workspace
         .and_then(|x| x.get("id"))
         .and_then(|x| x.as_i64())
         .unwrap_or(0) as i32;
 
+    // Hyprland's focus history is its stacking order: 0 is the most recently
+    // focused, topmost window.
+    let z_index = value
+        .get("focusHistoryID")
+        .and_then(|x| x.as_i64())
+        .map(|x| x as i32);
+
     let id = value
         .get("address")
         .and_then(|x| x.as_str())
@@ -252,6 +484,12 @@ fn hypr_window(value: &Value) -> WindowInfo {
         workspace,
         is_minimized: false,
         is_fullscreen: fullscreen,
+        is_special,
+        rss_mb: None,
+        cpu_pct: None,
+        z_index,
+        layer: None,
+        focus_duration_ms: None,
     }
 }
 
@@ -279,25 +517,31 @@ fn hypr_bounds(value: &Value) -> Bounds {
     Bounds { x, y, w, h }
 }
 
-fn hypr_query(socket_path: &PathBuf, command: &str) -> Option<String> {
-    let mut stream = UnixStream::connect(socket_path).ok()?;
-    stream.write_all(command.as_bytes()).ok()?;
-    stream.shutdown(std::net::Shutdown::Write).ok()?;
-
-    let mut out = String::new();
-    stream.read_to_string(&mut out).ok()?;
-
-    if out.trim().is_empty() {
-        None
-    } else {
-        Some(out)
+/// Sends one IPC command, retrying a refused, reset, or empty exchange up to
+/// `HYPR_QUERY_ATTEMPTS` times: Hyprland drops connections that arrive while
+/// it is serving another client. A missing socket fails at once.
+fn hypr_query(socket_path: &Path, command: &str) -> Option<String> {
+    for attempt in 0..HYPR_QUERY_ATTEMPTS {
+        if attempt > 0 {
+            std::thread::sleep(HYPR_RETRY_DELAY * attempt);
+        }
+        match hypr_query_once(socket_path, command) {
+            Ok(out) if !out.trim().is_empty() => return Some(out),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return None,
+            _ => {}
+        }
     }
+    None
 }
 
-fn linux_process_cwd(pid: u32) -> Option<String> {
-    fs::read_link(format!("/proc/{pid}/cwd"))
-        .ok()
-        .map(|path| path.display().to_string())
+fn hypr_query_once(socket_path: &Path, command: &str) -> io::Result<String> {
+    let mut stream = UnixStream::connect(socket_path)?;
+    stream.write_all(command.as_bytes())?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+
+    let mut out = String::new();
+    stream.read_to_string(&mut out)?;
+    Ok(out)
 }
 
 fn is_terminal_app(app: &str) -> bool {
@@ -338,6 +582,81 @@ fn detect_container() -> bool {
         .unwrap_or(false)
 }
 
+fn container_info(is_container: bool) -> Option<ContainerInfo> {
+    let cgroup = fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+    let kube_dir = PathBuf::from("/var/run/secrets/kubernetes.io/serviceaccount");
+    let in_kubernetes = env::var_os("KUBERNETES_SERVICE_HOST").is_some() || kube_dir.exists();
+
+    if !is_container && !in_kubernetes {
+        return None;
+    }
+
+    let runtime = container_runtime(&cgroup, &mountinfo);
+    let container_id = container_id_from(&cgroup).or_else(|| container_id_from(&mountinfo));
+
+    let (pod_name, pod_namespace) = if in_kubernetes {
+        let namespace = fs::read_to_string(kube_dir.join("namespace"))
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty());
+        let name = env::var("POD_NAME")
+            .or_else(|_| env::var("HOSTNAME"))
+            .ok()
+            .filter(|value| !value.is_empty());
+        (name, namespace)
+    } else {
+        (None, None)
+    };
+
+    Some(ContainerInfo {
+        runtime,
+        container_id,
+        pod_name,
+        pod_namespace,
+    })
+}
+
+fn container_runtime(cgroup: &str, mountinfo: &str) -> String {
+    if fs::metadata("/run/.containerenv").is_ok()
+        || env::var("container").is_ok_and(|value| value == "podman")
+        || cgroup.contains("libpod")
+    {
+        return "podman".to_string();
+    }
+
+    if fs::metadata("/.dockerenv").is_ok()
+        || cgroup.contains("docker")
+        || mountinfo.contains("/docker/containers/")
+    {
+        return "docker".to_string();
+    }
+
+    if cgroup.contains("containerd") || mountinfo.contains("containerd") {
+        return "containerd".to_string();
+    }
+
+    if cgroup.contains("crio") || mountinfo.contains("crio") {
+        return "cri-o".to_string();
+    }
+
+    "unknown".to_string()
+}
+
+fn container_id_from(text: &str) -> Option<String> {
+    text.split(|c: char| !c.is_ascii_hexdigit())
+        .find(|token| token.len() == 64)
+        .map(|token| token.to_ascii_lowercase())
+}
+
+fn linux_machine_id() -> Option<String> {
+    ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .map(|value| value.trim().to_string())
+        .find(|value| !value.is_empty())
+}
+
 fn chassis_from_dmi() -> Option<String> {
     let code = fs::read_to_string("/sys/class/dmi/id/chassis_type").ok()?;
     let code = code.trim().parse::<u32>().ok()?;
@@ -351,8 +670,91 @@ fn chassis_from_dmi() -> Option<String> {
     Some(value.to_string())
 }
 
-fn user_groups() -> Vec<String> {
-    command_stdout("id", &["-Gn"])
+fn hypervisor_from_dmi() -> Option<String> {
+    let dmi = Path::new("/sys/class/dmi/id");
+    hypervisor_product(
+        &read_trimmed(&dmi.join("sys_vendor")).unwrap_or_default(),
+        &read_trimmed(&dmi.join("product_name")).unwrap_or_default(),
+    )
+}
+
+/// Names the virtualization product from DMI `sys_vendor`/`product_name`.
+/// Cloud vendors are checked first because their images also carry the
+/// underlying KVM or Xen strings.
+fn hypervisor_product(sys_vendor: &str, product_name: &str) -> Option<String> {
+    let vendor = sys_vendor.to_ascii_lowercase();
+    let product = product_name.to_ascii_lowercase();
+
+    let name = if vendor.starts_with("amazon ec2") || product.starts_with("amazon ec2") {
+        "Amazon EC2"
+    } else if vendor == "google" && product.contains("compute engine") {
+        "Google Compute Engine"
+    } else if vendor.starts_with("digitalocean") {
+        "DigitalOcean"
+    } else if product.starts_with("openstack") || vendor.starts_with("openstack") {
+        "OpenStack"
+    } else if vendor.starts_with("vmware") || product.starts_with("vmware") {
+        "VMware"
+    } else if vendor == "microsoft corporation" && product == "virtual machine" {
+        "Hyper-V"
+    } else if vendor.starts_with("innotek") || product == "virtualbox" {
+        "VirtualBox"
+    } else if vendor.starts_with("parallels") || product.starts_with("parallels") {
+        "Parallels"
+    } else if vendor == "xen" || product.starts_with("hvm domu") {
+        "Xen"
+    } else if vendor == "qemu" || product.starts_with("kvm") {
+        "KVM"
+    } else if vendor == "bhyve" || product == "bhyve" {
+        "bhyve"
+    } else {
+        return None;
+    };
+
+    Some(name.to_string())
+}
+
+#[cfg(target_arch = "x86_64")]
+fn cpuid_hypervisor() -> Option<String> {
+    use std::arch::x86_64::__cpuid;
+
+    // Leaf 1 ECX bit 31 is reserved for hypervisors to announce themselves;
+    // leaf 0x4000_0000 then carries a 12-byte vendor signature.
+    if __cpuid(1).ecx & (1 << 31) == 0 {
+        return None;
+    }
+    let leaf = __cpuid(0x4000_0000);
+    let mut signature = Vec::with_capacity(12);
+    for register in [leaf.ebx, leaf.ecx, leaf.edx] {
+        signature.extend_from_slice(&register.to_le_bytes());
+    }
+    hypervisor_from_cpuid_signature(&signature)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn cpuid_hypervisor() -> Option<String> {
+    None
+}
+
+fn hypervisor_from_cpuid_signature(signature: &[u8]) -> Option<String> {
+    let name = match signature {
+        b"KVMKVMKVM\0\0\0" | b"Linux KVM Hv" => "KVM",
+        b"TCGTCGTCGTCG" => "QEMU",
+        b"Microsoft Hv" => "Hyper-V",
+        b"VMwareVMware" => "VMware",
+        b"XenVMMXenVMM" => "Xen",
+        b"VBoxVBoxVBox" => "VirtualBox",
+        b" lrpepyh  vr" | b"prl hyperv  " => "Parallels",
+        b"bhyve bhyve " => "bhyve",
+        b"ACRNACRNACRN" => "ACRN",
+        _ => return None,
+    };
+
+    Some(name.to_string())
+}
+
+fn user_groups(timeout: Duration) -> Vec<String> {
+    command_stdout("id", &["-Gn"], timeout)
         .map(|x| {
             x.split_whitespace()
                 .map(|s| s.to_string())
@@ -361,61 +763,378 @@ fn user_groups() -> Vec<String> {
         .unwrap_or_default()
 }
 
-fn default_gateway() -> Option<String> {
-    let output = command_stdout("ip", &["route", "show", "default"])?;
-    output.lines().find_map(|line| {
-        let cols: Vec<&str> = line.split_whitespace().collect();
-        let via_index = cols.iter().position(|value| *value == "via")?;
-        cols.get(via_index + 1).map(|x| x.to_string())
+fn audio_info(timeout: Duration) -> Option<AudioInfo> {
+    let (default_output, default_input) =
+        parse_pactl_info(&command_stdout("pactl", &["info"], timeout)?);
+    let mic_in_use = command_stdout("pactl", &["list", "short", "sources"], timeout)
+        .map(|raw| pactl_capture_running(&raw));
+
+    Some(AudioInfo {
+        default_output,
+        default_input,
+        mic_in_use,
     })
 }
 
-fn gpu_info() -> Vec<GpuInfo> {
-    let output = match command_stdout("lspci", &[]) {
-        Some(output) => output,
-        None => return Vec::new(),
+fn parse_pactl_info(raw: &str) -> (Option<String>, Option<String>) {
+    let field = |name: &str| {
+        raw.lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim() == name)
+            .map(|(_, value)| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    (field("Default Sink"), field("Default Source"))
+}
+
+/// A capture source (not a sink's `.monitor`) in the RUNNING state means
+/// something is recording from it right now.
+fn pactl_capture_running(raw: &str) -> bool {
+    raw.lines().any(|line| {
+        let fields: Vec<&str> = line.split('\t').collect();
+        matches!(
+            fields.as_slice(),
+            [_, name, _, _, state, ..] if !name.ends_with(".monitor") && *state == "RUNNING"
+        )
+    })
+}
+
+fn parse_localectl_status(raw: &str) -> (Option<String>, Option<String>) {
+    let mut locale = None;
+    let mut x11_layout = None;
+    let mut vc_keymap = None;
+
+    for line in raw.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if value.is_empty() || value == "n/a" {
+            continue;
+        }
+
+        match key.trim() {
+            "System Locale" => {
+                locale = value
+                    .split_whitespace()
+                    .find_map(|pair| pair.strip_prefix("LANG="))
+                    .map(|x| x.to_string());
+            }
+            "X11 Layout" => x11_layout = Some(value.to_string()),
+            "VC Keymap" => vc_keymap = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    (locale, x11_layout.or(vc_keymap))
+}
+
+fn parse_default_route(raw: &str) -> (Option<String>, Option<String>) {
+    let Some(cols) = raw
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+        .find(|cols| cols.first() == Some(&"default"))
+    else {
+        return (None, None);
+    };
+
+    let after = |key: &str| {
+        let index = cols.iter().position(|value| *value == key)?;
+        cols.get(index + 1).map(|x| x.to_string())
     };
+    (after("via"), after("dev"))
+}
+
+/// Lists GPUs from `lspci`, then fills driver, VRAM, and utilization by PCI
+/// address from `nvidia-smi` (bounded by `timeout`) and the DRM sysfs tree.
+/// NVIDIA GPUs that `lspci` missed (no pciutils installed) are appended.
+fn gpu_info(timeout: Duration) -> Vec<GpuInfo> {
+    let mut gpus = command_stdout("lspci", &["-D"], timeout)
+        .map(|output| parse_lspci_gpus(&output))
+        .unwrap_or_default();
+
+    let nvidia = run_with_timeout(
+        Command::new("nvidia-smi").args([
+            "--query-gpu=pci.bus_id,name,memory.total,driver_version,utilization.gpu",
+            "--format=csv,noheader,nounits",
+        ]),
+        timeout,
+    )
+    .ok()
+    .filter(|output| output.status.success())
+    .map(|output| parse_nvidia_smi(&String::from_utf8_lossy(&output.stdout)))
+    .unwrap_or_default();
+
+    merge_gpu_details(&mut gpus, drm_gpu_details(Path::new(DRM_ROOT)), false);
+    merge_gpu_details(&mut gpus, nvidia, true);
+    gpus
+}
 
+/// `lspci -D` display controllers (`VGA`, `3D`, `Display`), named by the text
+/// after the class and keyed by their domain-qualified PCI address.
+fn parse_lspci_gpus(output: &str) -> Vec<GpuInfo> {
     output
         .lines()
-        .filter(|line| line.contains("VGA") || line.contains("3D controller"))
-        .map(|line| GpuInfo {
-            name: line
-                .split(':')
-                .next_back()
-                .unwrap_or("unknown")
-                .trim()
-                .to_string(),
-            vram_gb: None,
-            driver: "unknown".to_string(),
+        .filter(|line| {
+            line.contains("VGA")
+                || line.contains("3D controller")
+                || line.contains("Display controller")
+        })
+        .map(|line| {
+            let (address, rest) = line.split_once(' ').unwrap_or(("", line));
+            GpuInfo {
+                name: rest
+                    .split_once(": ")
+                    .map_or(rest, |(_, name)| name)
+                    .trim()
+                    .to_string(),
+                vram_gb: None,
+                driver: "unknown".to_string(),
+                driver_version: None,
+                utilization_pct: None,
+                pci_address: normalize_pci_address(address),
+            }
         })
         .collect()
 }
 
+/// Rows of `nvidia-smi --query-gpu=pci.bus_id,name,memory.total,
+/// driver_version,utilization.gpu --format=csv,noheader,nounits`; memory is
+/// in MiB, and fields the driver cannot report read `[N/A]`.
+fn parse_nvidia_smi(output: &str) -> Vec<GpuInfo> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [bus_id, name, memory, version, utilization] = fields[..] else {
+                return None;
+            };
+            Some(GpuInfo {
+                name: name.to_string(),
+                vram_gb: memory.parse::<f64>().ok().map(|mib| mib / 1024.0),
+                driver: "nvidia".to_string(),
+                driver_version: Some(version.to_string()).filter(|v| !v.starts_with('[')),
+                utilization_pct: utilization.parse().ok(),
+                pci_address: Some(normalize_pci_address(bus_id)?),
+            })
+        })
+        .collect()
+}
+
+/// Per-card kernel driver and PCI slot from `card<n>/device/uevent`, plus
+/// `amdgpu`'s `mem_info_vram_total` (bytes) and `gpu_busy_percent`.
+fn drm_gpu_details(root: &Path) -> Vec<GpuInfo> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut cards: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("card"))
+                .is_some_and(|index| !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .collect();
+    cards.sort();
+
+    cards
+        .into_iter()
+        .filter_map(|card| {
+            let device = card.join("device");
+            let uevent = fs::read_to_string(device.join("uevent")).ok()?;
+            let field = |key: &str| {
+                uevent
+                    .lines()
+                    .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+                    .map(str::to_string)
+            };
+            let driver = field("DRIVER")?;
+            Some(GpuInfo {
+                name: String::new(),
+                vram_gb: read_trimmed(&device.join("mem_info_vram_total"))
+                    .and_then(|bytes| bytes.parse().ok())
+                    .map(bytes_to_gb),
+                driver,
+                driver_version: None,
+                utilization_pct: read_trimmed(&device.join("gpu_busy_percent"))
+                    .and_then(|pct| pct.parse().ok()),
+                pci_address: normalize_pci_address(&field("PCI_SLOT_NAME")?),
+            })
+        })
+        .collect()
+}
+
+/// Copies the driver, version, VRAM, and utilization each detail row knows
+/// onto the GPU with the same PCI address; with `append_unmatched`, rows no
+/// GPU matched are added as GPUs of their own.
+fn merge_gpu_details(gpus: &mut Vec<GpuInfo>, details: Vec<GpuInfo>, append_unmatched: bool) {
+    for detail in details {
+        let Some(gpu) = gpus
+            .iter_mut()
+            .find(|gpu| gpu.pci_address.is_some() && gpu.pci_address == detail.pci_address)
+        else {
+            if append_unmatched {
+                gpus.push(detail);
+            }
+            continue;
+        };
+        if detail.driver != "unknown" {
+            gpu.driver = detail.driver;
+        }
+        gpu.vram_gb = detail.vram_gb.or(gpu.vram_gb);
+        gpu.driver_version = detail.driver_version.or(gpu.driver_version.take());
+        gpu.utilization_pct = detail.utilization_pct.or(gpu.utilization_pct);
+    }
+}
+
+/// Spells a PCI address as `dddd:bb:dd.f` in lowercase: `lspci` without `-D`
+/// omits the domain and `nvidia-smi` pads it to eight digits.
+fn normalize_pci_address(address: &str) -> Option<String> {
+    let (domain, slot) = match address.trim().rsplitn(3, ':').collect::<Vec<_>>()[..] {
+        [function, bus] => (0, format!("{bus}:{function}")),
+        [function, bus, domain] => (
+            u32::from_str_radix(domain, 16).ok()?,
+            format!("{bus}:{function}"),
+        ),
+        _ => return None,
+    };
+    slot.contains('.')
+        .then(|| format!("{domain:04x}:{}", slot.to_ascii_lowercase()))
+}
+
+/// Reads every `temp*_input` (millidegrees C) and `fan*_input` (RPM) under
+/// each hwmon chip, labelled by the matching `*_label` file when present.
+/// Unreadable or non-numeric inputs (sensors that are off) are skipped.
+fn hwmon_readings(root: &Path) -> Vec<SensorReading> {
+    let Ok(chips) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut chips: Vec<PathBuf> = chips.flatten().map(|entry| entry.path()).collect();
+    chips.sort();
+
+    let mut readings = Vec::new();
+    for chip_dir in chips {
+        let chip = read_trimmed(&chip_dir.join("name")).unwrap_or_else(|| {
+            chip_dir
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default()
+        });
+        let Ok(entries) = fs::read_dir(&chip_dir) else {
+            continue;
+        };
+        let mut inputs: Vec<String> = entries
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| name.ends_with("_input"))
+            .collect();
+        inputs.sort();
+
+        for input in inputs {
+            let prefix = input.trim_end_matches("_input");
+            let (kind, scale) = if prefix.starts_with("temp") {
+                (SensorKind::TemperatureC, 1000.0)
+            } else if prefix.starts_with("fan") {
+                (SensorKind::FanRpm, 1.0)
+            } else {
+                continue;
+            };
+            let Some(raw) =
+                read_trimmed(&chip_dir.join(&input)).and_then(|raw| raw.parse::<f64>().ok())
+            else {
+                continue;
+            };
+
+            readings.push(SensorReading {
+                chip: chip.clone(),
+                label: read_trimmed(&chip_dir.join(format!("{prefix}_label")))
+                    .unwrap_or_else(|| prefix.to_string()),
+                kind,
+                value: raw / scale,
+            });
+        }
+    }
+
+    readings
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    let value = fs::read_to_string(path).ok()?.trim().to_string();
+    if value.is_empty() { None } else { Some(value) }
+}
+
+fn cgroup_memory_limit_bytes() -> Option<u64> {
+    if let Ok(raw) = fs::read_to_string("/sys/fs/cgroup/memory.max") {
+        return parse_cgroup_v2_memory_max(&raw);
+    }
+
+    let raw = fs::read_to_string("/sys/fs/cgroup/memory/memory.limit_in_bytes").ok()?;
+    let limit = raw.trim().parse::<u64>().ok()?;
+    // cgroup v1 reports "unlimited" as a page-aligned i64::MAX.
+    if limit >= 1 << 62 { None } else { Some(limit) }
+}
+
+fn cgroup_cpu_quota() -> Option<f32> {
+    if let Ok(raw) = fs::read_to_string("/sys/fs/cgroup/cpu.max") {
+        return parse_cgroup_v2_cpu_max(&raw);
+    }
+
+    let quota = fs::read_to_string("/sys/fs/cgroup/cpu/cpu.cfs_quota_us").ok()?;
+    let period = fs::read_to_string("/sys/fs/cgroup/cpu/cpu.cfs_period_us").ok()?;
+    cpu_quota_ratio(quota.trim().parse().ok()?, period.trim().parse().ok()?)
+}
+
+fn parse_cgroup_v2_memory_max(raw: &str) -> Option<u64> {
+    raw.trim().parse::<u64>().ok()
+}
+
+fn parse_cgroup_v2_cpu_max(raw: &str) -> Option<f32> {
+    let mut cols = raw.split_whitespace();
+    let quota = cols.next()?.parse::<i64>().ok()?;
+    let period = cols.next().unwrap_or("100000").parse::<i64>().ok()?;
+    cpu_quota_ratio(quota, period)
+}
+
+fn cpu_quota_ratio(quota: i64, period: i64) -> Option<f32> {
+    if quota <= 0 || period <= 0 {
+        return None;
+    }
+
+    Some(((quota as f64 / period as f64) * 100.0).round() as f32 / 100.0)
+}
+
+fn linux_tz_name(timeout: Duration) -> Option<String> {
+    fs::read_to_string("/etc/timezone")
+        .ok()
+        .and_then(|raw| iana_zone_name(&raw))
+        .or_else(|| {
+            command_stdout(
+                "timedatectl",
+                &["show", "-p", "Timezone", "--value"],
+                timeout,
+            )
+            .and_then(|raw| iana_zone_name(&raw))
+        })
+}
+
 fn linux_uptime_seconds() -> Option<u64> {
     let content = fs::read_to_string("/proc/uptime").ok()?;
     let first = content.split_whitespace().next()?;
     first.parse::<f64>().ok().map(|x| x as u64)
 }
 
-fn running_since_boot(now_ts: u64) -> Vec<RunningProcessInfo> {
-    let mut system = System::new_all();
-    system.refresh_all();
-
-    let boot_time = System::boot_time();
-    if boot_time == 0 {
+fn running_since_boot(system: &System, clock: &ProcessClock) -> Vec<RunningProcessInfo> {
+    if System::boot_time() == 0 {
         return Vec::new();
     }
 
     let mut processes: Vec<RunningProcessInfo> = system
         .processes()
         .values()
-        .filter(|process| process.start_time() <= boot_time.saturating_add(120))
-        .map(|process| RunningProcessInfo {
-            pid: process.pid().as_u32(),
-            app: process.name().to_string_lossy().to_string(),
-            started_ago_s: now_ts.saturating_sub(process.start_time()),
-        })
+        .filter(|process| clock.since_boot(process) <= 120.0)
+        .map(|process| clock.info(process))
         .collect();
 
     processes.sort_by_key(|process| process.started_ago_s);
@@ -424,67 +1143,325 @@ fn running_since_boot(now_ts: u64) -> Vec<RunningProcessInfo> {
     processes
 }
 
-fn other_sessions() -> Vec<SessionInfo> {
-    let output = match command_stdout("who", &[]) {
-        Some(output) => output,
-        None => return Vec::new(),
+fn other_sessions(timeout: Duration) -> Vec<SessionInfo> {
+    if !binary_in_path("who") {
+        status::report_missing("who");
+        return Vec::new();
+    }
+    let Some(output) = command_stdout("who", &["-u"], timeout) else {
+        return Vec::new();
     };
 
-    output.lines().filter_map(parse_who_line).collect()
+    let now = Local::now().naive_local();
+    output
+        .lines()
+        .filter_map(|line| parse_who_line(line, now))
+        .collect()
 }
 
-fn parse_who_line(line: &str) -> Option<SessionInfo> {
-    let cols: Vec<&str> = line.split_whitespace().collect();
-    if cols.len() < 5 {
+/// Runs `bin` under `timeout` (the caller's `--command-timeout`, capped by
+/// `--max-runtime`) and returns its trimmed stdout, or `None` when it fails,
+/// times out, or prints nothing.
+fn command_stdout(bin: &str, args: &[&str], timeout: Duration) -> Option<String> {
+    let output = run_with_timeout(Command::new(bin).args(args), timeout).ok()?;
+    if !output.status.success() {
         return None;
     }
 
-    let username = cols[0].to_string();
-    let tty = cols[1].to_string();
-    let month = cols[2];
-    let day = cols[3];
-    let time = cols[4];
+    let value = lossy_text(output.stdout).trim().to_string();
 
-    let mut year = Local::now().year();
-    let parse_with_year = |year: i32| -> Option<NaiveDateTime> {
-        NaiveDateTime::parse_from_str(&format!("{month} {day} {time} {year}"), "%b %e %H:%M %Y")
-            .ok()
+    if value.is_empty() { None } else { Some(value) }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::observation::{SensorKind, SensorReading};
+
+    use super::{
+        container_id_from, drm_gpu_details, hwmon_readings, hypervisor_from_cpuid_signature,
+        hypervisor_product, hypr_query, hypr_window, merge_gpu_details, pactl_capture_running,
+        parse_cgroup_v2_cpu_max, parse_cgroup_v2_memory_max, parse_default_route,
+        parse_localectl_status, parse_lspci_gpus, parse_nvidia_smi, parse_pactl_info,
     };
 
-    let mut naive = parse_with_year(year)?;
-    let now = Local::now().naive_local();
-    if naive > now {
-        year -= 1;
-        naive = parse_with_year(year)?;
-    }
-
-    let login_ts = Local
-        .from_local_datetime(&naive)
-        .earliest()
-        .map(|dt| dt.timestamp() as f64)
-        .unwrap_or(0.0);
-
-    let from = cols
-        .last()
-        .map(|x| x.to_string())
-        .unwrap_or_else(|| "local".to_string());
-
-    Some(SessionInfo {
-        username,
-        tty,
-        from,
-        login_ts,
-    })
-}
+    #[test]
+    fn container_id_from_finds_cgroup_and_mountinfo_ids() {
+        let id = "3f4e8a1b2c9d0e7f6a5b4c3d2e1f0a9b8c7d6e5f4a3b2c1d0e9f8a7b6c5d4e3f";
 
-fn command_stdout(bin: &str, args: &[&str]) -> Option<String> {
-    let output = Command::new(bin).args(args).output().ok()?;
-    if !output.status.success() {
-        return None;
+        let cgroup_v1 = format!("12:memory:/docker/{id}\n");
+        assert_eq!(container_id_from(&cgroup_v1).as_deref(), Some(id));
+
+        let kubepods = format!("0::/kubepods.slice/cri-containerd-{id}.scope\n");
+        assert_eq!(container_id_from(&kubepods).as_deref(), Some(id));
+
+        let mountinfo =
+            format!("612 598 0:52 /var/lib/docker/containers/{id}/hostname /etc/hostname rw\n");
+        assert_eq!(container_id_from(&mountinfo).as_deref(), Some(id));
+
+        assert_eq!(container_id_from("0::/\n"), None);
     }
 
-    let value = String::from_utf8(output.stdout).ok()?;
-    let value = value.trim().to_string();
+    #[test]
+    fn cgroup_v2_limits_parse_quota_and_unlimited_values() {
+        assert_eq!(parse_cgroup_v2_memory_max("536870912\n"), Some(536_870_912));
+        assert_eq!(parse_cgroup_v2_memory_max("max\n"), None);
 
-    if value.is_empty() { None } else { Some(value) }
+        assert_eq!(parse_cgroup_v2_cpu_max("200000 100000\n"), Some(2.0));
+        assert_eq!(parse_cgroup_v2_cpu_max("50000 100000\n"), Some(0.5));
+        assert_eq!(parse_cgroup_v2_cpu_max("max 100000\n"), None);
+    }
+
+    #[test]
+    fn default_route_yields_gateway_and_interface() {
+        let raw = "default via 192.168.1.1 dev wlp2s0 proto dhcp src 192.168.1.20 metric 600\n\
+                   default via 10.0.0.1 dev eth0 metric 700\n";
+        assert_eq!(
+            parse_default_route(raw),
+            (Some("192.168.1.1".to_string()), Some("wlp2s0".to_string()))
+        );
+
+        assert_eq!(
+            parse_default_route("default dev wg0 scope link\n"),
+            (None, Some("wg0".to_string()))
+        );
+        assert_eq!(parse_default_route(""), (None, None));
+    }
+
+    #[test]
+    fn localectl_status_prefers_x11_layout_over_vc_keymap() {
+        let raw = "   System Locale: LANG=de_DE.UTF-8\n\
+                       VC Keymap: de-latin1\n\
+                      X11 Layout: de\n\
+                       X11 Model: pc105\n";
+        assert_eq!(
+            parse_localectl_status(raw),
+            (Some("de_DE.UTF-8".to_string()), Some("de".to_string()))
+        );
+
+        let raw = "   System Locale: LANG=C.UTF-8\n       VC Keymap: us\n      X11 Layout: n/a\n";
+        assert_eq!(
+            parse_localectl_status(raw),
+            (Some("C.UTF-8".to_string()), Some("us".to_string()))
+        );
+    }
+
+    #[test]
+    fn pactl_info_and_sources_yield_defaults_and_mic_state() {
+        let raw = "Server Name: PulseAudio (on PipeWire 1.0.5)\n\
+                   Default Sink: alsa_output.pci-0000_00_1f.3.analog-stereo\n\
+                   Default Source: alsa_input.usb-Blue_Yeti-00.analog-stereo\n";
+        assert_eq!(
+            parse_pactl_info(raw),
+            (
+                Some("alsa_output.pci-0000_00_1f.3.analog-stereo".to_string()),
+                Some("alsa_input.usb-Blue_Yeti-00.analog-stereo".to_string())
+            )
+        );
+
+        let idle = "55\talsa_output.pci.analog-stereo.monitor\tPipeWire\ts32le 2ch 48000Hz\tRUNNING\n\
+                    56\talsa_input.usb-Blue_Yeti-00.analog-stereo\tPipeWire\ts16le 2ch 48000Hz\tSUSPENDED\n";
+        assert!(!pactl_capture_running(idle));
+        assert!(pactl_capture_running(&idle.replace("SUSPENDED", "RUNNING")));
+    }
+
+    #[test]
+    fn hwmon_readings_scale_temps_and_skip_unreadable_inputs() {
+        let root = std::env::temp_dir().join(format!("vz-hwmon-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let chip = root.join("hwmon0");
+        std::fs::create_dir_all(&chip).unwrap();
+        for (file, contents) in [
+            ("name", "coretemp\n"),
+            ("temp1_input", "45500\n"),
+            ("temp1_label", "Package id 0\n"),
+            ("temp2_input", "\n"),
+            ("fan1_input", "1200\n"),
+            ("in0_input", "900\n"),
+        ] {
+            std::fs::write(chip.join(file), contents).unwrap();
+        }
+
+        let readings = hwmon_readings(&root);
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(
+            readings,
+            vec![
+                SensorReading {
+                    chip: "coretemp".to_string(),
+                    label: "fan1".to_string(),
+                    kind: SensorKind::FanRpm,
+                    value: 1200.0,
+                },
+                SensorReading {
+                    chip: "coretemp".to_string(),
+                    label: "Package id 0".to_string(),
+                    kind: SensorKind::TemperatureC,
+                    value: 45.5,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn hypervisor_names_come_from_dmi_strings_and_cpuid_signatures() {
+        let dmi = |vendor: &str, product: &str| hypervisor_product(vendor, product);
+        assert_eq!(
+            dmi("QEMU", "Standard PC (Q35 + ICH9, 2009)").as_deref(),
+            Some("KVM")
+        );
+        assert_eq!(
+            dmi("VMware, Inc.", "VMware Virtual Platform").as_deref(),
+            Some("VMware")
+        );
+        assert_eq!(
+            dmi("Microsoft Corporation", "Virtual Machine").as_deref(),
+            Some("Hyper-V")
+        );
+        assert_eq!(
+            dmi("innotek GmbH", "VirtualBox").as_deref(),
+            Some("VirtualBox")
+        );
+        assert_eq!(dmi("Amazon EC2", "m5.large").as_deref(), Some("Amazon EC2"));
+        assert_eq!(dmi("Xen", "HVM domU").as_deref(), Some("Xen"));
+        assert_eq!(
+            dmi("Google", "Google Compute Engine").as_deref(),
+            Some("Google Compute Engine")
+        );
+        assert_eq!(dmi("Microsoft Corporation", "Surface Laptop 5"), None);
+        assert_eq!(dmi("LENOVO", "20XW0055US"), None);
+
+        assert_eq!(
+            hypervisor_from_cpuid_signature(b"KVMKVMKVM\0\0\0").as_deref(),
+            Some("KVM")
+        );
+        assert_eq!(
+            hypervisor_from_cpuid_signature(b"Microsoft Hv").as_deref(),
+            Some("Hyper-V")
+        );
+        assert_eq!(hypervisor_from_cpuid_signature(b"GenuineIntel"), None);
+    }
+
+    #[test]
+    fn hypr_window_takes_z_index_from_focus_history() {
+        let window = hypr_window(&serde_json::json!({
+            "address": "0x5a1",
+            "class": "kitty",
+            "title": "zsh",
+            "pid": 4242,
+            "at": [0, 0],
+            "size": [800, 600],
+            "workspace": { "id": 2 },
+            "fullscreen": 0,
+            "focusHistoryID": 1
+        }));
+        assert_eq!(window.z_index, Some(1));
+        assert_eq!(window.workspace, 2);
+
+        let unknown = hypr_window(&serde_json::json!({ "address": "0x5a2" }));
+        assert_eq!(unknown.z_index, None);
+    }
+
+    #[test]
+    fn hypr_window_keeps_special_workspaces_apart() {
+        let scratchpad = hypr_window(&serde_json::json!({
+            "address": "0x5a3",
+            "workspace": { "id": -98, "name": "special:term" }
+        }));
+        assert_eq!(scratchpad.workspace, -98);
+        assert!(scratchpad.is_special);
+
+        let normal = hypr_window(&serde_json::json!({
+            "address": "0x5a4",
+            "workspace": { "id": 3, "name": "3" }
+        }));
+        assert!(!normal.is_special);
+    }
+
+    #[test]
+    fn hypr_query_retries_a_dropped_connection() {
+        use std::io::{Read, Write};
+        use std::os::unix::net::UnixListener;
+
+        let dir = std::env::temp_dir().join(format!("vz-hypr-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("temp dir should be creatable");
+        let socket = dir.join(".socket.sock");
+        let listener = UnixListener::bind(&socket).expect("socket should bind");
+        let server = std::thread::spawn(move || {
+            drop(listener.accept().expect("first client"));
+            let (mut stream, _) = listener.accept().expect("second client");
+            let mut request = String::new();
+            stream.read_to_string(&mut request).expect("request");
+            stream
+                .write_all(format!("ok {request}").as_bytes())
+                .expect("reply");
+        });
+
+        assert_eq!(
+            hypr_query(&socket, "j/clients").as_deref(),
+            Some("ok j/clients")
+        );
+        server.join().expect("server thread");
+        assert_eq!(hypr_query(&dir.join("missing.sock"), "j/clients"), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn gpu_details_merge_into_lspci_rows_by_pci_address() {
+        let mut gpus = parse_lspci_gpus(
+            "0000:00:02.0 VGA compatible controller: Intel Corporation UHD Graphics 630\n\
+             0000:00:1f.3 Audio device: Intel Corporation Cannon Lake PCH cAVS\n\
+             0000:01:00.0 3D controller: NVIDIA Corporation TU117M [GeForce GTX 1650 Mobile]",
+        );
+        assert_eq!(gpus.len(), 2);
+        assert_eq!(gpus[0].name, "Intel Corporation UHD Graphics 630");
+        assert_eq!(gpus[1].pci_address.as_deref(), Some("0000:01:00.0"));
+
+        let nvidia = parse_nvidia_smi(
+            "00000000:01:00.0, NVIDIA GeForce GTX 1650, 4096, 535.54.03, 7\n\
+             00000000:02:00.0, NVIDIA A100, 40960, [N/A], [N/A]",
+        );
+        assert_eq!(nvidia.len(), 2);
+        assert_eq!(nvidia[1].driver_version, None);
+        merge_gpu_details(&mut gpus, nvidia, true);
+
+        assert_eq!(gpus.len(), 3);
+        assert_eq!(gpus[0].driver, "unknown");
+        assert_eq!(
+            gpus[1].name,
+            "NVIDIA Corporation TU117M [GeForce GTX 1650 Mobile]"
+        );
+        assert_eq!(gpus[1].driver, "nvidia");
+        assert_eq!(gpus[1].vram_gb, Some(4.0));
+        assert_eq!(gpus[1].driver_version.as_deref(), Some("535.54.03"));
+        assert_eq!(gpus[1].utilization_pct, Some(7.0));
+        assert_eq!(gpus[2].name, "NVIDIA A100");
+        assert_eq!(gpus[2].pci_address.as_deref(), Some("0000:02:00.0"));
+    }
+
+    #[test]
+    fn drm_gpu_details_read_driver_and_amdgpu_counters() {
+        let root = std::env::temp_dir().join(format!("vz-drm-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let amd = root.join("card1").join("device");
+        std::fs::create_dir_all(&amd).unwrap();
+        std::fs::write(
+            amd.join("uevent"),
+            "DRIVER=amdgpu\nPCI_CLASS=30000\nPCI_SLOT_NAME=0000:03:00.0\n",
+        )
+        .unwrap();
+        std::fs::write(amd.join("mem_info_vram_total"), "8589934592\n").unwrap();
+        std::fs::write(amd.join("gpu_busy_percent"), "12\n").unwrap();
+        std::fs::create_dir_all(root.join("card1-DP-1")).unwrap();
+
+        let details = drm_gpu_details(&root);
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(details.len(), 1);
+        assert_eq!(details[0].driver, "amdgpu");
+        assert_eq!(details[0].pci_address.as_deref(), Some("0000:03:00.0"));
+        assert_eq!(details[0].vram_gb, Some(8.0));
+        assert_eq!(details[0].utilization_pct, Some(12.0));
+    }
 }
@@ -1,7 +1,8 @@
 use std::process::Command;
+use std::time::Duration;
 
 use anyhow::Result;
-use chrono::{Datelike, Local, NaiveDateTime, TimeZone};
+use chrono::Local;
 use core_foundation::base::{CFType, TCFType};
 use core_foundation::dictionary::CFDictionary;
 use core_foundation::number::CFNumber;
@@ -14,14 +15,25 @@ use serde_json::Value;
 use sysinfo::System;
 
 use crate::observation::{
-    Bounds, DisplayInfo, GpuInfo, Point, RunningProcessInfo, SessionInfo, WakeObservation,
-    WindowInfo,
+    AudioInfo, Bounds, DisplayInfo, GpuInfo, Point, RunningProcessInfo, SensorKind, SensorReading,
+    SessionInfo, WakeObservation, WindowInfo,
 };
-use crate::observer::common::{BaselineObserver, BaselineWaker};
-use crate::observer::{Observer, ObserverConfig, WakeConfig, Waker};
+use crate::observer::capabilities::Capabilities;
+use crate::observer::common::{
+    BaselineObserver, BaselineWaker, ProcessClock, binary_in_path, iana_zone_name,
+};
+use crate::observer::plan::CollectorPlan;
+use crate::observer::{Observer, ObserverConfig, WakeConfig, WakeSection, Waker, common};
+use crate::util::deadline::Budget;
+use crate::util::net::normalize_mac;
+use crate::util::process::lossy_text;
+use crate::util::status;
+use crate::util::system_profiler::parse_displays_data;
+use crate::util::who::parse_who_line;
 
 pub fn create_observer(config: ObserverConfig) -> Box<dyn Observer> {
     Box::new(MacObserver {
+        include_desktop: config.include_desktop,
         baseline: BaselineObserver::new(config),
     })
 }
@@ -34,24 +46,39 @@ pub fn create_waker(config: WakeConfig) -> Box<dyn Waker> {
 
 struct MacObserver {
     baseline: BaselineObserver,
+    include_desktop: bool,
 }
 
 impl Observer for MacObserver {
+    fn wait_for_event(&mut self, timeout: Duration) -> bool {
+        self.baseline.wait_for_event(timeout)
+    }
+
     fn snapshot(&mut self) -> Result<crate::observation::Observation> {
-        let mut observation = self.baseline.snapshot()?;
+        let mut observation = self.baseline.collect()?;
+        let budget = Budget::new(self.baseline.deadline());
 
-        let displays = collect_displays();
-        if !displays.is_empty() {
-            observation.displays = displays;
+        if !self.baseline.focus_only() {
+            let displays = budget.run(collect_displays);
+            if !displays.is_empty() {
+                observation.displays = displays;
+            }
         }
 
-        let windows = collect_windows(&observation.displays);
+        let windows = budget.run(|| collect_windows(&observation.displays, self.include_desktop));
         if !windows.is_empty() {
-            observation.focus = Some(windows[0].clone());
+            // Desktop elements (`--include-desktop`) can sit in front of the
+            // focused app, so focus is the frontmost normal-layer window.
+            observation.focus = windows
+                .iter()
+                .find(|window| window.layer.unwrap_or(0) == 0)
+                .cloned();
             observation.windows = windows;
         }
 
-        if let Some(cursor) = cursor_position() {
+        if !self.baseline.focus_only()
+            && let Some(cursor) = cursor_position()
+        {
             observation.cursor = cursor;
         }
 
@@ -59,6 +86,8 @@ impl Observer for MacObserver {
             observation.idle_ms = idle_ms;
         }
 
+        observation.truncated |= budget.truncated();
+        self.baseline.finish(&mut observation);
         Ok(observation)
     }
 }
@@ -69,68 +98,168 @@ struct MacWaker {
 
 impl Waker for MacWaker {
     fn wake(&self) -> Result<WakeObservation> {
-        let mut wake = self.baseline.wake()?;
+        let (mut wake, processes) = self.baseline.wake_with_processes()?;
+        let budget = Budget::new(self.baseline.deadline());
 
-        wake.machine.os = "macOS".to_string();
+        if self.baseline.collects(WakeSection::Machine) {
+            wake.machine.os = "macOS".to_string();
 
-        if let Some(version) = command_stdout("sw_vers", &["-productVersion"]) {
-            wake.machine.os_version = version;
-        }
+            if let Some(version) = command_stdout("sw_vers", &["-productVersion"]) {
+                wake.machine.os_version = version;
+            }
 
-        if let Some(kernel) = command_stdout("uname", &["-r"]) {
-            wake.machine.kernel = format!("Darwin {kernel}");
-        }
+            if let Some(kernel) = command_stdout("uname", &["-r"]) {
+                wake.machine.kernel = format!("Darwin {kernel}");
+            }
 
-        if let Some(model) = command_stdout("sysctl", &["-n", "hw.model"]) {
-            wake.machine.chassis = if model.starts_with("MacBook") {
-                "Laptop".to_string()
-            } else {
-                "Desktop".to_string()
-            };
+            if let Some(model) = command_stdout("sysctl", &["-n", "hw.model"]) {
+                wake.machine.chassis = if model.starts_with("MacBook") {
+                    "Laptop".to_string()
+                } else {
+                    "Desktop".to_string()
+                };
+            }
+
+            if let Some(machine_id) = platform_uuid() {
+                wake.machine.machine_id = machine_id;
+            }
         }
 
-        let groups = user_groups();
-        if !groups.is_empty() {
-            wake.user.groups = groups;
+        if self.baseline.collects(WakeSection::User) {
+            let groups = budget.run(user_groups);
+            if !groups.is_empty() {
+                wake.user.groups = groups;
+            }
+
+            if wake.user.locale.is_none() {
+                wake.user.locale = command_stdout("defaults", &["read", "-g", "AppleLocale"]);
+            }
+            wake.user.keyboard_layout = current_input_source();
         }
 
-        if let Some(default_gateway) = default_gateway() {
-            wake.network_identity.default_gateway = Some(default_gateway);
+        if self.baseline.collects(WakeSection::Network) {
+            if let Some(default_gateway) = budget.run(default_gateway) {
+                wake.network_identity.default_gateway = Some(default_gateway);
+            }
+            wake.network_identity.primary_mac = budget.run(primary_mac);
+
+            let dns = budget.run(dns_servers);
+            if !dns.is_empty() {
+                wake.network_identity.dns_servers = dns;
+            }
         }
 
-        let dns = dns_servers();
-        if !dns.is_empty() {
-            wake.network_identity.dns_servers = dns;
+        if self.baseline.collects(WakeSection::Resources) {
+            let ram_total_gb = Some(wake.resources.ram_total_gb).filter(|gb| *gb > 0.0);
+            let gpus = budget.run(|| gpu_info(ram_total_gb));
+            if !gpus.is_empty() {
+                wake.resources.gpus = gpus;
+            }
+
+            if self.baseline.config().sensors {
+                wake.resources.sensors = Some(budget.run(smc_sensors));
+            }
         }
 
-        let gpus = gpu_info();
-        if !gpus.is_empty() {
-            wake.resources.gpus = gpus;
+        if self.baseline.collects(WakeSection::Audio) {
+            wake.audio = budget.run(audio_info);
         }
 
-        if let Some(uptime_seconds) = uptime_seconds_from_boottime(wake.ts) {
-            wake.datetime.uptime_seconds = uptime_seconds;
-            wake.datetime.login_ts = wake.ts - uptime_seconds as f64;
+        if self.baseline.collects(WakeSection::Datetime) {
+            if wake.datetime.tz_name.is_none() {
+                wake.datetime.tz_name =
+                    command_stdout("systemsetup", &["-gettimezone"]).and_then(|raw| {
+                        raw.split_once(':')
+                            .and_then(|(_, zone)| iana_zone_name(zone))
+                    });
+            }
+
+            if let Some(uptime_seconds) = uptime_seconds_from_boottime(wake.ts) {
+                wake.datetime.uptime_seconds = uptime_seconds;
+                wake.datetime.login_ts = wake.ts - uptime_seconds as f64;
+            }
         }
 
-        let running_since_boot = running_since_boot(wake.ts as u64);
+        let running_since_boot = running_since_boot(&processes, &ProcessClock::new(wake.ts));
         if !running_since_boot.is_empty() {
             wake.recent_activity.running_since_boot = running_since_boot;
         }
 
-        let sessions = other_sessions();
-        if !sessions.is_empty() {
-            wake.datetime.login_ts = sessions
-                .iter()
-                .map(|x| x.login_ts)
-                .fold(wake.datetime.login_ts, f64::min);
-            wake.other_sessions = sessions;
+        if self.baseline.collects(WakeSection::Sessions) {
+            let sessions = budget.run(other_sessions);
+            if !sessions.is_empty() {
+                wake.datetime.login_ts = sessions
+                    .iter()
+                    .map(|x| x.login_ts)
+                    .fold(wake.datetime.login_ts, f64::min);
+                wake.other_sessions = sessions;
+            }
         }
 
+        wake.truncated |= budget.truncated();
+        self.baseline.finish(&mut wake);
+
         Ok(wake)
     }
 }
 
+pub fn wake_plan(config: &WakeConfig) -> Vec<CollectorPlan> {
+    let mut plan = common::wake_plan(config);
+    plan.extend([
+        CollectorPlan::new("os_release")
+            .command("sw_vers -productVersion")
+            .command("uname -r")
+            .command("sysctl -n hw.model"),
+        CollectorPlan::new("machine_id").command("ioreg -rd1 -c IOPlatformExpertDevice"),
+        CollectorPlan::new("user_groups").command("id -Gn"),
+        CollectorPlan::new("locale")
+            .command("defaults read -g AppleLocale")
+            .command("defaults read com.apple.HIToolbox AppleCurrentKeyboardLayoutInputSourceID"),
+        CollectorPlan::new("default_route")
+            .command("netstat -nr")
+            .command("route -n get default")
+            .command("ifconfig <interface>"),
+        CollectorPlan::new("dns_servers").command("scutil --dns"),
+        CollectorPlan::new("gpus").command("system_profiler SPDisplaysDataType -json"),
+        CollectorPlan::new("audio").command("system_profiler SPAudioDataType -json"),
+        CollectorPlan::new("sensors")
+            .command("powermetrics --samplers smc -n 1 -i 200")
+            .enabled_if(config.sensors, "--sensors not set"),
+        CollectorPlan::new("tz_name").command("systemsetup -gettimezone"),
+        CollectorPlan::new("uptime").command("sysctl -n kern.boottime"),
+        CollectorPlan::new("running_since_boot").reads("process table"),
+        CollectorPlan::new("other_sessions").command("who -u"),
+    ]);
+    plan
+}
+
+/// CoreGraphics displays, windows, and cursor, the IOHID idle timer, audio
+/// devices, and SMC sensors (root only) on top of the baseline probes.
+pub fn capabilities(observer: &ObserverConfig, wake: &WakeConfig) -> Capabilities {
+    let mut capabilities = common::capabilities(observer, wake);
+    let displays = collect_displays();
+    let windows = collect_windows(&displays, false);
+    capabilities.displays = !displays.is_empty();
+    capabilities.windows = !windows.is_empty();
+    capabilities.focus = !windows.is_empty();
+    capabilities.cursor = cursor_position().is_some();
+    capabilities.idle = idle_ms().is_some();
+    capabilities.audio = audio_info().is_some();
+    capabilities.sensors = !smc_sensors().is_empty();
+    capabilities
+}
+
+pub fn snapshot_plan(config: &ObserverConfig) -> Vec<CollectorPlan> {
+    let mut plan = common::snapshot_plan(config);
+    plan.extend([
+        CollectorPlan::new("displays").reads("CoreGraphics display list"),
+        CollectorPlan::new("windows").reads("CoreGraphics window list"),
+        CollectorPlan::new("cursor").reads("CoreGraphics event source"),
+        CollectorPlan::new("idle_ms").command("ioreg -c IOHIDSystem"),
+    ]);
+    plan
+}
+
 fn collect_displays() -> Vec<DisplayInfo> {
     let main_display = CGDisplay::main().id;
 
@@ -147,8 +276,9 @@ fn collect_displays() -> Vec<DisplayInfo> {
                     bounds.size.height.round() as i32,
                 );
 
-                let scale_factor = display
-                    .display_mode()
+                let mode = display.display_mode();
+                let scale_factor = mode
+                    .as_ref()
                     .map(|mode| {
                         if mode.width() == 0 {
                             1.0
@@ -157,12 +287,20 @@ fn collect_displays() -> Vec<DisplayInfo> {
                         }
                     })
                     .unwrap_or(1.0);
+                // Modes without an IOKit mode id are synthetic (e.g. AirPlay) and report no
+                // pixel encoding, so depth stays unknown rather than guessed.
+                let bit_depth = mode
+                    .as_ref()
+                    .filter(|mode| mode.mode_id() != 0)
+                    .and_then(|mode| channel_bit_depth(mode.bit_depth()));
 
                 DisplayInfo {
                     id: id as i32,
                     bounds: Bounds { x, y, w, h },
                     is_primary: id == main_display,
                     scale_factor,
+                    bit_depth,
+                    hdr: bit_depth.map(|bits| bits > 8),
                 }
             })
             .collect(),
@@ -170,9 +308,27 @@ fn collect_displays() -> Vec<DisplayInfo> {
     }
 }
 
-fn collect_windows(displays: &[DisplayInfo]) -> Vec<WindowInfo> {
-    let options =
-        window::kCGWindowListOptionOnScreenOnly | window::kCGWindowListExcludeDesktopElements;
+/// Maps CoreGraphics' bits-per-pixel for a mode's pixel encoding to bits per color channel.
+fn channel_bit_depth(bits_per_pixel: usize) -> Option<u32> {
+    match bits_per_pixel {
+        16 => Some(5),
+        32 => Some(8),
+        30 => Some(10),
+        48 | 64 => Some(16),
+        96 => Some(32),
+        _ => None,
+    }
+}
+
+/// Lists on-screen app windows front to back. With `include_desktop`, the
+/// wallpaper, Dock, menu bar, and other non-zero layers are kept too, each
+/// tagged with its CoreGraphics `layer`.
+fn collect_windows(displays: &[DisplayInfo], include_desktop: bool) -> Vec<WindowInfo> {
+    let options = if include_desktop {
+        window::kCGWindowListOptionOnScreenOnly
+    } else {
+        window::kCGWindowListOptionOnScreenOnly | window::kCGWindowListExcludeDesktopElements
+    };
     let windows = match window::create_window_list(options, window::kCGNullWindowID)
         .and_then(window::create_description_from_array)
     {
@@ -190,8 +346,8 @@ fn collect_windows(displays: &[DisplayInfo]) -> Vec<WindowInfo> {
     let mut output = Vec::new();
 
     for entry in windows.iter() {
-        let layer = dict_i64(&entry, &key_layer).unwrap_or(0);
-        if layer != 0 {
+        let layer = dict_i64(&entry, &key_layer).unwrap_or(0) as i32;
+        if layer != 0 && !include_desktop {
             continue;
         }
 
@@ -217,6 +373,13 @@ fn collect_windows(displays: &[DisplayInfo]) -> Vec<WindowInfo> {
             workspace,
             is_minimized: false,
             is_fullscreen,
+            is_special: false,
+            rss_mb: None,
+            cpu_pct: None,
+            // The window list is ordered front to back.
+            z_index: Some(output.len() as i32),
+            layer: include_desktop.then_some(layer),
+            focus_duration_ms: None,
         });
     }
 
@@ -267,6 +430,18 @@ fn idle_ms() -> Option<u64> {
     Some(nanos / 1_000_000)
 }
 
+fn platform_uuid() -> Option<String> {
+    let output = command_stdout("ioreg", &["-rd1", "-c", "IOPlatformExpertDevice"])?;
+    let marker = "\"IOPlatformUUID\" = ";
+    let line = output.lines().find(|line| line.contains(marker))?;
+    let value = line.split_once(marker)?.1.trim().trim_matches('"');
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
 fn user_groups() -> Vec<String> {
     command_stdout("id", &["-Gn"])
         .map(|x| {
@@ -277,6 +452,23 @@ fn user_groups() -> Vec<String> {
         .unwrap_or_default()
 }
 
+fn current_input_source() -> Option<String> {
+    let source = command_stdout(
+        "defaults",
+        &[
+            "read",
+            "com.apple.HIToolbox",
+            "AppleCurrentKeyboardLayoutInputSourceID",
+        ],
+    )?;
+    Some(
+        source
+            .strip_prefix("com.apple.keylayout.")
+            .unwrap_or(&source)
+            .to_string(),
+    )
+}
+
 fn default_gateway() -> Option<String> {
     let output = command_stdout("netstat", &["-nr"])?;
     output.lines().find_map(|line| {
@@ -295,6 +487,21 @@ fn default_gateway() -> Option<String> {
     })
 }
 
+fn primary_mac() -> Option<String> {
+    let route = command_stdout("route", &["-n", "get", "default"])?;
+    let interface = route
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("interface:"))?
+        .trim()
+        .to_string();
+
+    let ifconfig = command_stdout("ifconfig", &[&interface])?;
+    ifconfig
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("ether "))
+        .and_then(normalize_mac)
+}
+
 fn dns_servers() -> Vec<String> {
     let output = match command_stdout("scutil", &["--dns"]) {
         Some(output) => output,
@@ -316,40 +523,72 @@ fn dns_servers() -> Vec<String> {
     servers
 }
 
-fn gpu_info() -> Vec<GpuInfo> {
-    let output = match command_stdout("system_profiler", &["SPDisplaysDataType", "-json"]) {
-        Some(output) => output,
-        None => return Vec::new(),
-    };
+fn audio_info() -> Option<AudioInfo> {
+    let output = command_stdout("system_profiler", &["SPAudioDataType", "-json"])?;
+    let root: Value = serde_json::from_str(&output).ok()?;
+    let devices: Vec<&Value> = root
+        .get("SPAudioDataType")?
+        .as_array()?
+        .iter()
+        .filter_map(|group| group.get("_items").and_then(|items| items.as_array()))
+        .flatten()
+        .collect();
 
-    let root: Value = match serde_json::from_str(&output) {
-        Ok(root) => root,
-        Err(_) => return Vec::new(),
+    let default_device = |key: &str| {
+        devices
+            .iter()
+            .find(|device| device.get(key).and_then(|value| value.as_str()) == Some("spaudio_yes"))
+            .and_then(|device| device.get("_name").and_then(|value| value.as_str()))
+            .map(|name| name.to_string())
     };
 
-    let gpus = root
-        .get("SPDisplaysDataType")
-        .and_then(|value| value.as_array())
-        .cloned()
-        .unwrap_or_default();
-
-    gpus.into_iter()
-        .map(|gpu| {
-            let name = gpu
-                .get("sppci_model")
-                .and_then(|value| value.as_str())
-                .unwrap_or("unknown")
-                .to_string();
-
-            GpuInfo {
-                name,
-                vram_gb: None,
-                driver: "metal".to_string(),
-            }
+    // system_profiler does not say whether an input is capturing; that needs
+    // CoreAudio's kAudioDevicePropertyDeviceIsRunningSomewhere.
+    Some(AudioInfo {
+        default_output: default_device("coreaudio_default_audio_output_device"),
+        default_input: default_device("coreaudio_default_audio_input_device"),
+        mic_in_use: None,
+    })
+}
+
+/// `powermetrics` needs root; without it (or on Apple Silicon, which has no
+/// `smc` sampler) the list stays empty.
+fn smc_sensors() -> Vec<SensorReading> {
+    command_stdout(
+        "powermetrics",
+        &["--samplers", "smc", "-n", "1", "-i", "200"],
+    )
+    .map(|raw| parse_powermetrics_smc(&raw))
+    .unwrap_or_default()
+}
+
+fn parse_powermetrics_smc(raw: &str) -> Vec<SensorReading> {
+    raw.lines()
+        .filter_map(|line| {
+            let (label, value) = line.split_once(':')?;
+            let mut parts = value.split_whitespace();
+            let value: f64 = parts.next()?.parse().ok()?;
+            let kind = match parts.next()? {
+                "C" => SensorKind::TemperatureC,
+                "rpm" => SensorKind::FanRpm,
+                _ => return None,
+            };
+            Some(SensorReading {
+                chip: "smc".to_string(),
+                label: label.trim().to_string(),
+                kind,
+                value,
+            })
         })
         .collect()
 }
 
+fn gpu_info(unified_memory_gb: Option<f64>) -> Vec<GpuInfo> {
+    command_stdout("system_profiler", &["SPDisplaysDataType", "-json"])
+        .map(|output| parse_displays_data(&output, unified_memory_gb))
+        .unwrap_or_default()
+}
+
 fn uptime_seconds_from_boottime(now_ts: f64) -> Option<u64> {
     let output = command_stdout("sysctl", &["-n", "kern.boottime"])?;
     let sec_fragment = output.split("sec = ").nth(1)?;
@@ -363,24 +602,16 @@ fn uptime_seconds_from_boottime(now_ts: f64) -> Option<u64> {
     Some(now.saturating_sub(boot_sec))
 }
 
-fn running_since_boot(now_ts: u64) -> Vec<RunningProcessInfo> {
-    let mut system = System::new_all();
-    system.refresh_all();
-
-    let boot_time = System::boot_time();
-    if boot_time == 0 {
+fn running_since_boot(system: &System, clock: &ProcessClock) -> Vec<RunningProcessInfo> {
+    if System::boot_time() == 0 {
         return Vec::new();
     }
 
     let mut processes: Vec<RunningProcessInfo> = system
         .processes()
         .values()
-        .filter(|process| process.start_time() <= boot_time.saturating_add(120))
-        .map(|process| RunningProcessInfo {
-            pid: process.pid().as_u32(),
-            app: process.name().to_string_lossy().to_string(),
-            started_ago_s: now_ts.saturating_sub(process.start_time()),
-        })
+        .filter(|process| clock.since_boot(process) <= 120.0)
+        .map(|process| clock.info(process))
         .collect();
 
     processes.sort_by_key(|process| process.started_ago_s);
@@ -390,61 +621,19 @@ fn running_since_boot(now_ts: u64) -> Vec<RunningProcessInfo> {
 }
 
 fn other_sessions() -> Vec<SessionInfo> {
-    let output = match command_stdout("who", &[]) {
-        Some(output) => output,
-        None => return Vec::new(),
-    };
-
-    output
-        .lines()
-        .filter_map(parse_who_line)
-        .collect::<Vec<SessionInfo>>()
-}
-
-fn parse_who_line(line: &str) -> Option<SessionInfo> {
-    let cols: Vec<&str> = line.split_whitespace().collect();
-    if cols.len() < 5 {
-        return None;
+    if !binary_in_path("who") {
+        status::report_missing("who");
+        return Vec::new();
     }
-
-    let username = cols[0].to_string();
-    let tty = cols[1].to_string();
-    let month = cols[2];
-    let day = cols[3];
-    let time = cols[4];
-
-    let mut year = Local::now().year();
-    let parse_with_year = |year: i32| -> Option<NaiveDateTime> {
-        NaiveDateTime::parse_from_str(&format!("{month} {day} {time} {year}"), "%b %e %H:%M %Y")
-            .ok()
+    let Some(output) = command_stdout("who", &["-u"]) else {
+        return Vec::new();
     };
 
-    let mut naive = parse_with_year(year)?;
     let now = Local::now().naive_local();
-    if naive > now {
-        year -= 1;
-        naive = parse_with_year(year)?;
-    }
-
-    let login_ts = Local
-        .from_local_datetime(&naive)
-        .earliest()
-        .map(|dt| dt.timestamp() as f64)
-        .unwrap_or(0.0);
-
-    let from = line
-        .split('(')
-        .nth(1)
-        .and_then(|x| x.split(')').next())
-        .map(|x| x.to_string())
-        .unwrap_or_else(|| "local".to_string());
-
-    Some(SessionInfo {
-        username,
-        tty,
-        from,
-        login_ts,
-    })
+    output
+        .lines()
+        .filter_map(|line| parse_who_line(line, now))
+        .collect()
 }
 
 fn command_stdout(bin: &str, args: &[&str]) -> Option<String> {
@@ -453,8 +642,7 @@ fn command_stdout(bin: &str, args: &[&str]) -> Option<String> {
         return None;
     }
 
-    let value = String::from_utf8(output.stdout).ok()?;
-    let value = value.trim().to_string();
+    let value = lossy_text(output.stdout).trim().to_string();
 
     if value.is_empty() { None } else { Some(value) }
 }
@@ -0,0 +1,84 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+
+use crate::observation::{Observation, WakeObservation};
+use crate::observer::{Observer, Waker};
+
+/// Replays observations from a JSON fixture instead of probing the host. The
+/// fixture is one `Observation` or an array of them; each snapshot returns the
+/// next entry and the last one repeats.
+pub struct MockObserver {
+    observations: Vec<Observation>,
+    next: usize,
+    last_monotonic_ms: Option<u64>,
+}
+
+impl MockObserver {
+    pub fn load(path: &Path) -> Result<Self> {
+        let value: serde_json::Value = read_fixture(path)?;
+        let observations = if value.is_array() {
+            serde_json::from_value(value)
+        } else {
+            serde_json::from_value(value).map(|observation| vec![observation])
+        }
+        .with_context(|| format!("{} is not an observation fixture", path.display()))?;
+
+        Ok(Self::new(observations))
+    }
+
+    pub fn new(observations: Vec<Observation>) -> Self {
+        Self {
+            observations,
+            next: 0,
+            last_monotonic_ms: None,
+        }
+    }
+}
+
+impl Observer for MockObserver {
+    fn snapshot(&mut self) -> Result<Observation> {
+        let index = self.next.min(self.observations.len().saturating_sub(1));
+        let mut observation = self
+            .observations
+            .get(index)
+            .cloned()
+            .context("observation fixture is empty")?;
+        self.next += 1;
+
+        // Diffing requires strictly increasing clocks even when a fixture
+        // entry repeats.
+        if let Some(last) = self.last_monotonic_ms {
+            observation.monotonic_ms = observation.monotonic_ms.max(last + 1);
+        }
+        self.last_monotonic_ms = Some(observation.monotonic_ms);
+        Ok(observation)
+    }
+}
+
+pub struct MockWaker {
+    wake: WakeObservation,
+}
+
+impl MockWaker {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self::new(read_fixture(path)?))
+    }
+
+    pub fn new(wake: WakeObservation) -> Self {
+        Self { wake }
+    }
+}
+
+impl Waker for MockWaker {
+    fn wake(&self) -> Result<WakeObservation> {
+        Ok(self.wake.clone())
+    }
+}
+
+fn read_fixture<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let contents = fs::read(path).with_context(|| format!("reading fixture {}", path.display()))?;
+    serde_json::from_slice(&contents).with_context(|| format!("parsing fixture {}", path.display()))
+}
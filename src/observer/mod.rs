@@ -1,30 +1,311 @@
+use std::net::IpAddr;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use globset::Glob;
 
 use crate::observation::{Observation, WakeObservation};
+use crate::observer::capabilities::Capabilities;
+use crate::observer::catalog::{CatalogEntry, builtin_catalog};
+use crate::observer::plan::Plan;
+use crate::util::deadline::Deadline;
+use crate::util::net::is_local_scope;
+use crate::util::process::DEFAULT_COMMAND_TIMEOUT;
 
+pub mod builder;
+pub mod capabilities;
+pub mod catalog;
 pub mod common;
 #[cfg(target_os = "linux")]
 pub mod linux;
 #[cfg(target_os = "macos")]
 pub mod macos;
+pub mod mock;
+pub mod plan;
 #[cfg(target_os = "windows")]
 pub mod windows;
+#[cfg(target_os = "linux")]
+pub mod wlr;
+
+pub const DEFAULT_EXCLUDE_PATHS: [&str; 10] = [
+    "Library",
+    ".cache",
+    ".cargo",
+    ".rustup",
+    ".npm",
+    ".local/share/Trash",
+    "Dropbox",
+    "OneDrive*",
+    "**/node_modules",
+    "**/.git",
+];
+
+pub const DEFAULT_ENV_VARS: [&str; 8] = [
+    "PATH",
+    "SHELL",
+    "TERM",
+    "TERM_PROGRAM",
+    "LANG",
+    "LC_ALL",
+    "EDITOR",
+    "VISUAL",
+];
+
+pub const DEFAULT_PUBLIC_IP_TIMEOUT: Duration = Duration::from_millis(500);
+pub const DEFAULT_PUBLIC_IP_RETRIES: u32 = 2;
+pub const DEFAULT_MAX_FS_EVENTS: usize = 500;
 
 #[derive(Debug, Clone)]
 pub struct ObserverConfig {
     pub watch_path: Option<PathBuf>,
-    pub all_connections: bool,
+    pub include_loopback: bool,
+    pub conn_states: Vec<String>,
+    pub no_network: bool,
+    pub no_windows: bool,
+    pub no_connections: bool,
+    pub clipboard: bool,
+    pub window_resources: bool,
+    pub include_desktop: bool,
+    pub max_fs_events: usize,
+    pub redact_titles: Option<TitleRedaction>,
+    pub focus_only: bool,
+    pub deadline: Deadline,
+}
+
+impl Default for ObserverConfig {
+    fn default() -> Self {
+        Self {
+            watch_path: None,
+            include_loopback: false,
+            conn_states: Vec::new(),
+            no_network: false,
+            no_windows: false,
+            no_connections: false,
+            clipboard: false,
+            window_resources: false,
+            include_desktop: false,
+            max_fs_events: DEFAULT_MAX_FS_EVENTS,
+            redact_titles: None,
+            focus_only: false,
+            deadline: Deadline::default(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct WakeConfig {
     pub no_public_ip: bool,
+    pub no_network: bool,
+    pub public_ip_timeout: Duration,
+    pub public_ip_retries: u32,
+    pub proxy: Option<String>,
+    pub public_ip_provider: Option<String>,
+    pub top_processes: usize,
+    pub sort_by: ProcessSort,
+    pub collectors: Vec<ExternalCollector>,
+    pub command_timeout: Duration,
+    pub exclude_paths: Vec<Glob>,
+    pub disk_health: bool,
+    pub all_mounts: bool,
+    pub only: Vec<WakeSection>,
+    pub env: bool,
+    pub env_vars: Vec<String>,
+    pub sensors: bool,
+    pub include_hidden: bool,
+    pub git: bool,
+    pub app_catalog: Vec<CatalogEntry>,
+    pub ip_filter: IpFilter,
+    pub deadline: Deadline,
+}
+
+impl Default for WakeConfig {
+    fn default() -> Self {
+        Self {
+            no_public_ip: false,
+            no_network: false,
+            public_ip_timeout: DEFAULT_PUBLIC_IP_TIMEOUT,
+            public_ip_retries: DEFAULT_PUBLIC_IP_RETRIES,
+            proxy: None,
+            public_ip_provider: None,
+            top_processes: 0,
+            sort_by: ProcessSort::default(),
+            collectors: Vec::new(),
+            command_timeout: DEFAULT_COMMAND_TIMEOUT,
+            exclude_paths: Vec::new(),
+            disk_health: false,
+            all_mounts: false,
+            only: Vec::new(),
+            env: false,
+            env_vars: Vec::new(),
+            sensors: false,
+            include_hidden: false,
+            git: false,
+            app_catalog: builtin_catalog(),
+            ip_filter: IpFilter::All,
+            deadline: Deadline::default(),
+        }
+    }
+}
+
+impl WakeConfig {
+    pub fn collects(&self, section: WakeSection) -> bool {
+        self.only.is_empty() || self.only.contains(&section)
+    }
+
+    /// JSON Pointers of the wake fields this config turned off: sections
+    /// outside `only`, `public_ip` under `no_public_ip` or `no_network`, and
+    /// opt-in fields whose flag is unset. `--merge-previous` leaves them empty
+    /// rather than reviving them from the older payload.
+    pub fn merge_skip_paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = WakeSection::value_variants()
+            .iter()
+            .filter(|section| !self.collects(**section))
+            .map(|section| section.pointer().to_string())
+            .collect();
+        let off = [
+            (
+                self.no_public_ip || self.no_network,
+                "/network_identity/public_ip",
+            ),
+            (!self.sensors, "/resources/sensors"),
+            (!self.env, "/env"),
+            (!self.git, "/filesystem/git_repos"),
+        ];
+        paths.extend(
+            off.into_iter()
+                .filter(|(off, _)| *off)
+                .map(|(_, path)| path.to_string()),
+        );
+        paths
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum WakeSection {
+    Machine,
+    User,
+    Datetime,
+    Filesystem,
+    #[value(alias = "installed_apps")]
+    Apps,
+    #[value(alias = "network_identity")]
+    Network,
+    #[value(alias = "listening_ports")]
+    Ports,
+    Resources,
+    Audio,
+    #[value(alias = "recent_activity")]
+    Activity,
+    #[value(alias = "other_sessions")]
+    Sessions,
+    #[value(alias = "dev_context")]
+    Dev,
+}
+
+impl WakeSection {
+    /// Where the section lives in a `WakeObservation`, as a JSON Pointer.
+    pub fn pointer(self) -> &'static str {
+        match self {
+            Self::Machine => "/machine",
+            Self::User => "/user",
+            Self::Datetime => "/datetime",
+            Self::Filesystem => "/filesystem",
+            Self::Apps => "/installed_apps",
+            Self::Network => "/network_identity",
+            Self::Ports => "/listening_ports",
+            Self::Resources => "/resources",
+            Self::Audio => "/audio",
+            Self::Activity => "/recent_activity",
+            Self::Sessions => "/other_sessions",
+            Self::Dev => "/dev_context",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalCollector {
+    pub name: String,
+    pub command: String,
+}
+
+impl FromStr for ExternalCollector {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (name, command) = value
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected <name>=<command>, got {value:?}"))?;
+        let name = name.trim();
+        let command = command.trim();
+
+        if name.is_empty() || command.is_empty() {
+            return Err(anyhow!("expected <name>=<command>, got {value:?}"));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            command: command.to_string(),
+        })
+    }
+}
+
+pub fn parse_exclude_path(value: &str) -> Result<Glob> {
+    let pattern = value.strip_prefix("~/").unwrap_or(value);
+    Ok(Glob::new(pattern)?)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ProcessSort {
+    #[default]
+    Cpu,
+    Mem,
+}
+
+/// Which addresses `--ip-filter` keeps in wake's `local_ips`. `Global`
+/// drops link-local and IPv6 unique-local addresses (private IPv4 ranges
+/// stay, since they are usually the LAN address).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum IpFilter {
+    #[default]
+    All,
+    Global,
+    V4,
+    V6,
+}
+
+impl IpFilter {
+    pub fn admits(self, ip: IpAddr) -> bool {
+        match self {
+            IpFilter::All => true,
+            IpFilter::Global => !is_local_scope(ip),
+            IpFilter::V4 => ip.is_ipv4(),
+            IpFilter::V6 => ip.is_ipv6(),
+        }
+    }
+}
+
+/// What `--redact-titles` puts in place of each window title: a short
+/// SHA-256 prefix that still changes when the title does, or a coarse
+/// category (`web`, `mail`, `file`, `other`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TitleRedaction {
+    Hash,
+    Category,
 }
 
 pub trait Observer {
     fn snapshot(&mut self) -> Result<Observation>;
+
+    /// Blocks for up to `timeout` waiting for filesystem activity and reports
+    /// whether any arrived; the events themselves surface in the next
+    /// snapshot. Observers without a watcher sleep out the timeout.
+    fn wait_for_event(&mut self, timeout: Duration) -> bool {
+        std::thread::sleep(timeout);
+        false
+    }
 }
 
 pub trait Waker {
@@ -70,3 +351,75 @@ pub fn create_waker(config: WakeConfig) -> Box<dyn Waker> {
 pub fn create_waker(config: WakeConfig) -> Box<dyn Waker> {
     Box::new(common::BaselineWaker::new(config))
 }
+
+#[cfg(target_os = "macos")]
+pub fn explain_wake(config: &WakeConfig) -> Plan {
+    Plan::new(
+        "wake",
+        common::scope_wake_plan(config, macos::wake_plan(config)),
+    )
+}
+
+#[cfg(target_os = "linux")]
+pub fn explain_wake(config: &WakeConfig) -> Plan {
+    Plan::new(
+        "wake",
+        common::scope_wake_plan(config, linux::wake_plan(config)),
+    )
+}
+
+#[cfg(target_os = "windows")]
+pub fn explain_wake(config: &WakeConfig) -> Plan {
+    Plan::new(
+        "wake",
+        common::scope_wake_plan(config, windows::wake_plan(config)),
+    )
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+pub fn explain_wake(config: &WakeConfig) -> Plan {
+    Plan::new(
+        "wake",
+        common::scope_wake_plan(config, common::wake_plan(config)),
+    )
+}
+
+#[cfg(target_os = "macos")]
+pub fn explain_snapshot(config: &ObserverConfig) -> Plan {
+    Plan::new("snapshot", macos::snapshot_plan(config))
+}
+
+#[cfg(target_os = "linux")]
+pub fn explain_snapshot(config: &ObserverConfig) -> Plan {
+    Plan::new("snapshot", linux::snapshot_plan(config))
+}
+
+#[cfg(target_os = "windows")]
+pub fn explain_snapshot(config: &ObserverConfig) -> Plan {
+    Plan::new("snapshot", windows::snapshot_plan(config))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+pub fn explain_snapshot(config: &ObserverConfig) -> Plan {
+    Plan::new("snapshot", common::snapshot_plan(config))
+}
+
+#[cfg(target_os = "macos")]
+pub fn capabilities(observer: &ObserverConfig, wake: &WakeConfig) -> Capabilities {
+    macos::capabilities(observer, wake)
+}
+
+#[cfg(target_os = "linux")]
+pub fn capabilities(observer: &ObserverConfig, wake: &WakeConfig) -> Capabilities {
+    linux::capabilities(observer, wake)
+}
+
+#[cfg(target_os = "windows")]
+pub fn capabilities(observer: &ObserverConfig, wake: &WakeConfig) -> Capabilities {
+    windows::capabilities(observer, wake)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+pub fn capabilities(observer: &ObserverConfig, wake: &WakeConfig) -> Capabilities {
+    common::capabilities(observer, wake)
+}
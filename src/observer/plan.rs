@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Plan {
+    pub command: String,
+    pub collectors: Vec<CollectorPlan>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CollectorPlan {
+    pub name: String,
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub endpoints: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reads: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+}
+
+impl Plan {
+    pub fn new(command: impl Into<String>, collectors: Vec<CollectorPlan>) -> Self {
+        Self {
+            command: command.into(),
+            collectors,
+        }
+    }
+}
+
+impl CollectorPlan {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            enabled: true,
+            reason: None,
+            commands: Vec::new(),
+            endpoints: Vec::new(),
+            reads: Vec::new(),
+            timeout_ms: None,
+        }
+    }
+
+    pub fn command(mut self, command: impl Into<String>) -> Self {
+        self.commands.push(command.into());
+        self
+    }
+
+    pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoints.push(endpoint.into());
+        self
+    }
+
+    pub fn reads(mut self, path: impl Into<String>) -> Self {
+        self.reads.push(path.into());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout_ms = Some(timeout.as_millis() as u64);
+        self
+    }
+
+    pub fn enabled_if(mut self, enabled: bool, reason: impl Into<String>) -> Self {
+        if self.enabled && !enabled {
+            self.enabled = false;
+            self.reason = Some(reason.into());
+        }
+        self
+    }
+}
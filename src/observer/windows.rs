@@ -1,10 +1,394 @@
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::Result;
+use windows_sys::Win32::Foundation::{LPARAM, RECT};
+use windows_sys::Win32::Graphics::Gdi::{
+    EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFO, MONITORINFOF_PRIMARY,
+};
+use windows_sys::Win32::System::RemoteDesktop::{
+    WTS_CURRENT_SERVER_HANDLE, WTS_INFO_CLASS, WTS_SESSION_INFOW, WTSClientName,
+    WTSEnumerateSessionsW, WTSFreeMemory, WTSINFOW, WTSQuerySessionInformationW, WTSSessionInfo,
+};
+use windows_sys::Win32::System::SystemInformation::GetTickCount64;
+use windows_sys::Win32::UI::HiDpi::{
+    DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, GetDpiForMonitor, MDT_EFFECTIVE_DPI,
+    SetThreadDpiAwarenessContext,
+};
+use windows_sys::core::BOOL;
+
+use crate::observation::{
+    Bounds, DisplayInfo, Observation, SensorKind, SensorReading, SessionInfo, WakeObservation,
+};
+use crate::observer::capabilities::Capabilities;
 use crate::observer::common::{BaselineObserver, BaselineWaker};
-use crate::observer::{Observer, ObserverConfig, WakeConfig, Waker};
+use crate::observer::plan::CollectorPlan;
+use crate::observer::{Observer, ObserverConfig, WakeConfig, WakeSection, Waker, common};
+use crate::util::deadline::Budget;
+use crate::util::net::normalize_mac;
+use crate::util::process::run_with_timeout;
 
 pub fn create_observer(config: ObserverConfig) -> Box<dyn Observer> {
-    Box::new(BaselineObserver::new(config))
+    Box::new(WindowsObserver {
+        baseline: BaselineObserver::new(config),
+    })
 }
 
 pub fn create_waker(config: WakeConfig) -> Box<dyn Waker> {
-    Box::new(BaselineWaker::new(config))
+    Box::new(WindowsWaker {
+        baseline: BaselineWaker::new(config),
+    })
+}
+
+struct WindowsObserver {
+    baseline: BaselineObserver,
+}
+
+impl Observer for WindowsObserver {
+    fn wait_for_event(&mut self, timeout: Duration) -> bool {
+        self.baseline.wait_for_event(timeout)
+    }
+
+    fn snapshot(&mut self) -> Result<Observation> {
+        let mut observation = self.baseline.collect()?;
+        let budget = Budget::new(self.baseline.deadline());
+
+        if !self.baseline.focus_only() {
+            let displays = budget.run(collect_displays);
+            if !displays.is_empty() {
+                observation.displays = displays;
+            }
+        }
+
+        observation.truncated |= budget.truncated();
+        self.baseline.finish(&mut observation);
+        Ok(observation)
+    }
+}
+
+struct WindowsWaker {
+    baseline: BaselineWaker,
+}
+
+impl Waker for WindowsWaker {
+    fn wake(&self) -> Result<WakeObservation> {
+        let mut wake = self.baseline.wake()?;
+        let budget = Budget::new(self.baseline.deadline());
+        let config = self.baseline.config();
+        let timeout = || config.deadline.cap(config.command_timeout);
+
+        if self.baseline.collects(WakeSection::Machine)
+            && let Some(machine_id) = budget.run(|| machine_guid(timeout()))
+        {
+            wake.machine.machine_id = machine_id;
+        }
+        if self.baseline.collects(WakeSection::Network) {
+            wake.network_identity.primary_mac = budget.run(|| primary_mac(timeout()));
+        }
+        if self.baseline.collects(WakeSection::Resources) && config.sensors {
+            wake.resources.sensors = Some(budget.run(|| thermal_zones(timeout())));
+        }
+        if self.baseline.collects(WakeSection::Datetime) {
+            let uptime_seconds = uptime_seconds();
+            wake.datetime.uptime_seconds = uptime_seconds;
+            wake.datetime.login_ts = wake.ts - uptime_seconds as f64;
+        }
+        if self.baseline.collects(WakeSection::Sessions) {
+            let sessions = budget.run(other_sessions);
+            if !sessions.is_empty() {
+                wake.datetime.login_ts = sessions
+                    .iter()
+                    .map(|x| x.login_ts)
+                    .fold(wake.datetime.login_ts, f64::min);
+                wake.other_sessions = sessions;
+            }
+        }
+        wake.truncated |= budget.truncated();
+        self.baseline.finish(&mut wake);
+
+        Ok(wake)
+    }
+}
+
+pub fn wake_plan(config: &WakeConfig) -> Vec<CollectorPlan> {
+    let mut plan = common::wake_plan(config);
+    plan.extend([
+        CollectorPlan::new("machine_id")
+            .command(r"reg query HKLM\SOFTWARE\Microsoft\Cryptography /v MachineGuid")
+            .timeout(config.command_timeout),
+        CollectorPlan::new("sensors")
+            .command(
+                "powershell -NoProfile -NonInteractive -Command Get-CimInstance -Namespace root/wmi -ClassName MSAcpi_ThermalZoneTemperature",
+            )
+            .timeout(config.command_timeout)
+            .enabled_if(config.sensors, "--sensors not set"),
+        CollectorPlan::new("primary_mac")
+            .command(
+                "powershell -NoProfile -NonInteractive -Command (Get-NetRoute ... | Get-NetAdapter).MacAddress",
+            )
+            .timeout(config.command_timeout),
+        CollectorPlan::new("uptime").reads("GetTickCount64"),
+        CollectorPlan::new("other_sessions").reads("WTSEnumerateSessions session list"),
+    ]);
+    plan
+}
+
+/// Monitor enumeration, WTS sessions, and WMI thermal zones (usually
+/// elevated only) on top of the baseline probes.
+pub fn capabilities(observer: &ObserverConfig, wake: &WakeConfig) -> Capabilities {
+    let mut capabilities = common::capabilities(observer, wake);
+    capabilities.displays = !collect_displays().is_empty();
+    capabilities.sessions = !other_sessions().is_empty();
+    capabilities.sensors = !thermal_zones(wake.deadline.cap(wake.command_timeout)).is_empty();
+    capabilities
+}
+
+pub fn snapshot_plan(config: &ObserverConfig) -> Vec<CollectorPlan> {
+    let mut plan = common::snapshot_plan(config);
+    plan.push(CollectorPlan::new("displays").reads("EnumDisplayMonitors monitor list"));
+    plan
+}
+
+/// Monitors in `EnumDisplayMonitors` order, numbered from zero. Bounds are in
+/// virtual-screen pixels, so the thread is made per-monitor DPI aware for the
+/// duration; otherwise Windows reports scaled coordinates and a flat 96 DPI.
+fn collect_displays() -> Vec<DisplayInfo> {
+    let mut monitors: Vec<HMONITOR> = Vec::new();
+    unsafe {
+        let previous = SetThreadDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
+        EnumDisplayMonitors(
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            Some(push_monitor),
+            &mut monitors as *mut Vec<HMONITOR> as LPARAM,
+        );
+        let displays = monitors
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &monitor)| monitor_display(index as i32, monitor))
+            .collect();
+        if !previous.is_null() {
+            SetThreadDpiAwarenessContext(previous);
+        }
+        displays
+    }
+}
+
+unsafe extern "system" fn push_monitor(
+    monitor: HMONITOR,
+    _hdc: HDC,
+    _clip: *mut RECT,
+    data: LPARAM,
+) -> BOOL {
+    let monitors = unsafe { &mut *(data as *mut Vec<HMONITOR>) };
+    monitors.push(monitor);
+    1
+}
+
+unsafe fn monitor_display(id: i32, monitor: HMONITOR) -> Option<DisplayInfo> {
+    let mut info: MONITORINFO = unsafe { std::mem::zeroed() };
+    info.cbSize = std::mem::size_of::<MONITORINFO>() as u32;
+    if unsafe { GetMonitorInfoW(monitor, &mut info) } == 0 {
+        return None;
+    }
+
+    let (mut dpi_x, mut dpi_y) = (0u32, 0u32);
+    let scale_factor =
+        if unsafe { GetDpiForMonitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y) } == 0
+            && dpi_x > 0
+        {
+            dpi_x as f64 / 96.0
+        } else {
+            1.0
+        };
+
+    let rect = info.rcMonitor;
+    Some(DisplayInfo {
+        id,
+        bounds: Bounds {
+            x: rect.left,
+            y: rect.top,
+            w: rect.right - rect.left,
+            h: rect.bottom - rect.top,
+        },
+        is_primary: info.dwFlags & MONITORINFOF_PRIMARY != 0,
+        scale_factor,
+        bit_depth: None,
+        hdr: None,
+    })
+}
+
+fn machine_guid(timeout: Duration) -> Option<String> {
+    let output = run_with_timeout(
+        Command::new("reg").args([
+            "query",
+            r"HKLM\SOFTWARE\Microsoft\Cryptography",
+            "/v",
+            "MachineGuid",
+        ]),
+        timeout,
+    )
+    .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.lines()
+        .find(|line| line.contains("MachineGuid"))
+        .and_then(|line| line.split_whitespace().last())
+        .map(|value| value.to_string())
+}
+
+/// ACPI thermal zones from WMI, reported in tenths of a kelvin. The class
+/// usually needs an elevated shell; otherwise the list stays empty.
+fn thermal_zones(timeout: Duration) -> Vec<SensorReading> {
+    let Ok(output) = run_with_timeout(
+        Command::new("powershell").args([
+            "-NoProfile",
+            "-NonInteractive",
+            "-Command",
+            "Get-CimInstance -Namespace root/wmi -ClassName MSAcpi_ThermalZoneTemperature | \
+             ForEach-Object { \"$($_.InstanceName)`t$($_.CurrentTemperature)\" }",
+        ]),
+        timeout,
+    ) else {
+        return Vec::new();
+    };
+    if !output.status.success() {
+        return Vec::new();
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (label, tenths_kelvin) = line.trim().split_once('\t')?;
+            let tenths_kelvin: f64 = tenths_kelvin.trim().parse().ok()?;
+            Some(SensorReading {
+                chip: "acpi".to_string(),
+                label: label.to_string(),
+                kind: SensorKind::TemperatureC,
+                value: tenths_kelvin / 10.0 - 273.15,
+            })
+        })
+        .collect()
+}
+
+fn primary_mac(timeout: Duration) -> Option<String> {
+    let output = run_with_timeout(
+        Command::new("powershell").args([
+            "-NoProfile",
+            "-NonInteractive",
+            "-Command",
+            "(Get-NetRoute -DestinationPrefix '0.0.0.0/0' | Sort-Object RouteMetric | \
+             Select-Object -First 1 | Get-NetAdapter).MacAddress",
+        ]),
+        timeout,
+    )
+    .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    normalize_mac(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Milliseconds since boot from `GetTickCount64`, which (unlike the 32-bit
+/// tick count) does not wrap after 49.7 days.
+fn uptime_seconds() -> u64 {
+    unsafe { GetTickCount64() / 1000 }
+}
+
+/// Seconds between 1601-01-01 (the `FILETIME` epoch) and the Unix epoch.
+const FILETIME_UNIX_OFFSET_SECS: i64 = 11_644_473_600;
+
+/// Terminal Services sessions with a signed-in user: the console, RDP
+/// sessions, and disconnected-but-alive sessions. Session 0 and idle listener
+/// stations carry no user name and are skipped.
+fn other_sessions() -> Vec<SessionInfo> {
+    let mut list: *mut WTS_SESSION_INFOW = std::ptr::null_mut();
+    let mut count = 0u32;
+    unsafe {
+        if WTSEnumerateSessionsW(WTS_CURRENT_SERVER_HANDLE, 0, 1, &mut list, &mut count) == 0 {
+            return Vec::new();
+        }
+        let sessions = std::slice::from_raw_parts(list, count as usize)
+            .iter()
+            .filter_map(|session| session_info(session.SessionId))
+            .collect();
+        WTSFreeMemory(list.cast());
+        sessions
+    }
+}
+
+unsafe fn session_info(session_id: u32) -> Option<SessionInfo> {
+    let info = unsafe {
+        query_session(session_id, WTSSessionInfo, |buffer, bytes| {
+            (bytes >= std::mem::size_of::<WTSINFOW>())
+                .then(|| std::ptr::read_unaligned(buffer.cast::<WTSINFOW>()))
+        })
+    }?;
+    let username = wide_to_string(&info.UserName);
+    if username.is_empty() {
+        return None;
+    }
+    // Only remote sessions report a client machine name.
+    let from = unsafe {
+        query_session(session_id, WTSClientName, |buffer, bytes| {
+            let len = bytes / std::mem::size_of::<u16>();
+            Some(wide_to_string(std::slice::from_raw_parts(buffer, len)))
+        })
+    }
+    .filter(|name| !name.is_empty())
+    .unwrap_or_else(|| "local".to_string());
+    let login_ts = if info.LogonTime > 0 {
+        (info.LogonTime / 10_000_000 - FILETIME_UNIX_OFFSET_SECS) as f64
+    } else {
+        0.0
+    };
+
+    Some(SessionInfo {
+        username,
+        tty: wide_to_string(&info.WinStationName),
+        from,
+        login_ts,
+        idle: None,
+        pid: None,
+    })
+}
+
+/// Runs `read` over the WTS-allocated buffer one `WTSQuerySessionInformationW`
+/// call returns (pointer and size in bytes), then frees it.
+unsafe fn query_session<T>(
+    session_id: u32,
+    class: WTS_INFO_CLASS,
+    read: impl FnOnce(*const u16, usize) -> Option<T>,
+) -> Option<T> {
+    let mut buffer: *mut u16 = std::ptr::null_mut();
+    let mut bytes = 0u32;
+    unsafe {
+        if WTSQuerySessionInformationW(
+            WTS_CURRENT_SERVER_HANDLE,
+            session_id,
+            class,
+            &mut buffer,
+            &mut bytes,
+        ) == 0
+            || buffer.is_null()
+        {
+            return None;
+        }
+        let value = read(buffer, bytes as usize);
+        WTSFreeMemory(buffer.cast());
+        value
+    }
+}
+
+/// Decodes a UTF-16 buffer up to its first NUL.
+fn wide_to_string(wide: &[u16]) -> String {
+    let len = wide
+        .iter()
+        .position(|&unit| unit == 0)
+        .unwrap_or(wide.len());
+    String::from_utf16_lossy(&wide[..len])
 }
@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::env;
+
+use wayland_client::globals::{GlobalListContents, registry_queue_init};
+use wayland_client::protocol::wl_registry::{self, WlRegistry};
+use wayland_client::{Connection, Dispatch, EventQueue, Proxy, QueueHandle, event_created_child};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_handle_v1::{
+    self, ZwlrForeignToplevelHandleV1,
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_manager_v1::{
+    self, ZwlrForeignToplevelManagerV1,
+};
+
+use crate::observation::{Bounds, WindowInfo};
+
+const MANAGER_VERSION: u32 = 3;
+
+const STATE_MINIMIZED: u32 = 1;
+const STATE_ACTIVATED: u32 = 2;
+const STATE_FULLSCREEN: u32 = 3;
+
+#[derive(Debug, Default)]
+pub struct Toplevels {
+    pub windows: Vec<WindowInfo>,
+    pub focus: Option<WindowInfo>,
+}
+
+#[derive(Debug, Default)]
+struct Toplevel {
+    seq: u64,
+    title: Option<String>,
+    app_id: Option<String>,
+    states: Vec<u32>,
+    closed: bool,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    toplevels: BTreeMap<u32, Toplevel>,
+    next_seq: u64,
+}
+
+/// A foreign-toplevel manager bound on a connection that outlives one
+/// snapshot, so each window keeps its handle (and its id) while it is open.
+pub struct Session {
+    connection: Connection,
+    queue: EventQueue<State>,
+    state: State,
+    manager: ZwlrForeignToplevelManagerV1,
+    primed: bool,
+}
+
+impl Session {
+    pub fn connect() -> Option<Self> {
+        env::var_os("WAYLAND_DISPLAY")?;
+
+        let connection = Connection::connect_to_env().ok()?;
+        let (globals, queue) = registry_queue_init::<State>(&connection).ok()?;
+        let manager: ZwlrForeignToplevelManagerV1 = globals
+            .bind(&queue.handle(), 1..=MANAGER_VERSION, ())
+            .ok()?;
+
+        Some(Self {
+            connection,
+            queue,
+            state: State::default(),
+            manager,
+            primed: false,
+        })
+    }
+
+    /// Applies every event the compositor has sent since the last call and
+    /// lists the open toplevels. The first call's first roundtrip announces
+    /// every toplevel and its second delivers the title/app_id/state events
+    /// each new handle sends before its `done`; later calls only need one.
+    pub fn toplevels(&mut self) -> Option<Toplevels> {
+        if !self.primed {
+            self.queue.roundtrip(&mut self.state).ok()?;
+            self.primed = true;
+        }
+        self.queue.roundtrip(&mut self.state).ok()?;
+        self.state.toplevels.retain(|_, toplevel| !toplevel.closed);
+
+        let mut output = Toplevels::default();
+        for toplevel in self.state.toplevels.values() {
+            let window = WindowInfo {
+                id: format!("wlr-{}", toplevel.seq),
+                title: toplevel
+                    .title
+                    .clone()
+                    .unwrap_or_else(|| "unknown".to_string()),
+                app: toplevel
+                    .app_id
+                    .clone()
+                    .unwrap_or_else(|| "unknown".to_string()),
+                pid: 0,
+                bounds: Bounds {
+                    x: 0,
+                    y: 0,
+                    w: 0,
+                    h: 0,
+                },
+                workspace: 0,
+                is_minimized: toplevel.states.contains(&STATE_MINIMIZED),
+                is_fullscreen: toplevel.states.contains(&STATE_FULLSCREEN),
+                is_special: false,
+                rss_mb: None,
+                cpu_pct: None,
+                z_index: None,
+                layer: None,
+                focus_duration_ms: None,
+            };
+
+            if toplevel.states.contains(&STATE_ACTIVATED) {
+                output.focus = Some(window.clone());
+            }
+            output.windows.push(window);
+        }
+
+        Some(output)
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.manager.stop();
+        let _ = self.connection.flush();
+    }
+}
+
+/// Lists toplevels through `session`, connecting on first use. A failed
+/// roundtrip (the compositor went away or dropped us) clears the session so
+/// the next call reconnects.
+pub fn toplevels(session: &mut Option<Session>) -> Option<Toplevels> {
+    if session.is_none() {
+        *session = Session::connect();
+    }
+    let toplevels = session.as_mut()?.toplevels();
+    if toplevels.is_none() {
+        *session = None;
+    }
+    toplevels
+}
+
+fn decode_states(raw: &[u8]) -> Vec<u32> {
+    raw.chunks_exact(4)
+        .map(|chunk| u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+    fn event(
+        _state: &mut Self,
+        _registry: &WlRegistry,
+        _event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _connection: &Connection,
+        _queue: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _manager: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _data: &(),
+        _connection: &Connection,
+        _queue: &QueueHandle<Self>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event {
+            // Protocol ids are reused once a handle is destroyed, so windows
+            // are named by a per-session sequence number instead.
+            state.next_seq += 1;
+            let seq = state.next_seq;
+            state.toplevels.insert(
+                toplevel.id().protocol_id(),
+                Toplevel {
+                    seq,
+                    ..Toplevel::default()
+                },
+            );
+        }
+    }
+
+    event_created_child!(State, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _data: &(),
+        _connection: &Connection,
+        _queue: &QueueHandle<Self>,
+    ) {
+        let Some(toplevel) = state.toplevels.get_mut(&handle.id().protocol_id()) else {
+            return;
+        };
+
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::Title { title } => {
+                toplevel.title = Some(title);
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                toplevel.app_id = Some(app_id);
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::State { state } => {
+                toplevel.states = decode_states(&state);
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+                toplevel.closed = true;
+                handle.destroy();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{STATE_ACTIVATED, STATE_FULLSCREEN, decode_states};
+
+    #[test]
+    fn decode_states_reads_native_endian_u32_array() {
+        let mut raw = Vec::new();
+        raw.extend_from_slice(&STATE_ACTIVATED.to_ne_bytes());
+        raw.extend_from_slice(&STATE_FULLSCREEN.to_ne_bytes());
+        raw.push(0xff);
+
+        assert_eq!(decode_states(&raw), vec![STATE_ACTIVATED, STATE_FULLSCREEN]);
+    }
+}
@@ -0,0 +1,474 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+
+use crate::util::deadline::Deadline;
+
+const SYNC_EVERY_RECORDS: usize = 32;
+const SYNC_INTERVAL: Duration = Duration::from_secs(5);
+const ROLLING_PREFIX: &str = "vizier-";
+const ROLLING_SUFFIX: &str = ".ndjson";
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+const WEBHOOK_RETRIES: u32 = 3;
+const WEBHOOK_PAUSE: Duration = Duration::from_secs(1);
+const WEBHOOK_DRAIN: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+pub trait Sink {
+    fn write_record(&mut self, record: &[u8]) -> Result<()>;
+}
+
+pub struct StdoutSink;
+
+impl Sink for StdoutSink {
+    fn write_record(&mut self, record: &[u8]) -> Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(record)?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+pub struct AtomicFileSink {
+    path: PathBuf,
+}
+
+impl AtomicFileSink {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl Sink for AtomicFileSink {
+    fn write_record(&mut self, record: &[u8]) -> Result<()> {
+        write_atomic(&self.path, record)
+    }
+}
+
+pub struct AppendFileSink {
+    file: File,
+    unsynced: usize,
+    last_sync: Instant,
+}
+
+impl AppendFileSink {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open output file {}", path.display()))?;
+
+        Ok(Self {
+            file,
+            unsynced: 0,
+            last_sync: Instant::now(),
+        })
+    }
+}
+
+impl Sink for AppendFileSink {
+    fn write_record(&mut self, record: &[u8]) -> Result<()> {
+        self.file.write_all(record)?;
+        self.unsynced += 1;
+
+        if self.unsynced >= SYNC_EVERY_RECORDS || self.last_sync.elapsed() >= SYNC_INTERVAL {
+            self.file.sync_data()?;
+            self.unsynced = 0;
+            self.last_sync = Instant::now();
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for AppendFileSink {
+    fn drop(&mut self) {
+        if self.unsynced > 0 {
+            let _ = self.file.sync_data();
+        }
+    }
+}
+
+pub struct RollingFileSink {
+    dir: PathBuf,
+    max_file_bytes: u64,
+    max_files: usize,
+    index: u32,
+    current_bytes: u64,
+    current: AppendFileSink,
+}
+
+impl RollingFileSink {
+    pub fn open(dir: &Path, max_file_bytes: u64, max_files: usize) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create log dir {}", dir.display()))?;
+
+        let index = rolling_indices(dir)?.last().copied().unwrap_or(0);
+        let path = rolling_path(dir, index);
+        let current_bytes = fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0);
+
+        let sink = Self {
+            dir: dir.to_path_buf(),
+            max_file_bytes,
+            max_files: max_files.max(1),
+            index,
+            current_bytes,
+            current: AppendFileSink::open(&path)?,
+        };
+        sink.prune()?;
+
+        Ok(sink)
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        self.index += 1;
+        self.current = AppendFileSink::open(&rolling_path(&self.dir, self.index))?;
+        self.current_bytes = 0;
+        self.prune()
+    }
+
+    fn prune(&self) -> Result<()> {
+        let indices = rolling_indices(&self.dir)?;
+        let excess = indices.len().saturating_sub(self.max_files);
+
+        for index in &indices[..excess] {
+            fs::remove_file(rolling_path(&self.dir, *index))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Sink for RollingFileSink {
+    fn write_record(&mut self, record: &[u8]) -> Result<()> {
+        let len = record.len() as u64;
+        if self.current_bytes > 0 && self.current_bytes + len > self.max_file_bytes {
+            self.rotate()?;
+        }
+
+        self.current.write_record(record)?;
+        self.current_bytes += len;
+        Ok(())
+    }
+}
+
+pub struct CompressingSink {
+    inner: Box<dyn Sink>,
+    compression: Compression,
+}
+
+impl CompressingSink {
+    pub fn wrap(inner: Box<dyn Sink>, compression: Compression) -> Box<dyn Sink> {
+        if compression == Compression::None {
+            inner
+        } else {
+            Box::new(Self { inner, compression })
+        }
+    }
+}
+
+impl Sink for CompressingSink {
+    fn write_record(&mut self, record: &[u8]) -> Result<()> {
+        let compressed = compress(record, self.compression)?;
+        self.inner.write_record(&compressed)
+    }
+}
+
+/// Frames records as one JSON array for `watch --json-array`: `[` before the
+/// first, `,` between, and `]` when dropped, so a watch that ends through
+/// `--max-runtime` or Ctrl-C still leaves a complete document.
+pub struct JsonArraySink {
+    inner: Box<dyn Sink>,
+    opened: bool,
+}
+
+impl JsonArraySink {
+    pub fn new(inner: Box<dyn Sink>) -> Self {
+        Self {
+            inner,
+            opened: false,
+        }
+    }
+}
+
+impl Sink for JsonArraySink {
+    fn write_record(&mut self, record: &[u8]) -> Result<()> {
+        let record = record.strip_suffix(b"\n").unwrap_or(record);
+        let separator: &[u8] = if self.opened { b",\n" } else { b"[\n" };
+        self.opened = true;
+        self.inner.write_record(&[separator, record].concat())
+    }
+}
+
+impl Drop for JsonArraySink {
+    fn drop(&mut self) {
+        let closing: &[u8] = if self.opened { b"\n]\n" } else { b"[]\n" };
+        let _ = self.inner.write_record(closing);
+    }
+}
+
+pub struct WebhookSink {
+    inner: Option<Box<dyn Sink>>,
+    queue: Arc<WebhookQueue>,
+    worker: Option<JoinHandle<()>>,
+}
+
+struct WebhookQueue {
+    state: Mutex<QueueState>,
+    ready: Condvar,
+    capacity: usize,
+}
+
+#[derive(Default)]
+struct QueueState {
+    records: VecDeque<(u64, Vec<u8>)>,
+    next_seq: u64,
+    dropped: u64,
+    closed: bool,
+}
+
+impl WebhookSink {
+    /// Tees records to `url` (or only to `url` when `inner` is `None`),
+    /// delivering them from a background thread so a slow endpoint never
+    /// stalls collection.
+    pub fn new(inner: Option<Box<dyn Sink>>, url: &str, capacity: usize) -> Self {
+        let queue = Arc::new(WebhookQueue {
+            state: Mutex::new(QueueState::default()),
+            ready: Condvar::new(),
+            capacity: capacity.max(1),
+        });
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(WEBHOOK_TIMEOUT)
+            .timeout_read(WEBHOOK_TIMEOUT)
+            .timeout_write(WEBHOOK_TIMEOUT)
+            .build();
+        let worker = {
+            let queue = Arc::clone(&queue);
+            let url = url.to_string();
+            thread::spawn(move || deliver(&queue, &agent, &url))
+        };
+
+        Self {
+            inner,
+            queue,
+            worker: Some(worker),
+        }
+    }
+}
+
+impl Sink for WebhookSink {
+    fn write_record(&mut self, record: &[u8]) -> Result<()> {
+        if let Some(inner) = &mut self.inner {
+            inner.write_record(record)?;
+        }
+
+        let mut state = lock(&self.queue.state);
+        if state.records.len() >= self.queue.capacity {
+            state.records.pop_front();
+            state.dropped += 1;
+            if state.dropped == 1 {
+                eprintln!(
+                    "vz: warning: webhook queue full ({} records); dropping the oldest until the endpoint catches up",
+                    self.queue.capacity
+                );
+            }
+        }
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.records.push_back((seq, record.to_vec()));
+        self.queue.ready.notify_one();
+        Ok(())
+    }
+}
+
+impl Drop for WebhookSink {
+    fn drop(&mut self) {
+        // Reported before draining: nothing more is queued once the sink is
+        // dropped, and `--max-runtime`'s watchdog may end the drain early.
+        let dropped = lock(&self.queue.state).dropped;
+        if dropped > 0 {
+            eprintln!("vz: warning: dropped {dropped} webhook record(s) while the queue was full");
+        }
+
+        let started = Instant::now();
+        while !lock(&self.queue.state).records.is_empty() && started.elapsed() < WEBHOOK_DRAIN {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        lock(&self.queue.state).closed = true;
+        self.queue.ready.notify_one();
+        if let Some(worker) = self.worker.take()
+            && worker.is_finished()
+        {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn deliver(queue: &WebhookQueue, agent: &ureq::Agent, url: &str) {
+    loop {
+        let (seq, record) = {
+            let mut state = lock(&queue.state);
+            while state.records.is_empty() && !state.closed {
+                state = queue
+                    .ready
+                    .wait(state)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+            }
+            match state.records.front() {
+                Some((seq, record)) if !state.closed => (*seq, record.clone()),
+                _ => return,
+            }
+        };
+
+        let delivered = Deadline::default()
+            .retry(WEBHOOK_RETRIES, || {
+                agent
+                    .post(url)
+                    .set("Content-Type", "application/json")
+                    .send_bytes(&record)
+                    .ok()
+            })
+            .is_some();
+
+        let mut state = lock(&queue.state);
+        if delivered {
+            // Overflow may already have evicted this record.
+            if state
+                .records
+                .front()
+                .is_some_and(|(front, _)| *front == seq)
+            {
+                state.records.pop_front();
+            }
+        } else if !state.closed {
+            let _ = queue.ready.wait_timeout(state, WEBHOOK_PAUSE);
+        }
+    }
+}
+
+fn lock(state: &Mutex<QueueState>) -> std::sync::MutexGuard<'_, QueueState> {
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub fn compress(bytes: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    match compression {
+        Compression::None => Ok(bytes.to_vec()),
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(bytes)?;
+            Ok(encoder.finish()?)
+        }
+        Compression::Zstd => Ok(zstd::encode_all(bytes, 0)?),
+    }
+}
+
+fn rolling_path(dir: &Path, index: u32) -> PathBuf {
+    dir.join(format!("{ROLLING_PREFIX}{index:03}{ROLLING_SUFFIX}"))
+}
+
+fn rolling_indices(dir: &Path) -> Result<Vec<u32>> {
+    let mut indices: Vec<u32> = fs::read_dir(dir)
+        .with_context(|| format!("failed to read log dir {}", dir.display()))?
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            name.strip_prefix(ROLLING_PREFIX)?
+                .strip_suffix(ROLLING_SUFFIX)?
+                .parse::<u32>()
+                .ok()
+        })
+        .collect();
+
+    indices.sort_unstable();
+    Ok(indices)
+}
+
+pub fn create_sink(output: Option<&Path>, streaming: bool) -> Result<Box<dyn Sink>> {
+    match output {
+        None => Ok(Box::new(StdoutSink)),
+        Some(path) if streaming => Ok(Box::new(AppendFileSink::open(path)?)),
+        Some(path) => Ok(Box::new(AtomicFileSink::new(path.to_path_buf()))),
+    }
+}
+
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    write_atomic_mode(path, bytes, false)
+}
+
+/// `write_atomic` for per-user state and caches: missing parent directories
+/// are created owner-only (0700 on Unix) and the file is written 0600, so
+/// other local users can neither read it nor plant one for us to load.
+pub fn write_private(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        builder
+            .create(dir)
+            .with_context(|| format!("creating directory {}", dir.display()))?;
+    }
+    write_atomic_mode(path, bytes, true)
+}
+
+fn write_atomic_mode(path: &Path, bytes: &[u8], private: bool) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("output path {} has no file name", path.display()))?;
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let tmp_path = dir.join(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    let result = (|| -> Result<()> {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        if private {
+            use std::os::unix::fs::OpenOptionsExt;
+            let _ = fs::remove_file(&tmp_path);
+            options.create_new(true).mode(0o600);
+        }
+        #[cfg(not(unix))]
+        let _ = private;
+        let mut file = options.open(&tmp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+
+    result.with_context(|| format!("failed to write output file {}", path.display()))
+}
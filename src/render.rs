@@ -0,0 +1,541 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::diff::{DiffEnvelope, DiffRecord, Drift, WakeDelta};
+use crate::observation::{FocusRecord, Observation, SnapshotCounts, WakeCounts, WakeObservation};
+use crate::observer::capabilities::Capabilities;
+use crate::observer::plan::Plan;
+
+const LABEL_WIDTH: usize = 12;
+const EXPANDED_DEPTH: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Format {
+    #[default]
+    Json,
+    Text,
+    Csv,
+}
+
+/// Arrays `--format csv --section` can flatten into a table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Section {
+    Connections,
+    Windows,
+    Ports,
+    #[value(name = "recent_files")]
+    RecentFiles,
+}
+
+impl Section {
+    fn pointer(self) -> &'static str {
+        match self {
+            Section::Connections => "/net_connections",
+            Section::Windows => "/windows",
+            Section::Ports => "/listening_ports",
+            Section::RecentFiles => "/filesystem/recent_files",
+        }
+    }
+}
+
+pub struct Text<'a, T> {
+    value: &'a T,
+    color: bool,
+}
+
+impl<'a, T> Text<'a, T> {
+    pub fn new(value: &'a T, color: bool) -> Self {
+        Self { value, color }
+    }
+
+    fn row(&self, f: &mut Formatter<'_>, label: &str, value: &str) -> fmt::Result {
+        if self.color {
+            writeln!(f, "\x1b[36m{label:<LABEL_WIDTH$}\x1b[0m {value}")
+        } else {
+            writeln!(f, "{label:<LABEL_WIDTH$} {value}")
+        }
+    }
+
+    /// One `op path` row per operation.
+    fn patch_ops(&self, f: &mut Formatter<'_>, patch: &json_patch::Patch) -> fmt::Result {
+        for op in &patch.0 {
+            let value = serde_json::to_value(op).unwrap_or_default();
+            let name = value.get("op").and_then(|op| op.as_str()).unwrap_or("?");
+            self.row(f, name, op.path().as_str())?;
+        }
+        Ok(())
+    }
+
+    fn highlight(&self, value: &str) -> String {
+        if self.color {
+            format!("\x1b[1m{value}\x1b[0m")
+        } else {
+            value.to_string()
+        }
+    }
+}
+
+impl Display for Text<'_, WakeObservation> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let wake = self.value;
+        let machine = &wake.machine;
+
+        self.row(
+            f,
+            "host",
+            &format!(
+                "{} ({} {}, kernel {}, {})",
+                self.highlight(&machine.hostname),
+                machine.os,
+                machine.os_version,
+                machine.kernel,
+                machine.arch
+            ),
+        )?;
+        self.row(
+            f,
+            "user",
+            &format!(
+                "{} ({})",
+                self.highlight(&wake.user.username),
+                wake.user.home_dir
+            ),
+        )?;
+        self.row(f, "uptime", &format_duration(wake.datetime.uptime_seconds))?;
+
+        let app_names: Vec<&str> = wake
+            .installed_apps
+            .iter()
+            .map(|app| app.name.as_str())
+            .collect();
+        self.row(f, "apps", &summarize_list(&app_names, 5))?;
+
+        let mut network = summarize_list(
+            &wake
+                .network_identity
+                .local_ips
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<&str>>(),
+            3,
+        );
+        if let Some(public_ip) = &wake.network_identity.public_ip {
+            network.push_str(&format!(", public {}", self.highlight(public_ip)));
+        }
+        if let Some(vpn_interface) = &wake.network_identity.vpn_interface {
+            network.push_str(&format!(", vpn {vpn_interface}"));
+        }
+        self.row(f, "network", &network)?;
+        self.row(f, "listening", &wake.listening_ports.len().to_string())
+    }
+}
+
+impl Display for Text<'_, Observation> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let observation = self.value;
+
+        let focus = match &observation.focus {
+            Some(window) if window.title != window.app => {
+                format!("{} - {}", self.highlight(&window.app), window.title)
+            }
+            Some(window) => self.highlight(&window.app),
+            None => "none".to_string(),
+        };
+        self.row(f, "focus", &focus)?;
+        if let Some(session) = &observation.session {
+            let detail: Vec<&str> = [&session.compositor, &session.desktop]
+                .into_iter()
+                .flatten()
+                .map(String::as_str)
+                .collect();
+            let value = if detail.is_empty() {
+                session.display_server.clone()
+            } else {
+                format!("{} ({})", session.display_server, detail.join(", "))
+            };
+            self.row(f, "session", &value)?;
+        }
+        self.row(f, "displays", &observation.displays.len().to_string())?;
+        self.row(f, "windows", &observation.windows.len().to_string())?;
+
+        let mut per_app: BTreeMap<&str, u32> = BTreeMap::new();
+        for connection in &observation.net_connections {
+            *per_app.entry(connection.app.as_str()).or_default() +=
+                connection.connection_count.unwrap_or(1);
+        }
+        for group in observation.connections_by_app.iter().flatten() {
+            *per_app.entry(group.app.as_str()).or_default() += group.connections as u32;
+        }
+        let total: u32 = per_app.values().sum();
+        let mut ranked: Vec<(&str, u32)> = per_app.into_iter().collect();
+        ranked.sort_by(|left, right| right.1.cmp(&left.1).then(left.0.cmp(right.0)));
+        let apps: Vec<String> = ranked
+            .iter()
+            .map(|(app, count)| format!("{app} x{count}"))
+            .collect();
+        let apps: Vec<&str> = apps.iter().map(String::as_str).collect();
+
+        let connections = if total == 0 {
+            "0".to_string()
+        } else {
+            format!(
+                "{} ({})",
+                self.highlight(&total.to_string()),
+                summarize_list(&apps, 3)
+            )
+        };
+        self.row(f, "connections", &connections)?;
+        let fs_events = match observation.fs_events_dropped {
+            Some(dropped) => format!("{} (+{dropped} dropped)", observation.fs_events.len()),
+            None => observation.fs_events.len().to_string(),
+        };
+        self.row(f, "fs events", &fs_events)
+    }
+}
+
+impl Display for Text<'_, SnapshotCounts> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let counts = self.value;
+        self.row(f, "windows", &counts.windows.to_string())?;
+        self.row(f, "displays", &counts.displays.to_string())?;
+        self.row(f, "connections", &counts.connections.to_string())?;
+        self.row(f, "listening", &counts.listening.to_string())?;
+        self.row(f, "fs_events", &counts.fs_events.to_string())
+    }
+}
+
+impl Display for Text<'_, FocusRecord> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let record = self.value;
+        let focus = match (&record.app, &record.title) {
+            (Some(app), Some(title)) if title != app => {
+                format!("{} - {title}", self.highlight(app))
+            }
+            (Some(app), _) => self.highlight(app),
+            (None, _) => "none".to_string(),
+        };
+        self.row(f, "focus", &focus)?;
+        self.row(f, "idle", &format!("{}ms", record.idle_ms))
+    }
+}
+
+impl Display for Text<'_, WakeCounts> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let counts = self.value;
+        self.row(f, "apps", &counts.apps.to_string())?;
+        self.row(f, "mounts", &counts.mounts.to_string())?;
+        self.row(f, "listening", &counts.listening.to_string())?;
+        self.row(f, "sessions", &counts.sessions.to_string())?;
+        self.row(f, "recent_files", &counts.recent_files.to_string())
+    }
+}
+
+impl Display for Text<'_, Capabilities> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (name, available) in self.value.entries() {
+            let value = if available {
+                self.highlight("yes")
+            } else {
+                "no".to_string()
+            };
+            self.row(f, name, &value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Text<'_, Plan> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let plan = self.value;
+        self.row(f, "command", &self.highlight(&plan.command))?;
+
+        for collector in &plan.collectors {
+            let value = if collector.enabled {
+                let mut parts: Vec<String> = Vec::new();
+                parts.extend(collector.commands.iter().map(|x| format!("runs `{x}`")));
+                parts.extend(collector.endpoints.iter().map(|x| format!("contacts {x}")));
+                parts.extend(collector.reads.iter().map(|x| format!("reads {x}")));
+                if let Some(timeout_ms) = collector.timeout_ms {
+                    parts.push(format!("{timeout_ms}ms timeout"));
+                }
+                if parts.is_empty() {
+                    "on".to_string()
+                } else {
+                    parts.join("; ")
+                }
+            } else {
+                format!(
+                    "off ({})",
+                    collector.reason.as_deref().unwrap_or("disabled")
+                )
+            };
+            self.row(f, &collector.name, &value)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Text<'_, DiffEnvelope> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let envelope = self.value;
+        self.row(
+            f,
+            "patch",
+            &format!(
+                "{} ops at +{}ms",
+                self.highlight(&envelope.patch.0.len().to_string()),
+                envelope.monotonic_ms
+            ),
+        )
+    }
+}
+
+impl Display for Text<'_, Drift> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let drift = self.value;
+        if !drift.drifted {
+            return self.row(f, "drift", "none");
+        }
+
+        self.row(
+            f,
+            "drift",
+            &format!("{} ops", self.highlight(&drift.patch.0.len().to_string())),
+        )?;
+        self.patch_ops(f, &drift.patch)
+    }
+}
+
+impl Display for Text<'_, WakeDelta> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let delta = self.value;
+        self.row(f, "machine_id", &delta.machine_id)?;
+        if delta.patch.0.is_empty() {
+            return self.row(f, "delta", "none");
+        }
+
+        self.row(
+            f,
+            "delta",
+            &format!("{} ops", self.highlight(&delta.patch.0.len().to_string())),
+        )?;
+        self.patch_ops(f, &delta.patch)
+    }
+}
+
+impl Display for Text<'_, DiffRecord<'_>> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.value {
+            DiffRecord::Snapshot { observation, .. } => Text::new(*observation, self.color).fmt(f),
+            DiffRecord::Patch(envelope) => Text::new(*envelope, self.color).fmt(f),
+        }
+    }
+}
+
+/// Writes the `section` array of `value` as CSV: a header row with every
+/// flattened field name (`bounds.x`), then one row per element. Nested arrays
+/// are kept as inline JSON and missing fields are left empty. An empty array
+/// produces no output, since there are no columns to name.
+pub fn to_csv<T: Serialize>(value: &T, section: Section) -> Result<Vec<u8>> {
+    let value = serde_json::to_value(value)?;
+    let name = section
+        .to_possible_value()
+        .expect("sections are not skipped");
+    let rows = value
+        .pointer(section.pointer())
+        .and_then(Value::as_array)
+        .ok_or_else(|| anyhow!("this output has no `{}` section", name.get_name()))?;
+
+    let rows: Vec<BTreeMap<String, String>> = rows
+        .iter()
+        .map(|row| {
+            let mut cells = BTreeMap::new();
+            flatten_cells(String::new(), row, &mut cells);
+            cells
+        })
+        .collect();
+    let header: BTreeSet<&str> = rows
+        .iter()
+        .flat_map(|row| row.keys())
+        .map(String::as_str)
+        .collect();
+
+    if header.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(&header)?;
+    for row in &rows {
+        writer.write_record(
+            header
+                .iter()
+                .map(|column| row.get(*column).map_or("", String::as_str)),
+        )?;
+    }
+    Ok(writer.into_inner().map_err(|err| err.into_error())?)
+}
+
+fn flatten_cells(prefix: String, value: &Value, cells: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields {
+                let column = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten_cells(column, field, cells);
+            }
+        }
+        Value::Null => {
+            cells.insert(prefix, String::new());
+        }
+        Value::String(text) => {
+            cells.insert(prefix, text.clone());
+        }
+        other => {
+            cells.insert(prefix, other.to_string());
+        }
+    }
+}
+
+/// Serializes like `to_vec_pretty` for the outer two levels (top-level
+/// fields, and the items/fields of their values) and writes anything deeper
+/// on one line, so each window or connection is a single row.
+pub fn to_vec_pretty_compact<T: Serialize>(value: &T) -> serde_json::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut serializer =
+        serde_json::Serializer::with_formatter(&mut out, PrettyCompactFormatter::default());
+    value.serialize(&mut serializer)?;
+    Ok(out)
+}
+
+#[derive(Default)]
+struct PrettyCompactFormatter {
+    depth: usize,
+    has_value: bool,
+}
+
+impl PrettyCompactFormatter {
+    fn expanded(&self) -> bool {
+        self.depth <= EXPANDED_DEPTH
+    }
+
+    fn begin<W: ?Sized + Write>(&mut self, writer: &mut W, open: &[u8]) -> io::Result<()> {
+        self.depth += 1;
+        self.has_value = false;
+        writer.write_all(open)
+    }
+
+    fn end<W: ?Sized + Write>(&mut self, writer: &mut W, close: &[u8]) -> io::Result<()> {
+        if self.expanded() && self.has_value {
+            writer.write_all(b"\n")?;
+            indent(writer, self.depth - 1)?;
+        }
+        self.depth -= 1;
+        writer.write_all(close)
+    }
+
+    fn separate<W: ?Sized + Write>(&mut self, writer: &mut W, first: bool) -> io::Result<()> {
+        if self.expanded() {
+            writer.write_all(if first { b"\n" } else { b",\n" })?;
+            indent(writer, self.depth)
+        } else if first {
+            Ok(())
+        } else {
+            writer.write_all(b", ")
+        }
+    }
+}
+
+impl serde_json::ser::Formatter for PrettyCompactFormatter {
+    fn begin_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.begin(writer, b"[")
+    }
+
+    fn end_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.end(writer, b"]")
+    }
+
+    fn begin_array_value<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.separate(writer, first)
+    }
+
+    fn end_array_value<W: ?Sized + Write>(&mut self, _writer: &mut W) -> io::Result<()> {
+        self.has_value = true;
+        Ok(())
+    }
+
+    fn begin_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.begin(writer, b"{")
+    }
+
+    fn end_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.end(writer, b"}")
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.separate(writer, first)
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(b": ")
+    }
+
+    fn end_object_value<W: ?Sized + Write>(&mut self, _writer: &mut W) -> io::Result<()> {
+        self.has_value = true;
+        Ok(())
+    }
+}
+
+fn indent<W: ?Sized + Write>(writer: &mut W, depth: usize) -> io::Result<()> {
+    for _ in 0..depth {
+        writer.write_all(b"  ")?;
+    }
+    Ok(())
+}
+
+fn summarize_list(items: &[&str], limit: usize) -> String {
+    if items.is_empty() {
+        return "none".to_string();
+    }
+
+    let shown = items[..items.len().min(limit)].join(", ");
+    if items.len() > limit {
+        format!("{shown} (+{} more)", items.len() - limit)
+    } else {
+        shown
+    }
+}
+
+fn format_duration(seconds: u64) -> String {
+    let days = seconds / 86_400;
+    let hours = (seconds % 86_400) / 3_600;
+    let minutes = (seconds % 3_600) / 60;
+
+    if days > 0 {
+        format!("{days}d {hours}h")
+    } else if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else if minutes > 0 {
+        format!("{minutes}m")
+    } else {
+        format!("{seconds}s")
+    }
+}
@@ -0,0 +1,108 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use flate2::read::MultiGzDecoder;
+use serde_json::Value;
+
+use crate::observation::Observation;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Debug, Clone)]
+pub struct ReplayStep {
+    pub index: usize,
+    pub observation: Observation,
+}
+
+pub struct Replay<R> {
+    lines: Lines<R>,
+    index: usize,
+    state: Option<Value>,
+}
+
+impl<R: BufRead> Replay<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            index: 0,
+            state: None,
+        }
+    }
+
+    fn apply(&mut self, line: &str) -> Result<Option<Observation>> {
+        let mut record: Value = serde_json::from_str(line)?;
+
+        match record.get("kind").and_then(Value::as_str) {
+            Some("snapshot") => {
+                let observation = record
+                    .get_mut("observation")
+                    .map(Value::take)
+                    .context("snapshot record has no observation")?;
+                self.state = Some(observation);
+            }
+            Some("patch") => {
+                let Some(state) = self.state.as_mut() else {
+                    return Ok(None);
+                };
+                let patch: json_patch::Patch = serde_json::from_value(
+                    record
+                        .get_mut("patch")
+                        .map(Value::take)
+                        .context("patch record has no patch")?,
+                )?;
+                json_patch::patch(state, &patch)?;
+            }
+            Some(kind) => bail!("unknown record kind {kind:?}"),
+            None => bail!("record has no kind"),
+        }
+
+        match &self.state {
+            Some(state) => Ok(Some(serde_json::from_value(state.clone())?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Replay<R> {
+    type Item = Result<ReplayStep>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err.into())),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let index = self.index;
+            self.index += 1;
+
+            match self.apply(&line) {
+                Ok(Some(observation)) => return Some(Ok(ReplayStep { index, observation })),
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err.context(format!("record {index}")))),
+            }
+        }
+    }
+}
+
+pub fn open(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let head = reader.fill_buf()?;
+
+    if head.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else if head.starts_with(&ZSTD_MAGIC) {
+        Ok(Box::new(BufReader::new(zstd::Decoder::with_buffer(
+            reader,
+        )?)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
@@ -0,0 +1,296 @@
+use std::fmt::Write as _;
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::thread;
+use std::time::Duration;
+#[cfg(unix)]
+use std::time::Instant;
+
+#[cfg(unix)]
+use anyhow::bail;
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sysinfo::System;
+use tiny_http::{Header, Method, Response};
+
+use crate::observer::common::bytes_to_gb;
+use crate::observer::{Observer, Waker};
+use crate::util::deadline::Deadline;
+
+const HTTP_POLL: Duration = Duration::from_secs(1);
+#[cfg(unix)]
+const ACCEPT_POLL: Duration = Duration::from_millis(50);
+#[cfg(unix)]
+const SESSION_IDLE: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+enum Request {
+    Snapshot,
+    Wake,
+}
+
+pub struct Server {
+    observer: Box<dyn Observer>,
+    waker: Box<dyn Waker>,
+    filter_apps: Vec<String>,
+    compact_level: u8,
+    system: System,
+}
+
+impl Server {
+    pub fn new(
+        observer: Box<dyn Observer>,
+        waker: Box<dyn Waker>,
+        filter_apps: Vec<String>,
+        compact_level: u8,
+    ) -> Self {
+        Self {
+            observer,
+            waker,
+            filter_apps,
+            compact_level,
+            system: System::new(),
+        }
+    }
+
+    pub fn handle(&mut self, line: &str) -> Vec<u8> {
+        let response = match self.respond(line) {
+            Ok(payload) => payload,
+            Err(err) => json!({ "error": format!("{err:#}") }),
+        };
+
+        let mut bytes = serde_json::to_vec(&response).unwrap_or_default();
+        bytes.push(b'\n');
+        bytes
+    }
+
+    fn respond(&mut self, line: &str) -> Result<Value> {
+        let request: Request = serde_json::from_str(line).context("invalid request")?;
+        self.payload(request)
+    }
+
+    fn payload(&mut self, request: Request) -> Result<Value> {
+        Ok(match request {
+            Request::Snapshot => {
+                serde_json::to_value(self.observer.snapshot()?.filter_apps(&self.filter_apps))?
+            }
+            Request::Wake => {
+                let wake = self.waker.wake()?.filter_apps(&self.filter_apps);
+                let wake = wake.compact_to(self.compact_level);
+                serde_json::to_value(wake)?
+            }
+        })
+    }
+
+    pub fn metrics(&mut self) -> Result<String> {
+        let snapshot = self.observer.snapshot()?.filter_apps(&self.filter_apps);
+        self.system.refresh_memory();
+
+        let gauges = [
+            (
+                "idle_ms",
+                "Milliseconds since the last user input.",
+                snapshot.idle_ms as f64,
+            ),
+            (
+                "windows",
+                "Visible windows in the current snapshot.",
+                snapshot.windows.len() as f64,
+            ),
+            (
+                "net_connections",
+                "Active network connections in the current snapshot.",
+                snapshot.net_connections.len() as f64,
+            ),
+            (
+                "ram_free_gb",
+                "Available memory in gigabytes.",
+                bytes_to_gb(self.system.available_memory()),
+            ),
+        ];
+
+        let mut body = String::new();
+        for (name, help, value) in gauges {
+            let _ = writeln!(body, "# HELP vizier_{name} {help}");
+            let _ = writeln!(body, "# TYPE vizier_{name} gauge");
+            let _ = writeln!(body, "vizier_{name} {value}");
+        }
+        Ok(body)
+    }
+
+    pub fn run_http(&mut self, addr: SocketAddr, deadline: Deadline) -> Result<()> {
+        let server = tiny_http::Server::http(addr)
+            .map_err(|err| anyhow!("failed to listen on {addr}: {err}"))?;
+
+        while !deadline.expired() {
+            let Some(request) = server.recv_timeout(deadline.cap(HTTP_POLL))? else {
+                continue;
+            };
+
+            let path = request.url().split('?').next().unwrap_or_default();
+            let (status, content_type, body) = if *request.method() != Method::Get {
+                (405, "text/plain", "method not allowed\n".to_string())
+            } else {
+                let result = match path {
+                    "/snapshot" => Some(self.payload(Request::Snapshot).map(|x| x.to_string())),
+                    "/wake" => Some(self.payload(Request::Wake).map(|x| x.to_string())),
+                    "/metrics" => Some(self.metrics()),
+                    _ => None,
+                };
+                let content_type = if path == "/metrics" {
+                    "text/plain; version=0.0.4"
+                } else {
+                    "application/json"
+                };
+                match result {
+                    Some(Ok(body)) => (200, content_type, body),
+                    Some(Err(err)) => (
+                        500,
+                        "application/json",
+                        json!({ "error": format!("{err:#}") }).to_string(),
+                    ),
+                    None => (404, "text/plain", "not found\n".to_string()),
+                }
+            };
+
+            let header = Header::from_bytes("Content-Type", content_type)
+                .map_err(|()| anyhow!("invalid content type {content_type:?}"))?;
+            let response = Response::from_string(body)
+                .with_status_code(status)
+                .with_header(header);
+            // A scraper that hangs up early only loses its own response.
+            let _ = request.respond(response);
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    pub fn run(&mut self, listener: &UnixListener, deadline: Deadline) -> Result<()> {
+        listener.set_nonblocking(true)?;
+        while !deadline.expired() {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(deadline.cap(ACCEPT_POLL));
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            // A client that disconnects mid-reply or goes idle only ends its own session.
+            let _ = self.session(stream, &deadline);
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn session(&mut self, stream: UnixStream, deadline: &Deadline) -> Result<()> {
+        stream.set_nonblocking(false)?;
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        let mut line = Vec::new();
+        let mut last_request = Instant::now();
+        loop {
+            if deadline.expired() || last_request.elapsed() >= SESSION_IDLE {
+                return Ok(());
+            }
+            reader
+                .get_ref()
+                .set_read_timeout(Some(deadline.cap(HTTP_POLL).max(ACCEPT_POLL)))?;
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => return Ok(()),
+                Ok(_) if line.ends_with(b"\n") => {}
+                // A partial line stays buffered until the rest arrives.
+                Ok(_) => continue,
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            }
+
+            last_request = Instant::now();
+            let request = String::from_utf8_lossy(&line).into_owned();
+            line.clear();
+            if request.trim().is_empty() {
+                continue;
+            }
+            writer.write_all(&self.handle(request.trim_end()))?;
+        }
+    }
+}
+
+#[cfg(unix)]
+pub struct Socket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl Socket {
+    pub fn bind(path: &Path) -> Result<Self> {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if !metadata.file_type().is_socket() => {
+                bail!("{} exists and is not a socket", path.display());
+            }
+            Ok(_) => Self::remove_stale(path)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to inspect {}", path.display()));
+            }
+        }
+
+        // The socket file is created by bind, so the umask keeps it private from the start.
+        // SAFETY: umask only swaps the process file-creation mask and cannot fail.
+        let previous = unsafe { libc::umask(0o177) };
+        let bound = UnixListener::bind(path);
+        unsafe { libc::umask(previous) };
+        let listener = bound.with_context(|| format!("failed to bind {}", path.display()))?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Removes a socket file nobody answers on, left behind by a server that
+    /// did not exit cleanly.
+    fn remove_stale(path: &Path) -> Result<()> {
+        if UnixStream::connect(path).is_ok() {
+            bail!("another server is already listening on {}", path.display());
+        }
+        fs::remove_file(path)
+            .with_context(|| format!("failed to remove stale socket {}", path.display()))
+    }
+
+    pub fn listener(&self) -> &UnixListener {
+        &self.listener
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Socket {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path)
+            && err.kind() != ErrorKind::NotFound
+        {
+            eprintln!(
+                "vz: warning: failed to remove {}: {err}",
+                self.path.display()
+            );
+        }
+    }
+}
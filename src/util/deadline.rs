@@ -0,0 +1,220 @@
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Deadline(Option<Instant>);
+
+impl Deadline {
+    pub fn after(limit: Option<Duration>) -> Self {
+        Self(limit.map(|limit| Instant::now() + limit))
+    }
+
+    pub fn expired(&self) -> bool {
+        self.0.is_some_and(|at| Instant::now() >= at)
+    }
+
+    pub fn remaining(&self) -> Option<Duration> {
+        self.0
+            .map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    pub fn cap(&self, timeout: Duration) -> Duration {
+        self.remaining()
+            .map_or(timeout, |remaining| remaining.min(timeout))
+    }
+
+    pub fn retry<T>(&self, retries: u32, mut attempt: impl FnMut() -> Option<T>) -> Option<T> {
+        for n in 0..=retries {
+            if n > 0 {
+                thread::sleep(self.cap(jittered_backoff(n)));
+            }
+            if self.expired() {
+                break;
+            }
+            if let Some(value) = attempt() {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+fn jittered_backoff(retry: u32) -> Duration {
+    let base = RETRY_BACKOFF * 2u32.saturating_pow(retry.saturating_sub(1)).min(8);
+    let jitter = RandomState::new().build_hasher().finish() % base.as_millis().max(1) as u64;
+    base + Duration::from_millis(jitter)
+}
+
+/// Random offsets in `[0, max)` for `watch --jitter-ms`, from a xorshift64*
+/// generator seeded once per process by `RandomState`.
+#[derive(Debug, Clone)]
+pub struct Jitter {
+    state: u64,
+    max_ms: u64,
+}
+
+impl Jitter {
+    pub fn new(max_ms: u64) -> Self {
+        Self {
+            state: RandomState::new().build_hasher().finish() | 1,
+            max_ms,
+        }
+    }
+
+    pub fn sample(&mut self) -> Duration {
+        if self.max_ms == 0 {
+            return Duration::ZERO;
+        }
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        let random = self.state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        Duration::from_millis(random % self.max_ms)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Budget {
+    deadline: Deadline,
+    truncated: Cell<bool>,
+}
+
+impl Budget {
+    pub fn new(deadline: Deadline) -> Self {
+        Self {
+            deadline,
+            truncated: Cell::new(false),
+        }
+    }
+
+    pub fn run<T: Default>(&self, collect: impl FnOnce() -> T) -> T {
+        if self.deadline.expired() {
+            self.truncated.set(true);
+            T::default()
+        } else {
+            collect()
+        }
+    }
+
+    pub fn truncated(&self) -> bool {
+        self.truncated.get()
+    }
+}
+
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .with_context(|| format!("expected a duration like 500ms, 5s, or 2m, got {value:?}"))?;
+
+    Ok(match unit {
+        "" | "ms" => Duration::from_millis(amount),
+        "s" => Duration::from_secs(amount),
+        "m" => Duration::from_secs(amount * 60),
+        "h" => Duration::from_secs(amount * 3600),
+        _ => bail!("unknown duration unit {unit:?} in {value:?} (use ms, s, m, or h)"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Budget, Deadline, Jitter, jittered_backoff, parse_duration};
+
+    #[test]
+    fn parse_duration_accepts_unit_suffixes_and_bare_millis() {
+        assert_eq!(parse_duration("250").ok(), Some(Duration::from_millis(250)));
+        assert_eq!(
+            parse_duration("500ms").ok(),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(parse_duration("5s").ok(), Some(Duration::from_secs(5)));
+        assert_eq!(parse_duration("2m").ok(), Some(Duration::from_secs(120)));
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("fast").is_err());
+    }
+
+    #[test]
+    fn budget_skips_work_once_deadline_has_passed() {
+        let open = Budget::new(Deadline::default());
+        assert_eq!(open.run(|| 7), 7);
+        assert!(!open.truncated());
+
+        let expired = Budget::new(Deadline::after(Some(Duration::ZERO)));
+        assert_eq!(expired.run(|| 7), 0);
+        assert!(expired.truncated());
+    }
+
+    #[test]
+    fn retry_stops_on_success_and_after_exhausting_attempts() {
+        let deadline = Deadline::default();
+
+        let mut calls = 0;
+        assert_eq!(
+            deadline.retry(2, || {
+                calls += 1;
+                (calls == 2).then_some(calls)
+            }),
+            Some(2)
+        );
+
+        let mut calls = 0;
+        assert_eq!(
+            deadline.retry(2, || {
+                calls += 1;
+                None::<()>
+            }),
+            None
+        );
+        assert_eq!(calls, 3);
+
+        let expired = Deadline::after(Some(Duration::ZERO));
+        let mut calls = 0;
+        assert_eq!(
+            expired.retry(5, || {
+                calls += 1;
+                None::<()>
+            }),
+            None
+        );
+        assert_eq!(calls, 0);
+    }
+
+    #[test]
+    fn jittered_backoff_doubles_within_bounds() {
+        for retry in 1..=3 {
+            let base = Duration::from_millis(100 * 2u64.pow(retry - 1));
+            let delay = jittered_backoff(retry);
+            assert!(
+                delay >= base && delay < base * 2,
+                "{delay:?} for retry {retry}"
+            );
+        }
+    }
+
+    #[test]
+    fn jitter_samples_stay_below_the_bound_and_vary() {
+        assert_eq!(Jitter::new(0).sample(), Duration::ZERO);
+
+        let mut jitter = Jitter::new(50);
+        let samples: Vec<Duration> = (0..200).map(|_| jitter.sample()).collect();
+        assert!(
+            samples
+                .iter()
+                .all(|delay| *delay < Duration::from_millis(50))
+        );
+        assert!(samples.iter().any(|delay| *delay != samples[0]));
+    }
+}
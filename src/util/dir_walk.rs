@@ -0,0 +1,144 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use globset::GlobSet;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileTimes {
+    pub path: PathBuf,
+    pub created: Option<SystemTime>,
+    pub accessed: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+}
+
+#[derive(Debug, Default)]
+pub struct DirScan {
+    pub files: Vec<FileTimes>,
+    pub skipped_symlinks: usize,
+}
+
+struct Walker<'a> {
+    root: &'a Path,
+    max_depth: usize,
+    excludes: &'a GlobSet,
+    visited: HashSet<(u64, u64)>,
+    scan: DirScan,
+}
+
+pub fn scan_files(root: &Path, max_depth: usize, excludes: &GlobSet) -> DirScan {
+    let mut walker = Walker {
+        root,
+        max_depth,
+        excludes,
+        visited: HashSet::new(),
+        scan: DirScan::default(),
+    };
+    walker.visit(root, 1);
+    walker.scan
+}
+
+impl Walker<'_> {
+    fn visit(&mut self, dir: &Path, depth: usize) {
+        let Ok(metadata) = fs::metadata(dir) else {
+            return;
+        };
+        if let Some(identity) = dir_identity(&metadata)
+            && !self.visited.insert(identity)
+        {
+            return;
+        }
+        let Ok(children) = fs::read_dir(dir) else {
+            return;
+        };
+
+        let mut subdirs = Vec::new();
+        for child in children.flatten() {
+            let Ok(file_type) = child.file_type() else {
+                continue;
+            };
+
+            if file_type.is_symlink() {
+                self.scan.skipped_symlinks += 1;
+            } else if file_type.is_dir() {
+                subdirs.push(child.path());
+            } else if file_type.is_file()
+                && let Ok(metadata) = child.metadata()
+            {
+                self.scan.files.push(FileTimes {
+                    path: child.path(),
+                    created: metadata.created().ok(),
+                    accessed: metadata.accessed().ok(),
+                    modified: metadata.modified().ok(),
+                });
+            }
+        }
+
+        if depth < self.max_depth {
+            subdirs.sort();
+            for subdir in subdirs {
+                let relative = subdir.strip_prefix(self.root).unwrap_or(&subdir);
+                if !self.excludes.is_match(relative) {
+                    self.visit(&subdir, depth + 1);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn dir_identity(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_identity(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use globset::{Glob, GlobSet, GlobSetBuilder};
+
+    use super::scan_files;
+
+    #[test]
+    fn scan_files_stops_at_max_depth_and_prunes_excludes() {
+        let dir = std::env::temp_dir().join(format!("vizier-dir-walk-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let root = dir.join("home");
+        fs::create_dir_all(root.join("a").join("b")).expect("root should be creatable");
+        fs::create_dir_all(root.join("skip")).expect("root should be creatable");
+        for file in ["top.txt", "a/mid.txt", "a/b/deep.txt", "skip/hidden.txt"] {
+            fs::write(root.join(file), "x").expect("file should be writable");
+        }
+        let excludes = GlobSetBuilder::new()
+            .add(Glob::new("skip").expect("glob"))
+            .build()
+            .expect("glob set");
+
+        let names = |depth: usize, excludes: &GlobSet| {
+            let mut names: Vec<String> = scan_files(&root, depth, excludes)
+                .files
+                .iter()
+                .filter_map(|file| file.path.strip_prefix(&root).ok())
+                .map(|path| path.to_string_lossy().replace('\\', "/"))
+                .collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(names(2, &excludes), ["a/mid.txt", "top.txt"]);
+        assert_eq!(
+            names(3, &GlobSet::empty()),
+            ["a/b/deep.txt", "a/mid.txt", "skip/hidden.txt", "top.txt"]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1 +1,10 @@
+pub mod deadline;
+pub mod dir_walk;
+pub mod interrupt;
+pub mod mount_table;
 pub mod net;
+pub mod process;
+pub mod status;
+pub mod system_profiler;
+pub mod toolchain;
+pub mod who;
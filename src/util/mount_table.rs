@@ -0,0 +1,96 @@
+use std::fs;
+
+const PROC_MOUNTS: &str = "/proc/self/mounts";
+
+/// One line of the kernel mount table: what is mounted where, as which
+/// filesystem type, and whether it is read-only.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountEntry {
+    pub device: String,
+    pub path: String,
+    pub fs_type: String,
+    pub read_only: bool,
+}
+
+/// Reads `/proc/self/mounts`. Returns an empty table where there is none
+/// (anything but Linux) or it cannot be read. Reading it never touches the
+/// mounted filesystems, so a hung NFS server cannot block it.
+pub fn read() -> Vec<MountEntry> {
+    fs::read_to_string(PROC_MOUNTS)
+        .map(|text| parse(&text))
+        .unwrap_or_default()
+}
+
+/// Parses `fstab`-style lines (`device path type options dump pass`),
+/// decoding the octal escapes (`\040` for a space) the kernel writes in
+/// device and path fields. Short lines are skipped.
+pub fn parse(text: &str) -> Vec<MountEntry> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let device = unescape(fields.next()?);
+            let path = unescape(fields.next()?);
+            let fs_type = fields.next()?.to_string();
+            let read_only = fields.next()?.split(',').any(|option| option == "ro");
+            Some(MountEntry {
+                device,
+                path,
+                fs_type,
+                read_only,
+            })
+        })
+        .collect()
+}
+
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let octal = bytes
+            .get(index + 1..index + 4)
+            .filter(|_| bytes[index] == b'\\')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match octal {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 4;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MountEntry, parse};
+
+    #[test]
+    fn parse_reads_types_flags_and_escaped_paths() {
+        let table = parse(
+            "/dev/vda / ext4 rw,relatime 0 0\n\
+             proc /proc proc rw,nosuid 0 0\n\
+             nas:/export/home /home nfs4 rw,vers=4.2 0 0\n\
+             /dev/sdb1 /media/My\\040Disk vfat ro,noatime 0 0\n\
+             truncated\n",
+        );
+        assert_eq!(table.len(), 4);
+        assert_eq!(
+            table[2],
+            MountEntry {
+                device: "nas:/export/home".into(),
+                path: "/home".into(),
+                fs_type: "nfs4".into(),
+                read_only: false,
+            }
+        );
+        assert_eq!(table[3].path, "/media/My Disk");
+        assert!(table[3].read_only);
+        assert!(!table[0].read_only);
+    }
+}
@@ -1,29 +1,162 @@
+use std::collections::BTreeSet;
 #[cfg(target_os = "macos")]
 use std::collections::HashSet;
 #[cfg(target_os = "linux")]
-use std::collections::HashSet as LinuxHashSet;
+use std::collections::{HashMap, HashSet as LinuxHashSet};
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+#[cfg(target_os = "linux")]
+use std::path::Path;
 use std::process::Command;
 
 use crate::observation::{ConnInfo, ListeningPort};
+use crate::util::process::lossy_text;
+use crate::util::status;
+
+pub const DEFAULT_CONN_STATES: [&str; 1] = ["ESTABLISHED"];
+
+#[cfg(target_os = "linux")]
+const PROC_ROOT: &str = "/proc";
+
+/// IANA service names for the TCP ports developers most often find
+/// listening, sorted by port for `well_known_service`.
+const WELL_KNOWN_SERVICES: &[(u16, &str)] = &[
+    (21, "ftp"),
+    (22, "ssh"),
+    (23, "telnet"),
+    (25, "smtp"),
+    (53, "domain"),
+    (80, "http"),
+    (110, "pop3"),
+    (111, "sunrpc"),
+    (139, "netbios-ssn"),
+    (143, "imap"),
+    (389, "ldap"),
+    (443, "https"),
+    (445, "microsoft-ds"),
+    (465, "submissions"),
+    (515, "printer"),
+    (548, "afpovertcp"),
+    (587, "submission"),
+    (631, "ipp"),
+    (636, "ldaps"),
+    (873, "rsync"),
+    (993, "imaps"),
+    (995, "pop3s"),
+    (1433, "ms-sql-s"),
+    (1883, "mqtt"),
+    (2049, "nfs"),
+    (2375, "docker"),
+    (2376, "docker-s"),
+    (3306, "mysql"),
+    (3389, "ms-wbt-server"),
+    (5353, "mdns"),
+    (5432, "postgresql"),
+    (5672, "amqp"),
+    (5900, "rfb"),
+    (6379, "redis"),
+    (8080, "http-alt"),
+    (11211, "memcache"),
+    (27017, "mongodb"),
+];
+
+/// The IANA service registered for `port`, if it is in the embedded table.
+pub fn well_known_service(port: u16) -> Option<&'static str> {
+    WELL_KNOWN_SERVICES
+        .binary_search_by_key(&port, |(port, _)| *port)
+        .ok()
+        .map(|index| WELL_KNOWN_SERVICES[index].1)
+}
+
+pub fn collect_active_connections(include_loopback: bool, states: &[String]) -> Vec<ConnInfo> {
+    let states = requested_states(states);
+    let mut connections = active_connections(include_loopback, &states);
+    if !connections.is_empty() {
+        apply_directions(&mut connections, &collect_listening_ports());
+    }
+    connections
+}
+
+pub fn active_connections_command(states: &[String]) -> Option<String> {
+    let states = requested_states(states);
+
+    if cfg!(target_os = "macos") {
+        Some(format!("lsof -nP -iTCP {}", lsof_state_filter(&states)))
+    } else if cfg!(target_os = "linux") && states.iter().all(|state| state == "ESTABLISHED") {
+        Some("ss -ntpH".to_string())
+    } else if cfg!(target_os = "linux") {
+        Some("ss -ntpH state all".to_string())
+    } else {
+        None
+    }
+}
+
+pub fn listening_ports_command() -> Option<String> {
+    if cfg!(target_os = "macos") {
+        Some("lsof -nP -iTCP -sTCP:LISTEN".to_string())
+    } else if cfg!(target_os = "linux") {
+        Some("ss -lntpH".to_string())
+    } else {
+        None
+    }
+}
+
+fn requested_states(states: &[String]) -> Vec<String> {
+    if states.is_empty() {
+        DEFAULT_CONN_STATES.iter().map(|x| x.to_string()).collect()
+    } else {
+        states
+            .iter()
+            .map(|state| normalize_tcp_state(state))
+            .collect()
+    }
+}
+
+/// The `-sTCP:` argument for normalized `states`. lsof only accepts the
+/// kernel's own state names, which on macOS spell `SYN_RECEIVED` as
+/// `SYN_RCVD`; the output is normalized back when parsed.
+fn lsof_state_filter(states: &[String]) -> String {
+    let names: Vec<&str> = states
+        .iter()
+        .map(|state| match state.as_str() {
+            "SYN_RECEIVED" => "SYN_RCVD",
+            state => state,
+        })
+        .collect();
+    format!("-sTCP:{}", names.join(","))
+}
 
-pub fn collect_active_connections(all_connections: bool) -> Vec<ConnInfo> {
+fn active_connections(include_loopback: bool, states: &[String]) -> Vec<ConnInfo> {
     #[cfg(target_os = "macos")]
     {
-        parse_established_lsof(all_connections)
+        parse_active_lsof(include_loopback, states)
     }
 
     #[cfg(target_os = "linux")]
     {
-        parse_established_ss(all_connections)
+        parse_active_ss(include_loopback, states)
     }
 
     #[cfg(all(not(target_os = "macos"), not(target_os = "linux")))]
     {
-        let _ = all_connections;
+        let _ = (include_loopback, states);
         Vec::new()
     }
 }
 
+pub fn normalize_tcp_state(state: &str) -> String {
+    let state = state.trim().to_ascii_uppercase().replace('-', "_");
+
+    match state.as_str() {
+        "ESTAB" => "ESTABLISHED".to_string(),
+        "SYN_RECV" | "SYN_RCVD" => "SYN_RECEIVED".to_string(),
+        "FIN_WAIT1" => "FIN_WAIT_1".to_string(),
+        "FIN_WAIT2" => "FIN_WAIT_2".to_string(),
+        "UNCONN" => "CLOSED".to_string(),
+        _ => state,
+    }
+}
+
 pub fn collect_listening_ports() -> Vec<ListeningPort> {
     #[cfg(target_os = "macos")]
     {
@@ -42,8 +175,9 @@ pub fn collect_listening_ports() -> Vec<ListeningPort> {
 }
 
 #[cfg(target_os = "macos")]
-fn parse_established_lsof(all_connections: bool) -> Vec<ConnInfo> {
-    let output = match run_command("lsof", &["-nP", "-iTCP", "-sTCP:ESTABLISHED"]) {
+fn parse_active_lsof(include_loopback: bool, states: &[String]) -> Vec<ConnInfo> {
+    let state_filter = lsof_state_filter(states);
+    let output = match run_command("lsof", &["-nP", "-iTCP", &state_filter]) {
         Some(output) => output,
         None => return Vec::new(),
     };
@@ -53,7 +187,7 @@ fn parse_established_lsof(all_connections: bool) -> Vec<ConnInfo> {
     output
         .lines()
         .skip(1)
-        .filter_map(|line| parse_established_line(line, all_connections))
+        .filter_map(|line| parse_active_line(line, include_loopback, states))
         .filter(|conn| {
             let key = format!(
                 "{}:{}:{}:{}:{}",
@@ -85,17 +219,32 @@ fn parse_listening_lsof() -> Vec<ListeningPort> {
 }
 
 #[cfg(target_os = "linux")]
-fn parse_established_ss(all_connections: bool) -> Vec<ConnInfo> {
-    let output = match run_command("ss", &["-ntpH"]) {
+fn parse_active_ss(include_loopback: bool, states: &[String]) -> Vec<ConnInfo> {
+    let args: &[&str] = if states.iter().all(|state| state == "ESTABLISHED") {
+        &["-ntpH"]
+    } else {
+        &["-ntpH", "state", "all"]
+    };
+    let output = match run_command("ss", args) {
         Some(output) => output,
         None => return Vec::new(),
     };
 
-    let mut seen = LinuxHashSet::new();
-
-    output
+    let mut connections: Vec<ConnInfo> = output
         .lines()
-        .filter_map(|line| parse_ss_established_line(line, all_connections))
+        .filter_map(|line| parse_ss_active_line(line, include_loopback, states))
+        .collect();
+    let mut owners = ProcOwners::new(Path::new(PROC_ROOT));
+    for conn in connections.iter_mut().filter(|conn| conn.pid == 0) {
+        if let Some((app, pid)) = owners.resolve(conn.local_port, conn.remote_port) {
+            conn.app = app;
+            conn.pid = pid;
+        }
+    }
+
+    let mut seen = LinuxHashSet::new();
+    connections
+        .into_iter()
         .filter(|conn| {
             let key = format!(
                 "{}:{}:{}:{}:{}",
@@ -113,11 +262,18 @@ fn parse_listening_ss() -> Vec<ListeningPort> {
         None => return Vec::new(),
     };
 
-    let mut seen = LinuxHashSet::new();
+    let mut ports: Vec<ListeningPort> = output.lines().filter_map(parse_ss_listen_line).collect();
+    let mut owners = ProcOwners::new(Path::new(PROC_ROOT));
+    for port in ports.iter_mut().filter(|port| port.pid == 0) {
+        if let Some((app, pid)) = owners.resolve(port.port, 0) {
+            port.app = app;
+            port.pid = pid;
+        }
+    }
 
-    output
-        .lines()
-        .filter_map(parse_ss_listen_line)
+    let mut seen = LinuxHashSet::new();
+    ports
+        .into_iter()
         .filter(|port| {
             let key = format!("{}:{}:{}:{}", port.app, port.pid, port.addr, port.port);
             seen.insert(key)
@@ -126,22 +282,27 @@ fn parse_listening_ss() -> Vec<ListeningPort> {
 }
 
 #[cfg(target_os = "macos")]
-fn parse_established_line(line: &str, all_connections: bool) -> Option<ConnInfo> {
+fn parse_active_line(line: &str, include_loopback: bool, states: &[String]) -> Option<ConnInfo> {
     let cols: Vec<&str> = line.split_whitespace().collect();
     if cols.len() < 9 {
         return None;
     }
 
+    let state = cols
+        .last()
+        .and_then(|x| x.strip_prefix('('))
+        .and_then(|x| x.strip_suffix(')'))
+        .map(normalize_tcp_state)?;
+    if !states.contains(&state) {
+        return None;
+    }
+
     let endpoint = cols.iter().find(|x| x.contains("->"))?;
-    let endpoint = endpoint
-        .strip_suffix("(ESTABLISHED)")
-        .unwrap_or(endpoint)
-        .trim();
     let (local, remote) = endpoint.split_once("->")?;
     let (local_addr, local_port) = parse_host_port(local)?;
     let (remote_addr, remote_port) = parse_host_port(remote)?;
 
-    if !all_connections && (is_loopback_addr(&local_addr) || is_loopback_addr(&remote_addr)) {
+    if !include_loopback && (is_loopback_addr(&local_addr) || is_loopback_addr(&remote_addr)) {
         return None;
     }
 
@@ -155,33 +316,29 @@ fn parse_established_line(line: &str, all_connections: bool) -> Option<ConnInfo>
         remote_port,
         pid,
         app,
-        state: "ESTABLISHED".to_string(),
+        state,
+        direction: "unknown".to_string(),
         connection_count: None,
         remote_host_count: None,
     })
 }
 
 #[cfg(target_os = "linux")]
-fn parse_ss_established_line(line: &str, all_connections: bool) -> Option<ConnInfo> {
-    let cols: Vec<&str> = line.split_whitespace().collect();
-    if cols.len() < 6 {
+fn parse_ss_active_line(line: &str, include_loopback: bool, states: &[String]) -> Option<ConnInfo> {
+    let row = SsRow::parse(line)?;
+    let state = normalize_tcp_state(row.state?);
+    if !states.contains(&state) {
         return None;
     }
 
-    if cols[0] != "ESTAB" {
-        return None;
-    }
-
-    let local = cols[3];
-    let remote = cols[4];
-    let (local_addr, local_port) = parse_host_port(local)?;
-    let (remote_addr, remote_port) = parse_host_port(remote)?;
+    let (local_addr, local_port) = parse_host_port(row.local)?;
+    let (remote_addr, remote_port) = parse_host_port(row.peer?)?;
 
-    if !all_connections && (is_loopback_addr(&local_addr) || is_loopback_addr(&remote_addr)) {
+    if !include_loopback && (is_loopback_addr(&local_addr) || is_loopback_addr(&remote_addr)) {
         return None;
     }
 
-    let (app, pid) = parse_ss_process(cols.get(5).copied().unwrap_or_default());
+    let (app, pid) = parse_ss_process(row.process.unwrap_or_default());
 
     Some(ConnInfo {
         proto: "tcp".to_string(),
@@ -190,7 +347,8 @@ fn parse_ss_established_line(line: &str, all_connections: bool) -> Option<ConnIn
         remote_port,
         pid,
         app,
-        state: "ESTABLISHED".to_string(),
+        state,
+        direction: "unknown".to_string(),
         connection_count: None,
         remote_host_count: None,
     })
@@ -216,19 +374,15 @@ fn parse_listen_line(line: &str) -> Option<ListeningPort> {
         pid,
         app,
         addr,
+        service: well_known_service(port).map(str::to_string),
     })
 }
 
 #[cfg(target_os = "linux")]
 fn parse_ss_listen_line(line: &str) -> Option<ListeningPort> {
-    let cols: Vec<&str> = line.split_whitespace().collect();
-    if cols.len() < 5 {
-        return None;
-    }
-
-    let local = cols[3];
-    let (addr, port) = parse_host_port(local)?;
-    let (app, pid) = parse_ss_process(cols.get(5).copied().unwrap_or_default());
+    let row = SsRow::parse(line)?;
+    let (addr, port) = parse_host_port(row.local)?;
+    let (app, pid) = parse_ss_process(row.process.unwrap_or_default());
 
     Some(ListeningPort {
         port,
@@ -236,18 +390,100 @@ fn parse_ss_listen_line(line: &str) -> Option<ListeningPort> {
         pid,
         app,
         addr,
+        service: well_known_service(port).map(str::to_string),
     })
 }
 
+/// One row of `ss -ntpH`/`-lntpH`, split by what each column looks like
+/// rather than where it sits: an optional `Netid`, an optional state (absent
+/// when `ss` was given a single-state filter), the two numeric queues, the
+/// local and peer endpoints, and the `users:((...))` process column wherever
+/// it appears (missing when `ss` could not see the owner). The process column
+/// is cut out before splitting on whitespace, since process names may contain
+/// spaces.
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq, Eq)]
+struct SsRow<'a> {
+    state: Option<&'a str>,
+    local: &'a str,
+    peer: Option<&'a str>,
+    process: Option<&'a str>,
+}
+
+#[cfg(target_os = "linux")]
+impl<'a> SsRow<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let (before, process, after) = match line.find("users:((") {
+            Some(start) => {
+                let process = ss_process_column(&line[start..]);
+                (
+                    &line[..start],
+                    Some(process),
+                    &line[start + process.len()..],
+                )
+            }
+            None => (line, None, ""),
+        };
+
+        let mut state = None;
+        let mut queues = 0;
+        let mut endpoints = Vec::with_capacity(2);
+        for column in before.split_whitespace().chain(after.split_whitespace()) {
+            if queues < 2 {
+                if column.parse::<u64>().is_ok() {
+                    queues += 1;
+                } else if queues == 0 {
+                    state = Some(column);
+                } else {
+                    return None;
+                }
+            } else if endpoints.len() < 2 && column.contains(':') {
+                endpoints.push(column);
+            }
+        }
+        if queues < 2 {
+            return None;
+        }
+
+        let mut endpoints = endpoints.into_iter();
+        Some(Self {
+            state,
+            local: endpoints.next()?,
+            peer: endpoints.next(),
+            process,
+        })
+    }
+}
+
+/// The `users:((...))` column at the start of `input`, ending at the first
+/// `))` outside a quoted process name.
+#[cfg(target_os = "linux")]
+fn ss_process_column(input: &str) -> &str {
+    let mut quoted = false;
+    let bytes = input.as_bytes();
+    for (index, byte) in bytes.iter().enumerate() {
+        match byte {
+            b'"' => quoted = !quoted,
+            b')' if !quoted && bytes.get(index + 1) == Some(&b')') => {
+                return &input[..index + 2];
+            }
+            _ => {}
+        }
+    }
+    input
+}
+
 #[cfg(target_os = "linux")]
 fn parse_ss_process(input: &str) -> (String, u32) {
-    let name = input
-        .split('"')
-        .nth(1)
-        .map(|x| x.to_string())
-        .unwrap_or_else(|| "unknown".to_string());
+    let (name, rest) = match input
+        .split_once('"')
+        .and_then(|(_, rest)| rest.split_once('"'))
+    {
+        Some((name, rest)) => (name.to_string(), rest),
+        None => ("unknown".to_string(), input),
+    };
 
-    let pid = input
+    let pid = rest
         .split("pid=")
         .nth(1)
         .and_then(|x| x.split(',').next())
@@ -257,26 +493,264 @@ fn parse_ss_process(input: &str) -> (String, u32) {
     (name, pid)
 }
 
+/// Recovers the owner of a socket `ss` could not label (it needs privileges to
+/// read other processes' fds): the port pair is looked up in `/proc/net/tcp{,6}`
+/// for its inode, and the inode in `/proc/<pid>/fd` for the pid. Both tables are
+/// read lazily, once, and only when some row is unlabeled.
+#[cfg(target_os = "linux")]
+struct ProcOwners<'a> {
+    root: &'a Path,
+    inodes: Option<HashMap<(u16, u16), u64>>,
+    owners: Option<HashMap<u64, u32>>,
+}
+
+#[cfg(target_os = "linux")]
+impl<'a> ProcOwners<'a> {
+    fn new(root: &'a Path) -> Self {
+        Self {
+            root,
+            inodes: None,
+            owners: None,
+        }
+    }
+
+    fn resolve(&mut self, local_port: u16, remote_port: u16) -> Option<(String, u32)> {
+        let root = self.root;
+        let inode = *self
+            .inodes
+            .get_or_insert_with(|| proc_net_inodes(root))
+            .get(&(local_port, remote_port))?;
+        let pid = *self
+            .owners
+            .get_or_insert_with(|| socket_owners(root))
+            .get(&inode)?;
+        let comm = std::fs::read_to_string(root.join(pid.to_string()).join("comm")).ok()?;
+        Some((comm.trim().to_string(), pid))
+    }
+}
+
+/// Maps `(local_port, remote_port)` to socket inode; listening sockets have
+/// remote port 0.
+#[cfg(target_os = "linux")]
+fn proc_net_inodes(root: &Path) -> HashMap<(u16, u16), u64> {
+    let port = |endpoint: &str| {
+        let (_, port) = endpoint.rsplit_once(':')?;
+        u16::from_str_radix(port, 16).ok()
+    };
+
+    ["tcp", "tcp6"]
+        .iter()
+        .filter_map(|table| std::fs::read_to_string(root.join("net").join(table)).ok())
+        .flat_map(|contents| {
+            contents
+                .lines()
+                .skip(1)
+                .filter_map(|line| {
+                    let cols: Vec<&str> = line.split_whitespace().collect();
+                    let inode = cols.get(9)?.parse::<u64>().ok()?;
+                    (inode != 0).then_some(((port(cols.get(1)?)?, port(cols.get(2)?)?), inode))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn socket_owners(root: &Path) -> HashMap<u64, u32> {
+    let mut owners = HashMap::new();
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return owners;
+    };
+
+    for entry in entries.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            if let Some(inode) = std::fs::read_link(fd.path()).ok().and_then(|target| {
+                target
+                    .to_str()?
+                    .strip_prefix("socket:[")?
+                    .strip_suffix(']')?
+                    .parse::<u64>()
+                    .ok()
+            }) {
+                owners.entry(inode).or_insert(pid);
+            }
+        }
+    }
+    owners
+}
+
+/// Addresses that only mean something on this link or site: IPv4 and IPv6
+/// link-local (`169.254.0.0/16`, `fe80::/10`) and IPv6 unique-local
+/// (`fc00::/7`).
+pub fn is_local_scope(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => v4.is_link_local(),
+        IpAddr::V6(v6) => v6.is_unicast_link_local() || v6.is_unique_local(),
+    }
+}
+
+/// Zeroes the host bits of an address for `--anonymize-ips`: IPv4 keeps its
+/// /24 and IPv6 its /64 (zone ids survive). Anything that isn't an address,
+/// such as `*` or a hostname, comes back unchanged.
+pub fn anonymize_ip(addr: &str) -> String {
+    let (ip, zone) = match addr.split_once('%') {
+        Some((ip, zone)) => (ip, Some(zone)),
+        None => (addr, None),
+    };
+
+    let masked = if let Ok(v4) = ip.parse::<Ipv4Addr>() {
+        Ipv4Addr::from(v4.to_bits() & !0xff).to_string()
+    } else if let Ok(v6) = ip.parse::<Ipv6Addr>() {
+        Ipv6Addr::from(v6.to_bits() & !u128::from(u64::MAX)).to_string()
+    } else {
+        return addr.to_string();
+    };
+
+    match zone {
+        Some(zone) => format!("{masked}%{zone}"),
+        None => masked,
+    }
+}
+
+pub fn normalize_mac(raw: &str) -> Option<String> {
+    let mac = raw.trim().to_ascii_lowercase().replace('-', ":");
+    let octets: Vec<&str> = mac.split(':').collect();
+    let valid = octets.len() == 6
+        && octets
+            .iter()
+            .all(|octet| octet.len() == 2 && octet.chars().all(|c| c.is_ascii_hexdigit()));
+
+    if !valid || mac == "00:00:00:00:00:00" {
+        return None;
+    }
+    Some(mac)
+}
+
+pub fn parse_proxy(raw: &str) -> Result<String, String> {
+    ureq::Proxy::new(raw).map_err(|err| format!("invalid proxy {raw:?}: {err}"))?;
+    Ok(raw.to_string())
+}
+
+pub fn proxy_for(url: &str, explicit: Option<&str>) -> Option<String> {
+    resolve_proxy(url, explicit, |name| {
+        std::env::var(name)
+            .or_else(|_| std::env::var(name.to_ascii_lowercase()))
+            .ok()
+    })
+}
+
+fn resolve_proxy(
+    url: &str,
+    explicit: Option<&str>,
+    env: impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    let host = rest.split(['/', ':']).next().unwrap_or_default();
+
+    if let Some(no_proxy) = env("NO_PROXY")
+        && no_proxy_matches(host, &no_proxy)
+    {
+        return None;
+    }
+
+    let proxy = match explicit {
+        Some(proxy) => Some(proxy.to_string()),
+        None if scheme == "https" => env("HTTPS_PROXY").or_else(|| env("HTTP_PROXY")),
+        None => env("HTTP_PROXY"),
+    };
+    proxy.filter(|proxy| !proxy.trim().is_empty())
+}
+
+fn no_proxy_matches(host: &str, no_proxy: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    no_proxy
+        .split(',')
+        .map(|entry| entry.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| entry == "*" || host == entry || host.ends_with(&format!(".{entry}")))
+}
+
+fn apply_directions(connections: &mut [ConnInfo], listening: &[ListeningPort]) {
+    let listening_ports: BTreeSet<(&str, u16)> = listening
+        .iter()
+        .map(|port| (port.proto.as_str(), port.port))
+        .collect();
+
+    for connection in connections {
+        let inbound = listening_ports.contains(&(connection.proto.as_str(), connection.local_port));
+        connection.direction = if inbound { "inbound" } else { "outbound" }.to_string();
+    }
+}
+
 fn run_command(bin: &str, args: &[&str]) -> Option<String> {
-    let output = Command::new(bin).args(args).output().ok()?;
+    let output = match Command::new(bin).args(args).output() {
+        Ok(output) => output,
+        Err(err) => {
+            if err.kind() == ErrorKind::NotFound {
+                status::report_missing(bin);
+            }
+            return None;
+        }
+    };
     if !output.status.success() {
         return None;
     }
-    String::from_utf8(output.stdout).ok()
+    Some(lossy_text(output.stdout))
 }
 
+/// Splits `host:port` as printed by `ss` and `lsof`: `1.2.3.4:443`,
+/// `[2001:db8::1]:443`, and scoped forms with the zone inside or after the
+/// brackets (`[fe80::1%eth0]:22`, `[fe80::1]%eth0:22`) or bare (`fe80::1%lo0:443`).
 fn parse_host_port(input: &str) -> Option<(String, u16)> {
-    let trimmed = input.trim().trim_start_matches('[').trim_end_matches(']');
+    let trimmed = input.trim();
 
-    if let Some((host, port)) = trimmed.rsplit_once(':') {
-        return Some((normalize_host(host), port.parse::<u16>().ok()?));
-    }
+    let (host, port) = match trimmed.strip_prefix('[') {
+        Some(bracketed) => {
+            let (inside, rest) = bracketed.split_once(']')?;
+            let (zone, port) = rest.rsplit_once(':')?;
+            match zone.strip_prefix('%') {
+                Some(zone) if !inside.contains('%') => (format!("{inside}%{zone}"), port),
+                _ => (inside.to_string(), port),
+            }
+        }
+        None => {
+            let (host, port) = trimmed.rsplit_once(':')?;
+            (host.to_string(), port)
+        }
+    };
 
-    None
+    Some((normalize_host(&host), port.parse::<u16>().ok()?))
 }
 
+/// Canonicalizes IPv6 text and unwraps IPv4-mapped addresses (`::ffff:1.2.3.4`
+/// becomes `1.2.3.4`) so one peer has one spelling regardless of socket family.
+/// Zone ids are kept on scoped addresses since `fe80::1` is ambiguous without them.
 fn normalize_host(input: &str) -> String {
-    input.trim_matches(['[', ']']).to_string()
+    let (addr, zone) = match input.split_once('%') {
+        Some((addr, zone)) => (addr, Some(zone)),
+        None => (input, None),
+    };
+
+    match addr.parse::<Ipv6Addr>() {
+        Ok(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => v4.to_string(),
+            None => match zone {
+                Some(zone) => format!("{v6}%{zone}"),
+                None => v6.to_string(),
+            },
+        },
+        Err(_) => input.to_string(),
+    }
 }
 
 fn is_loopback_addr(addr: &str) -> bool {
@@ -286,3 +760,350 @@ fn is_loopback_addr(addr: &str) -> bool {
         || addr.starts_with("fe80::1%")
         || addr == "*"
 }
+
+#[cfg(test)]
+mod tests {
+    #[cfg(target_os = "linux")]
+    use super::{ProcOwners, SsRow, parse_ss_active_line, parse_ss_listen_line};
+    use super::{
+        WELL_KNOWN_SERVICES, anonymize_ip, apply_directions, is_local_scope, lsof_state_filter,
+        normalize_mac, normalize_tcp_state, parse_host_port, requested_states, resolve_proxy,
+        well_known_service,
+    };
+    use crate::observation::{ConnInfo, ListeningPort};
+
+    fn connection(local_port: u16, remote_port: u16) -> ConnInfo {
+        ConnInfo {
+            proto: "tcp".to_string(),
+            local_port,
+            remote_addr: "203.0.113.9".to_string(),
+            remote_port,
+            pid: 1,
+            app: "app".to_string(),
+            state: "ESTABLISHED".to_string(),
+            direction: "unknown".to_string(),
+            connection_count: None,
+            remote_host_count: None,
+        }
+    }
+
+    #[test]
+    fn apply_directions_marks_listening_local_ports_inbound() {
+        let mut connections = vec![connection(22, 51234), connection(51235, 443)];
+        let listening = vec![ListeningPort {
+            port: 22,
+            proto: "tcp".to_string(),
+            pid: 1,
+            app: "sshd".to_string(),
+            addr: "0.0.0.0".to_string(),
+            service: Some("ssh".to_string()),
+        }];
+
+        apply_directions(&mut connections, &listening);
+
+        assert_eq!(connections[0].direction, "inbound");
+        assert_eq!(connections[1].direction, "outbound");
+    }
+
+    #[test]
+    fn well_known_service_names_common_ports_only() {
+        assert!(
+            WELL_KNOWN_SERVICES
+                .windows(2)
+                .all(|pair| pair[0].0 < pair[1].0)
+        );
+        assert_eq!(well_known_service(22), Some("ssh"));
+        assert_eq!(well_known_service(443), Some("https"));
+        assert_eq!(well_known_service(5432), Some("postgresql"));
+        assert_eq!(well_known_service(27017), Some("mongodb"));
+        assert_eq!(well_known_service(49152), None);
+    }
+
+    #[test]
+    fn parse_host_port_handles_v4_v6_brackets_and_scopes() {
+        let expect = |host: &str, port: u16| Some((host.to_string(), port));
+
+        assert_eq!(
+            parse_host_port("192.168.1.5:443"),
+            expect("192.168.1.5", 443)
+        );
+        assert_eq!(parse_host_port("*:22"), expect("*", 22));
+        assert_eq!(
+            parse_host_port("[2001:db8::1]:443"),
+            expect("2001:db8::1", 443)
+        );
+        assert_eq!(
+            parse_host_port("[2001:0db8:0:0::1]:443"),
+            expect("2001:db8::1", 443)
+        );
+        assert_eq!(
+            parse_host_port("[::ffff:203.0.113.9]:8080"),
+            expect("203.0.113.9", 8080)
+        );
+        assert_eq!(
+            parse_host_port("fe80::1%lo0:443"),
+            expect("fe80::1%lo0", 443)
+        );
+        assert_eq!(
+            parse_host_port("[fe80::1%eth0]:22"),
+            expect("fe80::1%eth0", 22)
+        );
+        assert_eq!(
+            parse_host_port("[fe80::1]%eth0:22"),
+            expect("fe80::1%eth0", 22)
+        );
+        assert_eq!(parse_host_port("[::]:80"), expect("::", 80));
+        assert_eq!(parse_host_port("0.0.0.0:*"), None);
+        assert_eq!(parse_host_port("[::1"), None);
+    }
+
+    #[test]
+    fn anonymize_ip_zeroes_host_bits_and_keeps_non_addresses() {
+        assert_eq!(anonymize_ip("192.168.1.37"), "192.168.1.0");
+        assert_eq!(anonymize_ip("0.0.0.0"), "0.0.0.0");
+        assert_eq!(
+            anonymize_ip("2001:db8:85a3:1:8a2e:370:7334:1"),
+            "2001:db8:85a3:1::"
+        );
+        assert_eq!(anonymize_ip("fe80::1c2b:3cff:fe4d:5e6f%en0"), "fe80::%en0");
+        assert_eq!(anonymize_ip("::"), "::");
+        assert_eq!(anonymize_ip("*"), "*");
+        assert_eq!(anonymize_ip("localhost"), "localhost");
+    }
+
+    #[test]
+    fn local_scope_covers_link_local_and_unique_local() {
+        let scoped = |ip: &str| is_local_scope(ip.parse().expect("valid address"));
+        assert!(scoped("169.254.10.1"));
+        assert!(scoped("fe80::1c2b:3cff:fe4d:5e6f"));
+        assert!(scoped("fd00::2"));
+        assert!(!scoped("192.168.1.37"));
+        assert!(!scoped("2001:db8::1"));
+    }
+
+    #[test]
+    fn normalize_tcp_state_maps_ss_and_lsof_spellings() {
+        assert_eq!(normalize_tcp_state("ESTAB"), "ESTABLISHED");
+        assert_eq!(normalize_tcp_state("close-wait"), "CLOSE_WAIT");
+        assert_eq!(normalize_tcp_state("TIME-WAIT"), "TIME_WAIT");
+        assert_eq!(normalize_tcp_state("SYN-RECV"), "SYN_RECEIVED");
+        assert_eq!(normalize_tcp_state("FIN-WAIT-1"), "FIN_WAIT_1");
+        assert_eq!(normalize_tcp_state("SYN_SENT"), "SYN_SENT");
+    }
+
+    #[test]
+    fn lsof_state_filter_uses_lsof_state_names() {
+        let requested = |states: &[&str]| {
+            requested_states(
+                &states
+                    .iter()
+                    .map(|state| state.to_string())
+                    .collect::<Vec<_>>(),
+            )
+        };
+        assert_eq!(
+            lsof_state_filter(&requested(&["syn-recv", "FIN-WAIT-1", "close_wait"])),
+            "-sTCP:SYN_RCVD,FIN_WAIT_1,CLOSE_WAIT"
+        );
+        assert_eq!(lsof_state_filter(&requested(&[])), "-sTCP:ESTABLISHED");
+
+        #[cfg(target_os = "macos")]
+        assert_eq!(
+            super::active_connections_command(&["SYN_RECEIVED".to_string()]).as_deref(),
+            Some("lsof -nP -iTCP -sTCP:SYN_RCVD")
+        );
+    }
+
+    #[test]
+    fn normalize_mac_lowercases_dash_separated_and_rejects_empty_macs() {
+        assert_eq!(
+            normalize_mac("AA-BB-CC-00-11-22\n").as_deref(),
+            Some("aa:bb:cc:00:11:22")
+        );
+        assert_eq!(
+            normalize_mac("a4:83:e7:0b:2c:91").as_deref(),
+            Some("a4:83:e7:0b:2c:91")
+        );
+        assert_eq!(normalize_mac("00:00:00:00:00:00"), None);
+        assert_eq!(normalize_mac(""), None);
+        assert_eq!(normalize_mac("not-a-mac"), None);
+    }
+
+    #[test]
+    fn resolve_proxy_prefers_flag_then_env_and_honors_no_proxy() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        let url = "https://api.ipify.org";
+
+        assert_eq!(resolve_proxy(url, None, env(&[])), None);
+        assert_eq!(
+            resolve_proxy(url, None, env(&[("HTTP_PROXY", "http://corp:3128")])).as_deref(),
+            Some("http://corp:3128")
+        );
+        assert_eq!(
+            resolve_proxy(
+                url,
+                None,
+                env(&[
+                    ("HTTPS_PROXY", "http://tls:3128"),
+                    ("HTTP_PROXY", "http://plain:3128")
+                ])
+            )
+            .as_deref(),
+            Some("http://tls:3128")
+        );
+        assert_eq!(
+            resolve_proxy(
+                url,
+                Some("http://flag:8080"),
+                env(&[("HTTPS_PROXY", "http://tls:3128")])
+            )
+            .as_deref(),
+            Some("http://flag:8080")
+        );
+        assert_eq!(
+            resolve_proxy(
+                url,
+                Some("http://flag:8080"),
+                env(&[("NO_PROXY", "localhost, .ipify.org")])
+            ),
+            None
+        );
+        assert_eq!(
+            resolve_proxy(
+                url,
+                None,
+                env(&[
+                    ("HTTPS_PROXY", "http://tls:3128"),
+                    ("NO_PROXY", "notipify.org")
+                ])
+            )
+            .as_deref(),
+            Some("http://tls:3128")
+        );
+    }
+
+    /// `ss -ntpH` as an unprivileged user: other users' sockets have no
+    /// process column, and browsers name their content processes with spaces.
+    #[cfg(target_os = "linux")]
+    const SS_ESTABLISHED: &str = "\
+ESTAB 0      0          192.168.1.23:48862     140.82.112.25:443   users:((\"firefox\",pid=2211,fd=97))
+ESTAB 0      0          192.168.1.23:40022      151.101.1.69:443   users:((\"Web Content\",pid=3021,fd=45),(\"Web Content\",pid=3020,fd=45))
+ESTAB 0      36             10.0.0.5:22             10.0.0.9:50514
+ESTAB 0      0     [2a01:4f8:c0c:1::2]:51234 [2606:4700::6810:84e5]:443   users:((\"curl\",pid=9911,fd=5))
+ESTAB 0      0             127.0.0.1:5432          127.0.0.1:41872 users:((\"postgres\",pid=1044,fd=9))
+";
+
+    /// `ss -lntpH`, including a scoped resolver address and nginx's
+    /// space-separated process titles.
+    #[cfg(target_os = "linux")]
+    const SS_LISTENING: &str = "\
+LISTEN 0      4096   127.0.0.53%lo:53        0.0.0.0:*    users:((\"systemd-resolve\",pid=612,fd=14))
+LISTEN 0      128          0.0.0.0:22        0.0.0.0:*
+LISTEN 0      511             [::]:80           [::]:*    users:((\"nginx: master p\",pid=1201,fd=7),(\"nginx: worker p\",pid=1202,fd=7))
+";
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn ss_rows_are_split_by_structure_not_position() {
+        let established = ["ESTABLISHED".to_string()];
+        let connections: Vec<_> = SS_ESTABLISHED
+            .lines()
+            .filter_map(|line| parse_ss_active_line(line, false, &established))
+            .map(|conn| (conn.app, conn.pid, conn.remote_addr, conn.remote_port))
+            .collect();
+        assert_eq!(
+            connections,
+            vec![
+                (
+                    "firefox".to_string(),
+                    2211,
+                    "140.82.112.25".to_string(),
+                    443
+                ),
+                (
+                    "Web Content".to_string(),
+                    3021,
+                    "151.101.1.69".to_string(),
+                    443
+                ),
+                ("unknown".to_string(), 0, "10.0.0.9".to_string(), 50514),
+                (
+                    "curl".to_string(),
+                    9911,
+                    "2606:4700::6810:84e5".to_string(),
+                    443
+                ),
+            ]
+        );
+
+        let ports: Vec<_> = SS_LISTENING
+            .lines()
+            .filter_map(parse_ss_listen_line)
+            .map(|port| (port.app, port.pid, port.addr, port.port))
+            .collect();
+        assert_eq!(
+            ports,
+            vec![
+                (
+                    "systemd-resolve".to_string(),
+                    612,
+                    "127.0.0.53%lo".to_string(),
+                    53
+                ),
+                ("unknown".to_string(), 0, "0.0.0.0".to_string(), 22),
+                ("nginx: master p".to_string(), 1201, "::".to_string(), 80),
+            ]
+        );
+
+        // A `Netid` column, a single-state filter that drops `State`, and
+        // `-e` extras after the process column all leave the fields in place.
+        assert_eq!(
+            SsRow::parse(
+                "tcp ESTAB 0 0 10.0.0.5:22 10.0.0.9:50514 users:((\"sshd: max\",pid=77,fd=4)) uid:0 ino:31337 sk:1"
+            ),
+            Some(SsRow {
+                state: Some("ESTAB"),
+                local: "10.0.0.5:22",
+                peer: Some("10.0.0.9:50514"),
+                process: Some("users:((\"sshd: max\",pid=77,fd=4))"),
+            })
+        );
+        let stateless = SsRow::parse("0 0 10.0.0.5:22 10.0.0.9:50514").expect("row should parse");
+        assert_eq!(stateless.state, None);
+        assert_eq!(stateless.peer, Some("10.0.0.9:50514"));
+        assert_eq!(SsRow::parse("ESTAB 0 10.0.0.5:22 10.0.0.9:50514"), None);
+        assert_eq!(SsRow::parse(""), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn proc_owners_resolve_unlabeled_sockets_by_inode() {
+        let root = std::env::temp_dir().join(format!("vizier-proc-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("net")).expect("net dir should be creatable");
+        std::fs::create_dir_all(root.join("4242").join("fd")).expect("fd dir should be creatable");
+        std::fs::write(
+            root.join("net").join("tcp"),
+            "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n\
+             0: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 777 1\n\
+             1: 0200A8C0:C350 0101A8C0:01BB 01 00000000:00000000 00:00000000 00000000  1000        0 888 1\n",
+        )
+        .expect("tcp table should be writable");
+        std::fs::write(root.join("4242").join("comm"), "server\n")
+            .expect("comm should be writable");
+        std::os::unix::fs::symlink("socket:[777]", root.join("4242").join("fd").join("3"))
+            .expect("fd link should be creatable");
+
+        let mut owners = ProcOwners::new(&root);
+        assert_eq!(owners.resolve(8080, 0), Some(("server".to_string(), 4242)));
+        assert_eq!(owners.resolve(50000, 443), None);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
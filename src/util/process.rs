@@ -0,0 +1,140 @@
+use std::io::{ErrorKind, Read};
+use std::process::{Command, Output, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, bail};
+
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn run_with_timeout(command: &mut Command, timeout: Duration) -> Result<Output> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let (done, finished) = mpsc::channel();
+    let stdout = drain(child.stdout.take(), done.clone());
+    let stderr = drain(child.stderr.take(), done);
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("timed out after {}ms", timeout.as_millis());
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    // Grandchildren can keep the pipes open after the child exits, so the
+    // readers only get what is left of the budget before being abandoned.
+    for _ in 0..2 {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if finished.recv_timeout(remaining).is_err() {
+            break;
+        }
+    }
+
+    Ok(Output {
+        status,
+        stdout: take(&stdout),
+        stderr: take(&stderr),
+    })
+}
+
+/// Decodes command output, replacing invalid UTF-8 with U+FFFD instead of
+/// failing. Tools like `lsof` and `ps` print names in the system locale, and one
+/// Latin-1 process name must not cost every other line of the output.
+pub fn lossy_text(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes)
+        .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned())
+}
+
+pub fn shell_command(script: &str) -> Command {
+    #[cfg(windows)]
+    {
+        let mut command = Command::new("cmd");
+        command.args(["/C", script]);
+        command
+    }
+
+    #[cfg(not(windows))]
+    {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]);
+        command
+    }
+}
+
+/// Reads `source` to the end on a detached thread, appending to a shared
+/// buffer so a caller that stops waiting still keeps what arrived, then
+/// signals `done`.
+fn drain(source: Option<impl Read + Send + 'static>, done: Sender<()>) -> Arc<Mutex<Vec<u8>>> {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&buffer);
+    thread::spawn(move || {
+        if let Some(mut source) = source {
+            let mut chunk = [0; 8192];
+            loop {
+                let read = match source.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(read) => read,
+                    Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                };
+                sink.lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .extend_from_slice(&chunk[..read]);
+            }
+        }
+        let _ = done.send(());
+    });
+    buffer
+}
+
+fn take(buffer: &Mutex<Vec<u8>>) -> Vec<u8> {
+    std::mem::take(&mut *buffer.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{lossy_text, run_with_timeout, shell_command};
+
+    #[test]
+    fn lossy_text_keeps_valid_lines_around_invalid_bytes() {
+        let raw = b"firefox 1234 max\ncaf\xe9 5678 max\nsshd 910 root\n".to_vec();
+        let text = lossy_text(raw);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "firefox 1234 max");
+        assert_eq!(lines[1], "caf\u{fffd} 5678 max");
+        assert_eq!(lines[2], "sshd 910 root");
+
+        assert_eq!(lossy_text(b"plain".to_vec()), "plain");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn background_grandchildren_do_not_hold_past_the_timeout() {
+        let started = Instant::now();
+        let output = run_with_timeout(
+            &mut shell_command("echo ready; sleep 5 &"),
+            Duration::from_millis(500),
+        )
+        .expect("the shell itself exits in time");
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"ready\n");
+    }
+}
@@ -0,0 +1,42 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    Complete = 0,
+    Degraded = 2,
+    MissingDependency = 3,
+    Drifted = 4,
+}
+
+static WORST: AtomicI32 = AtomicI32::new(Outcome::Complete as i32);
+static MISSING: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Records how a collector fared; the process keeps the worst outcome seen.
+pub fn report(outcome: Outcome) {
+    WORST.fetch_max(outcome as i32, Ordering::Relaxed);
+}
+
+pub fn exit_code() -> i32 {
+    WORST.load(Ordering::Relaxed)
+}
+
+/// Records a required tool that was not on `PATH`, so `--strict` can name it,
+/// and reports `MissingDependency`.
+pub fn report_missing(tool: &str) {
+    report(Outcome::MissingDependency);
+    let mut missing = MISSING
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if !missing.iter().any(|seen| seen == tool) {
+        missing.push(tool.to_string());
+    }
+}
+
+/// Required tools reported missing so far, in the order first seen.
+pub fn missing_tools() -> Vec<String> {
+    MISSING
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
@@ -0,0 +1,158 @@
+use serde_json::Value;
+
+use crate::observation::GpuInfo;
+
+/// Parses `system_profiler SPDisplaysDataType -json` into one `GpuInfo` per
+/// adapter. Dedicated VRAM (`spdisplays_vram`) wins over the shared
+/// allocation Intel integrated GPUs report (`spdisplays_vram_shared`, or
+/// `spdisplays_vram_dynamic` on older releases). Apple Silicon GPUs report no
+/// VRAM at all because they share unified memory, so they take
+/// `unified_memory_gb` (the machine's RAM) when it is known.
+pub fn parse_displays_data(raw: &str, unified_memory_gb: Option<f64>) -> Vec<GpuInfo> {
+    let Ok(root) = serde_json::from_str::<Value>(raw) else {
+        return Vec::new();
+    };
+    let Some(gpus) = root.get("SPDisplaysDataType").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+
+    gpus.iter()
+        .filter(|gpu| gpu.is_object())
+        .map(|gpu| {
+            let field = |key: &str| gpu.get(key).and_then(Value::as_str);
+            let name = field("sppci_model")
+                .or_else(|| field("_name"))
+                .unwrap_or("unknown")
+                .to_string();
+            let vram_gb = [
+                "spdisplays_vram",
+                "spdisplays_vram_shared",
+                "spdisplays_vram_dynamic",
+            ]
+            .into_iter()
+            .find_map(|key| field(key).and_then(parse_size_gb))
+            .or_else(|| is_apple_gpu(gpu).then_some(unified_memory_gb).flatten());
+
+            GpuInfo {
+                name,
+                vram_gb,
+                driver: "metal".to_string(),
+                driver_version: None,
+                utilization_pct: None,
+                pci_address: None,
+            }
+        })
+        .collect()
+}
+
+/// Apple Silicon GPUs are identified by vendor (`sppci_vendor_Apple`), or by
+/// an `Apple M...` model on releases that omit the vendor key.
+fn is_apple_gpu(gpu: &Value) -> bool {
+    let field = |key: &str| gpu.get(key).and_then(Value::as_str).unwrap_or_default();
+    field("spdisplays_vendor").eq_ignore_ascii_case("sppci_vendor_apple")
+        || field("sppci_model").starts_with("Apple M")
+}
+
+/// Reads sizes like `1536 MB`, `4 GB`, or `8GB` as gigabytes.
+fn parse_size_gb(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: f64 = amount.parse().ok()?;
+    match unit.trim().to_ascii_uppercase().as_str() {
+        "GB" => Some(amount),
+        "MB" => Some(amount / 1024.0),
+        "TB" => Some(amount * 1024.0),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_displays_data;
+
+    /// Intel MacBook Pro (2019) on macOS 12: integrated plus discrete AMD.
+    const INTEL_DUAL_GPU: &str = r#"{
+      "SPDisplaysDataType" : [
+        {
+          "_name" : "kHW_IntelUHDGraphics630Item",
+          "spdisplays_automatic_graphics_switching" : "spdisplays_supported",
+          "spdisplays_gmux-version" : "5.0.3",
+          "spdisplays_vram_shared" : "1536 MB",
+          "sppci_bus" : "spdisplays_builtin",
+          "sppci_device_type" : "spdisplays_gpu",
+          "sppci_model" : "Intel UHD Graphics 630",
+          "spdisplays_vendor" : "Intel"
+        },
+        {
+          "_name" : "kHW_AMDRadeonPro5500MItem",
+          "spdisplays_vram" : "4 GB",
+          "sppci_bus" : "spdisplays_pcie_device",
+          "sppci_device_type" : "spdisplays_gpu",
+          "sppci_model" : "AMD Radeon Pro 5500M",
+          "spdisplays_vendor" : "sppci_vendor_amd"
+        }
+      ]
+    }"#;
+
+    /// MacBook Pro (M2 Pro) on macOS 14: unified memory, no VRAM keys.
+    const APPLE_SILICON: &str = r#"{
+      "SPDisplaysDataType" : [
+        {
+          "_name" : "Apple M2 Pro",
+          "spdisplays_mtlgpufamilysupport" : "spdisplays_metal3",
+          "spdisplays_ndrvs" : [
+            { "_name" : "Color LCD", "spdisplays_main" : "spdisplays_yes" }
+          ],
+          "spdisplays_vendor" : "sppci_vendor_Apple",
+          "sppci_bus" : "spdisplays_builtin",
+          "sppci_cores" : "19",
+          "sppci_device_type" : "spdisplays_gpu",
+          "sppci_model" : "Apple M2 Pro"
+        }
+      ]
+    }"#;
+
+    /// Mac mini (2012) on macOS 10.13: dynamic VRAM and no `sppci_model`.
+    const LEGACY_DYNAMIC: &str = r#"{
+      "SPDisplaysDataType" : [
+        {
+          "_name" : "Intel HD Graphics 4000",
+          "spdisplays_vram_dynamic" : "1536 MB",
+          "sppci_bus" : "spdisplays_builtin"
+        }
+      ]
+    }"#;
+
+    #[test]
+    fn reads_dedicated_and_shared_vram() {
+        let gpus = parse_displays_data(INTEL_DUAL_GPU, Some(16.0));
+        assert_eq!(gpus.len(), 2);
+        assert_eq!(gpus[0].name, "Intel UHD Graphics 630");
+        assert_eq!(gpus[0].vram_gb, Some(1.5));
+        assert_eq!(gpus[1].name, "AMD Radeon Pro 5500M");
+        assert_eq!(gpus[1].vram_gb, Some(4.0));
+    }
+
+    #[test]
+    fn apple_silicon_reports_unified_memory() {
+        let gpus = parse_displays_data(APPLE_SILICON, Some(32.0));
+        assert_eq!(gpus.len(), 1);
+        assert_eq!(gpus[0].name, "Apple M2 Pro");
+        assert_eq!(gpus[0].vram_gb, Some(32.0));
+
+        assert_eq!(parse_displays_data(APPLE_SILICON, None)[0].vram_gb, None);
+    }
+
+    #[test]
+    fn falls_back_to_name_and_dynamic_vram() {
+        let gpus = parse_displays_data(LEGACY_DYNAMIC, Some(8.0));
+        assert_eq!(gpus[0].name, "Intel HD Graphics 4000");
+        assert_eq!(gpus[0].vram_gb, Some(1.5));
+
+        assert!(parse_displays_data("not json", None).is_empty());
+        assert!(parse_displays_data(r#"{"SPDisplaysDataType": {}}"#, None).is_empty());
+    }
+}
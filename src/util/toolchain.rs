@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The Node version in an `NVM_BIN` path such as
+/// `~/.nvm/versions/node/v20.11.0/bin`: the component after `node`.
+pub fn nvm_version(nvm_bin: &str) -> Option<String> {
+    let mut components = Path::new(nvm_bin)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy());
+    components.find(|component| component == "node")?;
+    components
+        .next()
+        .filter(|version| is_version(version))
+        .map(|version| version.into_owned())
+}
+
+/// `fnm current` prints `v20.11.0`, or `none`/`system` when fnm is not
+/// managing the active Node.
+pub fn parse_fnm_current(output: &str) -> Option<String> {
+    let version = output.trim();
+    is_version(version).then(|| version.to_string())
+}
+
+/// `rustup default` prints `stable-x86_64-unknown-linux-gnu (default)`.
+pub fn parse_rustup_default(output: &str) -> Option<String> {
+    output
+        .lines()
+        .next()?
+        .split_whitespace()
+        .next()
+        .filter(|toolchain| !toolchain.ends_with(':'))
+        .map(str::to_string)
+}
+
+/// `mise current` prints one `<tool> <version>...` line per tool; a tool
+/// pinned to several versions keeps them space-separated.
+pub fn parse_mise_current(output: &str) -> BTreeMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (tool, versions) = line.trim().split_once(char::is_whitespace)?;
+            let versions = versions.split_whitespace().collect::<Vec<_>>().join(" ");
+            (!versions.is_empty()).then(|| (tool.to_string(), versions))
+        })
+        .collect()
+}
+
+/// `asdf current` prints `<tool> <version> <source>` rows (0.16+ adds a
+/// `Name Version Source Installed` header); tools without a version show
+/// `______` and are skipped.
+pub fn parse_asdf_current(output: &str) -> BTreeMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            let tool = columns.next()?;
+            let version = columns.next()?;
+            (tool != "Name" && !version.starts_with('_'))
+                .then(|| (tool.to_string(), version.to_string()))
+        })
+        .collect()
+}
+
+fn is_version(value: &str) -> bool {
+    value
+        .strip_prefix('v')
+        .unwrap_or(value)
+        .starts_with(|c: char| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        nvm_version, parse_asdf_current, parse_fnm_current, parse_mise_current,
+        parse_rustup_default,
+    };
+
+    #[test]
+    fn reads_node_versions_from_nvm_and_fnm() {
+        assert_eq!(
+            nvm_version("/home/max/.nvm/versions/node/v20.11.0/bin").as_deref(),
+            Some("v20.11.0")
+        );
+        assert_eq!(nvm_version("/usr/local/bin"), None);
+        assert_eq!(parse_fnm_current("v18.19.1\n").as_deref(), Some("v18.19.1"));
+        assert_eq!(parse_fnm_current("none\n"), None);
+        assert_eq!(parse_fnm_current("system"), None);
+    }
+
+    #[test]
+    fn reads_rustup_default_toolchain() {
+        assert_eq!(
+            parse_rustup_default("stable-x86_64-unknown-linux-gnu (default)\n").as_deref(),
+            Some("stable-x86_64-unknown-linux-gnu")
+        );
+        assert_eq!(parse_rustup_default("error: no default toolchain"), None);
+        assert_eq!(parse_rustup_default(""), None);
+    }
+
+    #[test]
+    fn reads_mise_and_asdf_versions() {
+        let mise = parse_mise_current("node 20.11.0\npython 3.11.7 3.12.1\n\n");
+        assert_eq!(mise.get("node").map(String::as_str), Some("20.11.0"));
+        assert_eq!(
+            mise.get("python").map(String::as_str),
+            Some("3.11.7 3.12.1")
+        );
+
+        let legacy = parse_asdf_current(
+            "nodejs          20.11.0         /home/max/.tool-versions\n\
+             python          ______          No version is set. Run \"asdf <global|shell|local> python <version>\"\n",
+        );
+        assert_eq!(legacy.len(), 1);
+        assert_eq!(legacy.get("nodejs").map(String::as_str), Some("20.11.0"));
+
+        let current = parse_asdf_current(
+            "Name            Version         Source                      Installed\n\
+             golang          1.22.0          /home/max/.tool-versions    true\n",
+        );
+        assert_eq!(current.len(), 1);
+        assert_eq!(current.get("golang").map(String::as_str), Some("1.22.0"));
+    }
+}
@@ -0,0 +1,137 @@
+use chrono::{Datelike, Local, NaiveDateTime, TimeZone};
+
+use crate::observation::SessionInfo;
+
+/// Parses one line of `who -u` (plain `who` lines work too, without idle and
+/// pid). Accepts both login-time spellings: coreutils' ISO `2024-01-15 10:23`
+/// and the locale/BSD `Jan 15 10:23`, whose year is inferred from `now`.
+pub fn parse_who_line(line: &str, now: NaiveDateTime) -> Option<SessionInfo> {
+    let (columns, comment) = match line.split_once('(') {
+        Some((columns, rest)) => (columns, rest.split(')').next()),
+        None => (line, None),
+    };
+    let cols: Vec<&str> = columns.split_whitespace().collect();
+    if cols.len() < 4 {
+        return None;
+    }
+
+    let username = cols[0].to_string();
+    let tty = cols[1].to_string();
+    let (login, rest) = if cols[2].contains('-') {
+        let login =
+            NaiveDateTime::parse_from_str(&format!("{} {}", cols[2], cols[3]), "%Y-%m-%d %H:%M")
+                .ok()?;
+        (login, &cols[4..])
+    } else {
+        let (month, day, time) = (cols[2], cols[3], *cols.get(4)?);
+        let parse_with_year = |year: i32| {
+            NaiveDateTime::parse_from_str(&format!("{month} {day} {time} {year}"), "%b %e %H:%M %Y")
+                .ok()
+        };
+        let mut login = parse_with_year(now.year())?;
+        if login > now {
+            login = parse_with_year(now.year() - 1)?;
+        }
+        (login, &cols[5..])
+    };
+
+    let login_ts = Local
+        .from_local_datetime(&login)
+        .earliest()
+        .map(|dt| dt.timestamp() as f64)
+        .unwrap_or(0.0);
+
+    let idle = rest
+        .first()
+        .filter(|idle| is_idle_column(idle))
+        .map(|idle| idle.to_string());
+    let pid = rest
+        .get(usize::from(idle.is_some()))
+        .and_then(|pid| pid.parse().ok());
+    let from = comment
+        .map(str::trim)
+        .filter(|from| !from.is_empty())
+        .unwrap_or("local")
+        .to_string();
+
+    Some(SessionInfo {
+        username,
+        tty,
+        from,
+        login_ts,
+        idle,
+        pid,
+    })
+}
+
+/// `.` (active in the last minute), `old` (idle over a day), or `HH:MM`.
+fn is_idle_column(value: &str) -> bool {
+    value == "."
+        || value == "old"
+        || value.split_once(':').is_some_and(|(hours, minutes)| {
+            !hours.is_empty()
+                && minutes.len() == 2
+                && hours
+                    .chars()
+                    .chain(minutes.chars())
+                    .all(|c| c.is_ascii_digit())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    use super::parse_who_line;
+
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 3, 1)
+            .and_then(|date| date.and_hms_opt(12, 0, 0))
+            .expect("valid date")
+    }
+
+    #[test]
+    fn parses_coreutils_who_u_with_idle_pid_and_host() {
+        let session = parse_who_line(
+            "max      pts/0        2024-01-15 10:23 00:05       12345 (192.168.1.5)",
+            now(),
+        )
+        .expect("line should parse");
+        assert_eq!(session.username, "max");
+        assert_eq!(session.tty, "pts/0");
+        assert_eq!(session.idle.as_deref(), Some("00:05"));
+        assert_eq!(session.pid, Some(12345));
+        assert_eq!(session.from, "192.168.1.5");
+        assert!(session.login_ts > 0.0);
+    }
+
+    #[test]
+    fn parses_bsd_who_u_and_infers_the_year() {
+        let session = parse_who_line("max      console  Dec 30 09:00  old       152", now())
+            .expect("line should parse");
+        assert_eq!(session.tty, "console");
+        assert_eq!(session.idle.as_deref(), Some("old"));
+        assert_eq!(session.pid, Some(152));
+        assert_eq!(session.from, "local");
+
+        let december =
+            parse_who_line("max console Dec 30 09:00", now()).expect("line should parse");
+        let january = parse_who_line("max console Jan 2 09:00", now()).expect("line should parse");
+        assert!(december.login_ts < january.login_ts);
+    }
+
+    #[test]
+    fn plain_who_lines_have_no_idle_or_pid() {
+        let session =
+            parse_who_line("max      tty2         2024-02-28 08:00 (tty2)", now()).expect("parse");
+        assert_eq!(session.idle, None);
+        assert_eq!(session.pid, None);
+        assert_eq!(session.from, "tty2");
+
+        let active = parse_who_line("max pts/1 2024-02-28 08:00   .   777", now()).expect("parse");
+        assert_eq!(active.idle.as_deref(), Some("."));
+        assert_eq!(active.pid, Some(777));
+
+        assert!(parse_who_line("garbage", now()).is_none());
+    }
+}
@@ -1,12 +1,30 @@
-use vizier::diff::create_diff_envelope;
+use vizier::diff::{DiffRecord, create_diff_envelope, create_keyed_diff_envelope};
+use vizier::observation::{Bounds, WindowInfo};
+use vizier::observer::catalog::builtin_catalog;
 use vizier::observer::common::{BaselineObserver, BaselineWaker};
-use vizier::observer::{Observer, ObserverConfig, WakeConfig, Waker};
+use vizier::observer::{
+    DEFAULT_MAX_FS_EVENTS, DEFAULT_PUBLIC_IP_TIMEOUT, IpFilter, Observer, ObserverConfig,
+    ProcessSort, WakeConfig, Waker,
+};
+use vizier::replay::Replay;
+use vizier::util::deadline::Deadline;
 
 #[test]
 fn snapshot_shape_has_required_fields() {
     let mut observer = BaselineObserver::new(ObserverConfig {
         watch_path: Some(std::env::temp_dir()),
-        all_connections: false,
+        include_loopback: false,
+        conn_states: Vec::new(),
+        no_network: false,
+        no_windows: false,
+        no_connections: false,
+        clipboard: false,
+        window_resources: false,
+        include_desktop: false,
+        max_fs_events: DEFAULT_MAX_FS_EVENTS,
+        redact_titles: None,
+        focus_only: false,
+        deadline: Deadline::default(),
     });
 
     let snapshot = observer.snapshot().expect("snapshot should succeed");
@@ -18,7 +36,30 @@ fn snapshot_shape_has_required_fields() {
 
 #[test]
 fn wake_respects_no_public_ip_flag() {
-    let waker = BaselineWaker::new(WakeConfig { no_public_ip: true });
+    let waker = BaselineWaker::new(WakeConfig {
+        no_public_ip: true,
+        no_network: false,
+        public_ip_timeout: DEFAULT_PUBLIC_IP_TIMEOUT,
+        public_ip_retries: 0,
+        proxy: None,
+        public_ip_provider: None,
+        top_processes: 0,
+        sort_by: ProcessSort::Cpu,
+        collectors: Vec::new(),
+        command_timeout: std::time::Duration::from_secs(5),
+        exclude_paths: Vec::new(),
+        disk_health: false,
+        all_mounts: false,
+        only: Vec::new(),
+        env: false,
+        env_vars: Vec::new(),
+        sensors: false,
+        include_hidden: false,
+        git: false,
+        app_catalog: builtin_catalog(),
+        ip_filter: IpFilter::All,
+        deadline: Deadline::default(),
+    });
     let wake = waker.wake().expect("wake should succeed");
 
     assert_eq!(wake.schema_version, 1);
@@ -30,7 +71,18 @@ fn wake_respects_no_public_ip_flag() {
 fn diff_envelope_contains_patch_operations() {
     let mut observer = BaselineObserver::new(ObserverConfig {
         watch_path: Some(std::env::temp_dir()),
-        all_connections: false,
+        include_loopback: false,
+        conn_states: Vec::new(),
+        no_network: false,
+        no_windows: false,
+        no_connections: false,
+        clipboard: false,
+        window_resources: false,
+        include_desktop: false,
+        max_fs_events: DEFAULT_MAX_FS_EVENTS,
+        redact_titles: None,
+        focus_only: false,
+        deadline: Deadline::default(),
     });
 
     let previous = observer.snapshot().expect("first snapshot should succeed");
@@ -43,3 +95,279 @@ fn diff_envelope_contains_patch_operations() {
     assert_eq!(envelope.monotonic_ms, current.monotonic_ms);
     assert!(!envelope.patch.0.is_empty());
 }
+
+#[test]
+fn diff_envelope_ignores_clock_only_changes() {
+    let mut observer = BaselineObserver::new(ObserverConfig {
+        watch_path: Some(std::env::temp_dir()),
+        include_loopback: false,
+        conn_states: Vec::new(),
+        no_network: false,
+        no_windows: false,
+        no_connections: false,
+        clipboard: false,
+        window_resources: false,
+        include_desktop: false,
+        max_fs_events: DEFAULT_MAX_FS_EVENTS,
+        redact_titles: None,
+        focus_only: false,
+        deadline: Deadline::default(),
+    });
+
+    let previous = observer.snapshot().expect("snapshot should succeed");
+    let mut current = previous.clone();
+    current.ts += 1.0;
+    current.monotonic_ms += 1000;
+    current.idle_ms += 1000;
+
+    let quiet = create_diff_envelope(&previous, &current).expect("diff should succeed");
+    assert!(!quiet.patch.0.is_empty());
+    assert!(!quiet.has_changes());
+
+    current.cursor.x += 1;
+    let changed = create_diff_envelope(&previous, &current).expect("diff should succeed");
+    assert!(changed.has_changes());
+}
+
+#[test]
+fn monotonic_ms_strictly_increases_and_orders_diffs() {
+    let mut observer = BaselineObserver::new(ObserverConfig {
+        watch_path: Some(std::env::temp_dir()),
+        include_loopback: false,
+        conn_states: Vec::new(),
+        no_network: false,
+        no_windows: false,
+        no_connections: false,
+        clipboard: false,
+        window_resources: false,
+        include_desktop: false,
+        max_fs_events: DEFAULT_MAX_FS_EVENTS,
+        redact_titles: None,
+        focus_only: false,
+        deadline: Deadline::default(),
+    });
+
+    let first = observer.snapshot().expect("first snapshot should succeed");
+    let second = observer.snapshot().expect("second snapshot should succeed");
+    assert!(second.monotonic_ms > first.monotonic_ms);
+
+    let envelope = create_diff_envelope(&first, &second).expect("diff should succeed");
+    assert_eq!(envelope.base_monotonic_ms, first.monotonic_ms);
+    assert_eq!(envelope.monotonic_ms, second.monotonic_ms);
+
+    assert!(create_diff_envelope(&second, &first).is_err());
+}
+
+#[test]
+fn replay_reconstructs_each_observation_from_diff_stream() {
+    let mut observer = BaselineObserver::new(ObserverConfig {
+        watch_path: Some(std::env::temp_dir()),
+        include_loopback: false,
+        conn_states: Vec::new(),
+        no_network: false,
+        no_windows: false,
+        no_connections: false,
+        clipboard: false,
+        window_resources: false,
+        include_desktop: false,
+        max_fs_events: DEFAULT_MAX_FS_EVENTS,
+        redact_titles: None,
+        focus_only: false,
+        deadline: Deadline::default(),
+    });
+
+    let first = observer.snapshot().expect("snapshot should succeed");
+    let mut second = first.clone();
+    second.monotonic_ms += 1000;
+    second.idle_ms += 250;
+    second.windows.clear();
+    let mut third = second.clone();
+    third.monotonic_ms += 1000;
+    third.cursor.x += 10;
+
+    let first_patch = create_diff_envelope(&first, &second).expect("diff should succeed");
+    let second_patch = create_diff_envelope(&second, &third).expect("diff should succeed");
+    let stream = [
+        DiffRecord::Patch(&first_patch),
+        DiffRecord::snapshot(&first),
+        DiffRecord::Patch(&first_patch),
+        DiffRecord::Patch(&second_patch),
+    ]
+    .iter()
+    .map(|record| serde_json::to_string(record).expect("record should serialize"))
+    .collect::<Vec<String>>()
+    .join("\n");
+
+    let steps: Vec<_> = Replay::new(stream.as_bytes())
+        .collect::<Result<_, _>>()
+        .expect("replay should succeed");
+
+    let indices: Vec<usize> = steps.iter().map(|step| step.index).collect();
+    assert_eq!(indices, [1, 2, 3]);
+    for (step, expected) in steps.iter().zip([&first, &second, &third]) {
+        assert_eq!(
+            serde_json::to_value(&step.observation).expect("observation should serialize"),
+            serde_json::to_value(expected).expect("observation should serialize")
+        );
+    }
+}
+
+#[test]
+fn keyed_diff_moves_reordered_windows_instead_of_replacing_them() {
+    let mut observer = BaselineObserver::new(ObserverConfig {
+        watch_path: Some(std::env::temp_dir()),
+        include_loopback: false,
+        conn_states: Vec::new(),
+        no_network: false,
+        no_windows: false,
+        no_connections: false,
+        clipboard: false,
+        window_resources: false,
+        include_desktop: false,
+        max_fs_events: DEFAULT_MAX_FS_EVENTS,
+        redact_titles: None,
+        focus_only: false,
+        deadline: Deadline::default(),
+    });
+    let window = |id: &str, title: &str| WindowInfo {
+        id: id.to_string(),
+        title: title.to_string(),
+        app: "editor".to_string(),
+        pid: 42,
+        bounds: Bounds {
+            x: 0,
+            y: 0,
+            w: 800,
+            h: 600,
+        },
+        workspace: 1,
+        is_minimized: false,
+        is_fullscreen: false,
+        is_special: false,
+        rss_mb: None,
+        cpu_pct: None,
+        z_index: None,
+        layer: None,
+        focus_duration_ms: None,
+    };
+
+    let mut previous = observer.snapshot().expect("snapshot should succeed");
+    previous.windows = vec![window("a", "one"), window("b", "two"), window("c", "three")];
+    let mut current = previous.clone();
+    current.monotonic_ms += 1000;
+    current.windows = vec![
+        window("c", "three"),
+        window("a", "one*"),
+        window("d", "four"),
+    ];
+
+    let keyed = create_keyed_diff_envelope(&previous, &current).expect("diff should succeed");
+    let ops: Vec<(String, String)> = keyed
+        .patch
+        .0
+        .iter()
+        .map(|op| {
+            let value = serde_json::to_value(op).expect("op should serialize");
+            (
+                value["op"].as_str().unwrap_or_default().to_string(),
+                op.path().to_string(),
+            )
+        })
+        .filter(|(_, path)| path.starts_with("/windows"))
+        .collect();
+    let expected = [
+        ("remove", "/windows/1"),
+        ("move", "/windows/0"),
+        ("replace", "/windows/1/title"),
+        ("add", "/windows/2"),
+    ];
+    assert_eq!(
+        ops,
+        expected.map(|(op, path)| (op.to_string(), path.to_string()))
+    );
+
+    let mut state = serde_json::to_value(&previous).expect("observation should serialize");
+    json_patch::patch(&mut state, &keyed.patch).expect("keyed patch should apply");
+    assert_eq!(
+        state,
+        serde_json::to_value(&current).expect("observation should serialize")
+    );
+
+    let positional = create_diff_envelope(&previous, &current).expect("diff should succeed");
+    assert!(positional.patch.0.len() > keyed.patch.0.len());
+}
+
+#[test]
+fn config_builders_start_from_cli_defaults() {
+    let wake = WakeConfig::builder()
+        .no_network(true)
+        .top_processes(5)
+        .sort_by(ProcessSort::Mem)
+        .env_var("EDITOR")
+        .build();
+    assert!(wake.no_network);
+    assert_eq!(wake.top_processes, 5);
+    assert_eq!(wake.sort_by, ProcessSort::Mem);
+    assert_eq!(wake.env_vars, ["EDITOR"]);
+    assert_eq!(wake.public_ip_timeout, DEFAULT_PUBLIC_IP_TIMEOUT);
+    assert!(wake.only.is_empty());
+
+    let observer = ObserverConfig::builder()
+        .watch_path(std::env::temp_dir())
+        .conn_states(["ESTABLISHED", "LISTEN"])
+        .no_windows(true)
+        .build();
+    assert_eq!(observer.watch_path, Some(std::env::temp_dir()));
+    assert_eq!(observer.conn_states, ["ESTABLISHED", "LISTEN"]);
+    assert!(observer.no_windows);
+    assert!(!observer.clipboard);
+    assert_eq!(observer.max_fs_events, DEFAULT_MAX_FS_EVENTS);
+
+    let mut observer = BaselineObserver::new(observer);
+    assert!(observer.snapshot().is_ok());
+}
+
+#[test]
+fn fs_event_bursts_are_capped_and_counted() {
+    let dir = std::env::temp_dir().join(format!("vizier-fs-cap-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("create watch dir");
+    let mut observer = BaselineObserver::new(
+        ObserverConfig::builder()
+            .watch_path(&dir)
+            .no_windows(true)
+            .no_connections(true)
+            .max_fs_events(3)
+            .build(),
+    );
+    observer.snapshot().expect("first snapshot should succeed");
+
+    for index in 0..20 {
+        std::fs::write(dir.join(format!("file-{index}")), b"x").expect("write file");
+    }
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    let burst = observer.snapshot().expect("burst snapshot should succeed");
+    let quiet = observer.snapshot().expect("quiet snapshot should succeed");
+    std::fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(burst.fs_events.len(), 3);
+    assert!(burst.fs_events_dropped.is_some_and(|dropped| dropped >= 17));
+    assert!(quiet.fs_events.is_empty());
+    assert_eq!(quiet.fs_events_dropped, None);
+}
+
+#[test]
+fn unwatchable_path_is_reported_as_a_diagnostic() {
+    let missing = std::env::temp_dir().join(format!("vizier-missing-{}", std::process::id()));
+    let mut observer = BaselineObserver::new(
+        ObserverConfig::builder()
+            .watch_path(&missing)
+            .no_connections(true)
+            .build(),
+    );
+
+    let snapshot = observer.snapshot().expect("snapshot should succeed");
+    assert!(snapshot.fs_events.is_empty());
+    assert_eq!(snapshot.diagnostics.len(), 1);
+    assert_eq!(snapshot.diagnostics[0].source, "fs_events");
+    assert!(snapshot.diagnostics[0].message.starts_with("cannot watch "));
+}